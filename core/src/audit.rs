@@ -0,0 +1,62 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Administrative change of a polar.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    /// seconds since epoch
+    pub at: u64,
+    pub action: String,
+    /// id of the polar
+    pub polar: String,
+    pub detail: String,
+}
+
+/// Audit log of the administrative changes, one JSON entry per line appended to `file`.
+/// Nothing is kept without a file.
+#[derive(Debug, Default)]
+pub struct Audit {
+    file: Option<PathBuf>,
+    lock: Mutex<()>,
+}
+
+impl Audit {
+    pub fn new<P: Into<PathBuf>>(file: P) -> Self {
+        Audit { file: Some(file.into()), lock: Mutex::new(()) }
+    }
+
+    pub fn record(&self, action: &str, polar: &str, detail: String) -> Result<()> {
+        let Some(file) = &self.file else { return Ok(()) };
+        let at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let entry = AuditEntry { at, action: action.to_string(), polar: polar.to_string(), detail };
+        let line = serde_json::to_string(&entry)?;
+
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut f = OpenOptions::new().create(true).append(true).open(file)?;
+        writeln!(f, "{}", line)?;
+        Ok(())
+    }
+
+    /// Entries of the log, oldest first, only those of `polar` when given.
+    pub fn entries(&self, polar: Option<&str>) -> Result<Vec<AuditEntry>> {
+        let Some(file) = &self.file else { return Ok(Vec::new()) };
+        if !file.exists() {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        for line in BufReader::new(File::open(file)?).lines() {
+            let entry: AuditEntry = serde_json::from_str(&line?)?;
+            if polar.is_none_or(|polar| entry.polar == polar) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,117 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::polar::{PolarError, SailDefinition, WinchProfile};
+
+/// Entry of a catalog, identified by its key.
+pub trait Entry: Serialize + DeserializeOwned + Clone {
+    /// Kind of the entries in error messages, e.g. `sail`.
+    const KIND: &'static str;
+
+    fn key(&self) -> &str;
+
+    fn set_key(&mut self, key: String);
+}
+
+impl Entry for SailDefinition {
+    const KIND: &'static str = "sail";
+
+    fn key(&self) -> &str {
+        &self.id
+    }
+
+    fn set_key(&mut self, key: String) {
+        self.id = key;
+    }
+}
+
+impl Entry for WinchProfile {
+    const KIND: &'static str = "winch";
+
+    fn key(&self) -> &str {
+        &self.name
+    }
+
+    fn set_key(&mut self, key: String) {
+        self.name = key;
+    }
+}
+
+/// Entries shared by the polars, e.g. the sail definitions, kept in a YAML file sorted by key.
+/// A missing file is an empty catalog.
+pub struct Catalog<T> {
+    path: PathBuf,
+    entries: PhantomData<T>,
+}
+
+impl<T: Entry> Catalog<T> {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Catalog { path: path.into(), entries: PhantomData }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn list(&self) -> Result<Vec<T>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let reader = BufReader::new(File::open(&self.path)?);
+        let entries: Option<Vec<T>> = serde_yaml::from_reader(reader)?;
+        Ok(entries.unwrap_or_default())
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<T>> {
+        Ok(self.list()?.into_iter().find(|e| e.key() == key))
+    }
+
+    pub fn create(&self, entry: &T) -> Result<()> {
+        let mut entries = self.list()?;
+        if entries.iter().any(|e| e.key() == entry.key()) {
+            return Err(PolarError::AlreadyExists(format!("{} {}", T::KIND, entry.key())).into());
+        }
+        entries.push(entry.clone());
+        entries.sort_by(|a, b| a.key().cmp(b.key()));
+        self.save(&entries)
+    }
+
+    /// Replaces the entry of `key` by `entry`, which keeps that key.
+    pub fn update(&self, key: String, entry: &T) -> Result<()> {
+        let mut entries = self.list()?;
+        match entries.iter_mut().find(|e| e.key() == key) {
+            Some(existing) => {
+                *existing = entry.clone();
+                existing.set_key(key);
+            }
+            None => return Err(PolarError::NotFound(format!("{} {}", T::KIND, key)).into()),
+        }
+        self.save(&entries)
+    }
+
+    /// Deletes the entry of `key`, callers check first that no polar references it.
+    pub fn delete(&self, key: &str) -> Result<()> {
+        let mut entries = self.list()?;
+        if !entries.iter().any(|e| e.key() == key) {
+            return Err(PolarError::NotFound(format!("{} {}", T::KIND, key)).into());
+        }
+        entries.retain(|e| e.key() != key);
+        self.save(&entries)
+    }
+
+    fn save(&self, entries: &[T]) -> Result<()> {
+        let f = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&self.path)?;
+        serde_yaml::to_writer(f, entries)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+#[cfg(feature = "rocket")]
+use rocket::form::FromFormField;
+use serde::Serialize;
+
+use crate::compute;
+use crate::matrix;
+use crate::polar::Polar;
+use crate::stats;
+use crate::units::{SpeedFormat, Units};
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Comparison {
+    pub units: Units,
+    pub tws: f64,
+    pub ids: Vec<String>,
+    pub rows: Vec<ComparisonRow>,
+    pub crossovers: Vec<Crossover>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ComparisonRow {
+    pub twa: f64,
+    /// boat speeds, in the same order as `ids`
+    pub speeds: Vec<f64>,
+    pub fastest: String,
+    /// lead of the fastest boat over the second one
+    pub delta: f64,
+}
+
+impl Comparison {
+    pub fn format(&mut self, format: SpeedFormat) {
+        self.units = format.units;
+        for row in self.rows.iter_mut() {
+            row.speeds.iter_mut().for_each(|s| *s = format.apply(*s));
+            row.delta = format.apply(row.delta);
+        }
+    }
+}
+
+/// Angle where the fastest boat changes.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Crossover {
+    pub twa: f64,
+    pub from: String,
+    pub to: String,
+}
+
+/// Compares `polars` at `tws` on every twa of their grids, or every `step` degrees when given.
+/// `None` when `step` is below `compute::MIN_TWA_STEP`.
+pub fn compare(polars: &[Polar], tws: f64, step: Option<f64>) -> Option<Comparison> {
+    let ids: Vec<String> = polars.iter().map(|p| p.id.clone().unwrap_or_default()).collect();
+
+    let angles: Vec<f64> = match step {
+        Some(step) => {
+            let min = polars.iter().filter_map(|p| p.twa.first()).min().copied().unwrap_or_default() as f64;
+            let max = polars.iter().filter_map(|p| p.twa.last()).max().copied().unwrap_or_default() as f64;
+            compute::sweep(min, max, step)?
+        }
+        None => polars.iter()
+            .fold(Vec::new(), |acc, p| matrix::union(&acc, &p.twa))
+            .into_iter()
+            .map(|twa| twa as f64)
+            .collect(),
+    };
+
+    let mut rows: Vec<ComparisonRow> = Vec::new();
+    let mut crossovers = Vec::new();
+    let mut previous: Option<(f64, Vec<f64>, usize)> = None;
+
+    for twa in angles {
+        let speeds: Vec<f64> = polars.iter().map(|p| p.speed(twa, tws)).collect();
+        let (fastest, delta) = leader(&speeds);
+
+        if let Some((prev_twa, prev_speeds, prev_fastest)) = &previous {
+            if *prev_fastest != fastest {
+                // linear estimation of the angle where both boats have the same speed
+                let before = prev_speeds[*prev_fastest] - prev_speeds[fastest];
+                let after = speeds[*prev_fastest] - speeds[fastest];
+                let ratio = if before - after != 0.0 { before / (before - after) } else { 0.5 };
+                crossovers.push(Crossover {
+                    twa: prev_twa + (twa - prev_twa) * ratio,
+                    from: ids[*prev_fastest].clone(),
+                    to: ids[fastest].clone(),
+                });
+            }
+        }
+
+        rows.push(ComparisonRow { twa, speeds: speeds.clone(), fastest: ids[fastest].clone(), delta });
+        previous = Some((twa, speeds, fastest));
+    }
+
+    Some(Comparison { units: Units::Kts, tws, ids, rows, crossovers })
+}
+
+/// Index of the fastest boat and its lead over the second one.
+fn leader(speeds: &[f64]) -> (usize, f64) {
+    let mut fastest = 0;
+    for (i, speed) in speeds.iter().enumerate() {
+        if *speed > speeds[fastest] {
+            fastest = i;
+        }
+    }
+    let second = speeds.iter().enumerate()
+        .filter(|(i, _)| *i != fastest)
+        .map(|(_, s)| *s)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let delta = if second.is_finite() { speeds[fastest] - second } else { 0.0 };
+    (fastest, delta)
+}
+
+/// Measure polars are ranked by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "rocket", derive(FromFormField))]
+pub enum RankBy {
+    #[default]
+    Speed,
+    /// best speed made good at the tws, upwind when the twa is below 90 degrees, downwind above
+    Vmg,
+}
+
+/// Effective speed of a polar under given conditions.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Ranked {
+    pub id: Option<String>,
+    pub label: String,
+    pub sail: u8,
+    pub speed: f64,
+    /// best upwind or downwind vmg at the tws, on the side of the twa
+    pub vmg: f64,
+    /// twa of `vmg`
+    pub vmg_twa: f64,
+}
+
+impl Ranked {
+    /// `speed` of `polar` with `sail` at `(twa, tws)`, `None` when `polar` has no vmg at `tws`,
+    /// e.g. no sail left once fitted with the boat options.
+    pub fn new(polar: &Polar, twa: f64, tws: f64, (sail, speed): (u8, f64)) -> Option<Self> {
+        let (upwind, downwind) = stats::vmg(polar, tws)?;
+        let vmg = if twa.abs() < 90.0 { upwind } else { downwind };
+        Some(Ranked { id: polar.id.clone(), label: polar.label.clone(), sail, speed, vmg: vmg.vmg, vmg_twa: vmg.twa })
+    }
+
+    pub fn format(&mut self, format: SpeedFormat) {
+        self.speed = format.apply(self.speed);
+        self.vmg = format.apply(self.vmg);
+    }
+}
+
+/// Sorts `ranked` fastest first by `by`, then by id.
+pub fn rank(ranked: &mut [Ranked], by: RankBy) {
+    let measure = |r: &Ranked| match by {
+        RankBy::Speed => r.speed,
+        RankBy::Vmg => r.vmg,
+    };
+    ranked.sort_by(|a, b| measure(b).total_cmp(&measure(a)).then_with(|| a.id.cmp(&b.id)));
+}
@@ -0,0 +1,426 @@
+#[cfg(feature = "rocket")]
+use rocket::form::{FromForm, FromFormField};
+use serde::{Deserialize, Serialize};
+use serde::de::Deserializer;
+
+use crate::matrix::{self, Extrapolation, Interpolation};
+use crate::polar::{BoatOption, Foil, Polar};
+use crate::table::Table;
+use crate::units::{SpeedFormat, Units};
+
+/// Evaluation options of the speed, grid and stats endpoints.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "rocket", derive(FromForm))]
+pub struct Options {
+    /// service default when not given, set by the caller before the evaluation, `Clamp` otherwise
+    pub extrapolation: Option<Extrapolation>,
+    /// bilinear when not given, the interpolation of the game engine
+    pub method: Option<Interpolation>,
+    /// convention of the twa in the response, service default when not given. Starboard twa
+    /// from 0 to 180, e.g. of the stats and surfaces, read the same in both.
+    pub angles: Option<AngleConvention>,
+    pub in_ice: bool,
+    #[cfg_attr(feature = "rocket", field(default = FoilMode::Auto))]
+    pub foil: FoilMode,
+    #[cfg_attr(feature = "rocket", field(default = Units::Kts))]
+    pub units: Units,
+    pub precision: Option<u8>,
+    /// comma separated boat options, the full polar when not given
+    pub options: Option<String>,
+    /// comma separated ids of sails left out of the evaluation, e.g. missing sail packs
+    pub exclude_sails: Option<String>,
+    /// what-if overrides of the polar ratios, for this request only
+    pub global_speed_ratio: Option<f64>,
+    pub ice_speed_ratio: Option<f64>,
+    pub foil_speed_ratio: Option<f64>,
+}
+
+impl Options {
+    pub fn format(&self) -> SpeedFormat {
+        SpeedFormat { units: self.units, precision: self.precision }
+    }
+
+    /// Whether the effective polar of these options is the one of the default options, i.e. the
+    /// speeds are those of the binary exports kept by the service, whatever their format.
+    pub fn is_default_polar(&self) -> bool {
+        self.method.unwrap_or_default() == Interpolation::Bilinear
+            && !self.in_ice
+            && self.foil == FoilMode::Auto
+            && self.options.is_none()
+            && self.exclude_sails.is_none()
+            && self.global_speed_ratio.is_none()
+            && self.ice_speed_ratio.is_none()
+            && self.foil_speed_ratio.is_none()
+    }
+
+    /// Restricts `polar` to the boat options without the excluded sails and applies the ratio
+    /// overrides, `None` when one of the options is unknown or a sail id is not a number.
+    pub fn fit(&self, polar: &mut Polar) -> Option<()> {
+        fit(polar, self.options.as_deref())?;
+        if let Some(excluded) = &self.exclude_sails {
+            let excluded = excluded.split(',')
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse().ok())
+                .collect::<Option<Vec<u8>>>()?;
+            polar.sail.retain(|s| !excluded.contains(&s.id));
+        }
+        if let Some(ratio) = self.global_speed_ratio {
+            polar.global_speed_ratio = ratio;
+        }
+        if let Some(ratio) = self.ice_speed_ratio {
+            polar.ice_speed_ratio = ratio;
+        }
+        if let Some(ratio) = self.foil_speed_ratio {
+            polar.foil.speed_ratio = ratio;
+        }
+        Some(())
+    }
+}
+
+/// Restricts `polar` to the comma separated boat `options` when given,
+/// `None` when one of them is unknown.
+pub fn fit(polar: &mut Polar, options: Option<&str>) -> Option<()> {
+    if let Some(options) = options {
+        let options = options.split(',')
+            .map(|o| o.trim())
+            .filter(|o| !o.is_empty())
+            .map(BoatOption::parse)
+            .collect::<Option<Vec<BoatOption>>>()?;
+        polar.with_options(&options);
+    }
+    Some(())
+}
+
+/// Range of the twa given to and returned by the API. Incoming twa are accepted in both
+/// conventions and normalized to the signed one used internally.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "rocket", derive(FromFormField))]
+#[serde(rename_all = "camelCase")]
+pub enum AngleConvention {
+    /// -180 to 180 degrees, negative on port tack
+    #[default]
+    Signed,
+    /// 0 to 360 degrees, above 180 on port tack
+    Circular,
+}
+
+impl AngleConvention {
+    /// `twa` in degrees of any range as a signed twa, from -180 excluded to 180.
+    pub fn normalize(twa: f64) -> f64 {
+        let twa = twa.rem_euclid(360.0);
+        if twa > 180.0 { twa - 360.0 } else { twa }
+    }
+
+    /// Signed `twa` in this convention.
+    pub fn present(&self, twa: f64) -> f64 {
+        match self {
+            AngleConvention::Signed => twa,
+            AngleConvention::Circular => twa.rem_euclid(360.0),
+        }
+    }
+}
+
+/// Deserializes a twa given in either convention as a signed twa.
+pub fn signed_twa<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    f64::deserialize(deserializer).map(AngleConvention::normalize)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "rocket", derive(FromFormField))]
+pub enum FoilMode {
+    #[cfg_attr(feature = "rocket", field(value = "true"))]
+    On,
+    #[cfg_attr(feature = "rocket", field(value = "false"))]
+    Off,
+    /// foil applied when the polar has one, i.e. its speed ratio is above 1
+    #[default]
+    Auto,
+}
+
+impl FoilMode {
+    fn enabled(&self, foil: &Foil) -> bool {
+        match self {
+            FoilMode::On => true,
+            FoilMode::Off => false,
+            FoilMode::Auto => foil.speed_ratio > 1.0,
+        }
+    }
+}
+
+/// Speed ratio of the foil at `(twa, tws)` : the full gain inside the twa and tws ranges,
+/// ramping linearly down to none across the merge bands around them.
+pub fn foil_ratio(foil: &Foil, twa: f64, tws: f64) -> f64 {
+    let twa = twa.abs();
+    let coefficient = merge_coefficient(twa, foil.twa_min, foil.twa_max, foil.twa_merge)
+        * merge_coefficient(tws, foil.tws_min, foil.tws_max, foil.tws_merge);
+    1.0 + (foil.speed_ratio - 1.0) * coefficient
+}
+
+/// 1 inside `[min, max]`, 0 further than `merge` from it, linear in between.
+fn merge_coefficient(x: f64, min: f64, max: f64, merge: f64) -> f64 {
+    if x >= min && x <= max {
+        1.0
+    } else if merge <= 0.0 || x <= min - merge || x >= max + merge {
+        0.0
+    } else if x < min {
+        (x - (min - merge)) / merge
+    } else {
+        (max + merge - x) / merge
+    }
+}
+
+/// Fastest sail and boat speed at each `(twa, tws)` of `points`, extrapolated as `options` tells,
+/// with the speed ratios and foil of `options` applied. `table` is the evaluation table of `polar`.
+/// `None` when the extrapolation is `Error` and one of the points is off the grid.
+pub fn effective_speeds(polar: &Polar, table: &Table, options: &Options, points: &[(f64, f64)]) -> Option<Vec<(u8, f64)>> {
+    let extrapolation = options.extrapolation.unwrap_or_default();
+    if extrapolation == Extrapolation::Error && !points.iter().all(|(twa, tws)| polar.contains(*twa, *tws)) {
+        return None;
+    }
+    let ratio = polar.speed_ratio(options.in_ice);
+    let best = match options.method.unwrap_or_default() {
+        Interpolation::Bilinear => table.best_sails(points, extrapolation),
+        method => points.iter()
+            .map(|(twa, tws)| polar.best_sail_interpolated(*twa, *tws, extrapolation, method).unwrap_or_default())
+            .collect(),
+    };
+    Some(best.into_iter()
+        .zip(points)
+        .map(|((sail, speed), (twa, tws))| (sail, speed * ratio * foil(polar, options.foil, *twa, *tws)))
+        .collect())
+}
+
+/// Foil ratio of `polar` at `(twa, tws)` according to `mode`.
+pub fn foil(polar: &Polar, mode: FoilMode, twa: f64, tws: f64) -> f64 {
+    if mode.enabled(&polar.foil) {
+        foil_ratio(&polar.foil, twa, tws)
+    } else {
+        1.0
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoSail {
+    pub tws: f64,
+    pub tolerance: f64,
+    pub steps: Vec<AutoSailStep>,
+    pub changes: Vec<SailChange>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoSailStep {
+    pub twa: f64,
+    /// sail set by the auto sail
+    pub sail: u8,
+    pub speed: f64,
+    pub best_sail: u8,
+    pub best_speed: f64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SailChange {
+    pub twa: f64,
+    pub from: u8,
+    pub to: u8,
+}
+
+/// Table speed of `sail` at `(twa, tws)`, 0 where it can't be used.
+pub fn sail_speed(polar: &Polar, sail: u8, twa: f64, tws: f64) -> f64 {
+    polar.sail.iter()
+        .find(|s| s.id == sail && s.usable(twa, tws))
+        .map_or(0.0, |s| matrix::bilinear(&polar.twa, &polar.tws, s.matrix(twa), twa.abs(), tws))
+}
+
+/// Follows the auto sail along `angles` at `tws` : the current sail is kept as long as it can be
+/// used and its speed stays above `tolerance` times the speed of the best sail. Speeds are in
+/// the ice when `in_ice`.
+pub fn auto_sail(polar: &Polar, tws: f64, angles: &[f64], tolerance: f64, in_ice: bool) -> AutoSail {
+    let mut steps = Vec::with_capacity(angles.len());
+    let mut changes = Vec::new();
+    let mut current: Option<u8> = None;
+
+    for twa in angles {
+        let Some((best_sail, best_speed)) = polar.best_sail(*twa, tws) else { continue };
+        let sail = match current {
+            Some(sail) if sail_speed(polar, sail, *twa, tws) >= best_speed * tolerance => sail,
+            Some(sail) => {
+                changes.push(SailChange { twa: *twa, from: sail, to: best_sail });
+                best_sail
+            }
+            None => best_sail,
+        };
+        current = Some(sail);
+
+        let ratio = polar.speed_ratio(in_ice);
+        steps.push(AutoSailStep {
+            twa: *twa,
+            sail,
+            speed: sail_speed(polar, sail, *twa, tws) * ratio,
+            best_sail,
+            best_speed: best_speed * ratio,
+        });
+    }
+
+    AutoSail { tws, tolerance, steps, changes }
+}
+
+/// Table speed at `(twa, tws)` with `sail` set, and whether it is a bad sail : within
+/// `badSailTolerance` of the best sail the best speed is kept, otherwise the set sail speed is used.
+/// `None` when the polar has no such sail or it can't be used at `(twa, tws)`.
+pub fn set_sail(polar: &Polar, sail: u8, twa: f64, tws: f64, extrapolation: Extrapolation, method: Interpolation) -> Option<(f64, bool)> {
+    let set = polar.sail.iter().find(|s| s.id == sail && s.usable(twa, tws))?;
+    let speed = matrix::interpolate_with(&polar.twa, &polar.tws, set.matrix(twa), twa.abs(), tws, extrapolation, method);
+    let best = polar.best_sail_interpolated(twa, tws, extrapolation, method).map_or(speed, |(_, best)| best);
+
+    if speed >= best * polar.bad_sail_tolerance {
+        Some((best, false))
+    } else {
+        Some((speed, true))
+    }
+}
+
+/// Current flowing towards `set` (degrees) at `drift` knots.
+#[derive(Serialize, Debug, Clone, Copy)]
+#[cfg_attr(feature = "rocket", derive(FromForm))]
+#[serde(rename_all = "camelCase")]
+pub struct Current {
+    pub set: f64,
+    pub drift: f64,
+}
+
+/// Speed and course over ground of a boat sailing at `speed` on `heading` in `current`.
+/// Angles in degrees, course in `[0, 360)`.
+pub fn over_ground(heading: f64, speed: f64, current: Current) -> (f64, f64) {
+    let (h, c) = (heading.to_radians(), current.set.to_radians());
+    let north = speed * h.cos() + current.drift * c.cos();
+    let east = speed * h.sin() + current.drift * c.sin();
+    (north.hypot(east), east.atan2(north).to_degrees().rem_euclid(360.0))
+}
+
+/// Angles from `from` to `to` every `step` degrees, both ways. `None` when `step` is below
+/// `MIN_TWA_STEP` or the sweep would have more than `MAX_SWEEP_ANGLES` angles.
+pub fn sweep(from: f64, to: f64, step: f64) -> Option<Vec<f64>> {
+    if step.is_nan() || step < MIN_TWA_STEP || !from.is_finite() || !to.is_finite() {
+        return None;
+    }
+    let count = ((to - from).abs() / step).floor() as usize;
+    if count >= MAX_SWEEP_ANGLES {
+        return None;
+    }
+    let direction = if to >= from { 1.0 } else { -1.0 };
+    Some((0..=count).map(|i| from + direction * i as f64 * step).collect())
+}
+
+/// Smallest step of the twa sweeps, in degrees.
+pub const MIN_TWA_STEP: f64 = 0.1;
+
+/// Most angles of a twa sweep : a full turn every `MIN_TWA_STEP`.
+pub const MAX_SWEEP_ANGLES: usize = 3601;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polar::SailBounds;
+    use crate::template::test_polar;
+
+    const FOIL: Foil = Foil { speed_ratio: 1.2, twa_min: 80.0, twa_max: 150.0, twa_merge: 10.0, tws_min: 16.0, tws_max: 35.0, tws_merge: 5.0 };
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn foil_ramps_down_across_the_merge_bands() {
+        assert_close(foil_ratio(&FOIL, 100.0, 20.0), 1.2);
+        assert_close(foil_ratio(&FOIL, -100.0, 20.0), 1.2);
+        assert_close(foil_ratio(&FOIL, 75.0, 20.0), 1.1);
+        assert_close(foil_ratio(&FOIL, 100.0, 13.5), 1.1);
+        assert_close(foil_ratio(&FOIL, 155.0, 37.5), 1.05);
+        assert_close(foil_ratio(&FOIL, 75.0, 13.5), 1.05);
+        assert_close(foil_ratio(&FOIL, 60.0, 20.0), 1.0);
+        assert_close(foil_ratio(&FOIL, 100.0, 45.0), 1.0);
+    }
+
+    #[test]
+    fn auto_foil_mode_needs_a_speed_ratio_above_one() {
+        let mut polar = test_polar("compute", 1, 10, 4);
+        polar.foil = FOIL;
+        assert_close(foil(&polar, FoilMode::Auto, 100.0, 20.0), 1.2);
+        assert_close(foil(&polar, FoilMode::Off, 100.0, 20.0), 1.0);
+        polar.foil.speed_ratio = 1.0;
+        assert_close(foil(&polar, FoilMode::Auto, 100.0, 20.0), 1.0);
+        polar.foil.twa_min = 0.0;
+        assert_close(foil(&polar, FoilMode::On, 10.0, 20.0), 1.0);
+    }
+
+    /// Two sails of constant speeds, 10 and 8 knots.
+    fn two_sails() -> Polar {
+        let mut polar = test_polar("compute", 2, 10, 4);
+        for (sail, speed) in polar.sail.iter_mut().zip([10.0, 8.0]) {
+            sail.speed.iter_mut().flatten().for_each(|s| *s = speed);
+        }
+        polar
+    }
+
+    #[test]
+    fn set_sail_keeps_the_best_speed_within_the_bad_sail_tolerance() {
+        let mut polar = two_sails();
+        polar.bad_sail_tolerance = 0.9;
+        let set = |polar: &Polar, sail| set_sail(polar, sail, 90.0, 20.0, Extrapolation::Clamp, Interpolation::Bilinear);
+        assert_eq!(set(&polar, 1), Some((10.0, false)));
+        assert_eq!(set(&polar, 2), Some((8.0, true)));
+        assert_eq!(set(&polar, 3), None);
+
+        polar.bad_sail_tolerance = 0.75;
+        assert_eq!(set(&polar, 2), Some((10.0, false)));
+    }
+
+    #[test]
+    fn sails_are_only_set_where_they_can_be_used() {
+        let mut polar = two_sails();
+        polar.sail[0].bounds = Some(SailBounds { twa_max: Some(100.0), ..SailBounds::default() });
+        assert_eq!(sail_speed(&polar, 1, 90.0, 20.0), 10.0);
+        assert_eq!(sail_speed(&polar, 1, 110.0, 20.0), 0.0);
+        assert!(set_sail(&polar, 1, 90.0, 20.0, Extrapolation::Clamp, Interpolation::Bilinear).is_some());
+        assert!(set_sail(&polar, 1, -110.0, 20.0, Extrapolation::Clamp, Interpolation::Bilinear).is_none());
+    }
+
+    #[test]
+    fn auto_sail_leaves_sails_out_of_their_bounds() {
+        let mut polar = two_sails();
+        polar.sail[0].bounds = Some(SailBounds { twa_max: Some(100.0), ..SailBounds::default() });
+        polar.ice_speed_ratio = 0.5;
+        let angles = [90.0, 100.0, 110.0, 120.0];
+
+        let auto = auto_sail(&polar, 20.0, &angles, 0.9, false);
+        assert_eq!(auto.steps.iter().map(|s| s.sail).collect::<Vec<u8>>(), vec![1, 1, 2, 2]);
+        assert_eq!(auto.changes.len(), 1);
+        assert_eq!((auto.changes[0].twa, auto.changes[0].from, auto.changes[0].to), (110.0, 1, 2));
+        assert_eq!(auto.steps[0].speed, 10.0);
+
+        let in_ice = auto_sail(&polar, 20.0, &angles, 0.9, true);
+        assert_eq!(in_ice.steps[0].speed, 5.0);
+        assert_eq!(in_ice.steps[3].best_speed, 4.0);
+    }
+
+    #[test]
+    fn effective_speeds_refuse_points_off_the_grid_with_error() {
+        let polar = test_polar("compute", 2, 10, 4);
+        let table = Table::new(&polar);
+        let options = |extrapolation| Options { extrapolation: Some(extrapolation), ..Options::default() };
+
+        let inside = [(90.0, 20.0), (-90.0, 40.0)];
+        assert!(effective_speeds(&polar, &table, &options(Extrapolation::Error), &inside).is_some());
+        let outside = [(90.0, 20.0), (90.0, 45.0)];
+        assert!(effective_speeds(&polar, &table, &options(Extrapolation::Error), &outside).is_none());
+
+        let clamped = effective_speeds(&polar, &table, &options(Extrapolation::Clamp), &outside).unwrap();
+        let linear = effective_speeds(&polar, &table, &options(Extrapolation::Linear), &outside).unwrap();
+        assert_eq!(clamped[1], effective_speeds(&polar, &table, &Options::default(), &[(90.0, 40.0)]).unwrap()[0]);
+        assert!(linear[1].1 > clamped[1].1);
+    }
+}
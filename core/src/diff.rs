@@ -0,0 +1,145 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::polar::{self, Polar, SpeedDiff};
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PolarDiff {
+    pub fields: Vec<FieldChange>,
+    pub grid: GridDiff,
+    pub sails: SailsDiff,
+}
+
+/// A changed scalar, addressed by its path in the serialized polar (e.g. `/foil/speedRatio`).
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+    pub path: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GridDiff {
+    pub twa_added: Vec<u8>,
+    pub twa_removed: Vec<u8>,
+    pub tws_added: Vec<u8>,
+    pub tws_removed: Vec<u8>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SailsDiff {
+    pub added: Vec<u8>,
+    pub removed: Vec<u8>,
+    pub renamed: Vec<FieldChange>,
+    pub summary: Vec<SailSummary>,
+    /// sails of the second polar whose speed matrix is the same as a sail of the first one
+    pub same_matrix: Vec<SameMatrix>,
+    pub speed: SpeedDiff,
+}
+
+/// A speed matrix shared by both polars, as addressed in the matrix store.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SameMatrix {
+    pub sail: u8,
+    pub same_as: u8,
+    pub hash: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SailSummary {
+    pub sail: u8,
+    pub changed_cells: usize,
+    pub max_delta: f64,
+    pub mean_delta: f64,
+}
+
+/// Fields which are not compared as scalars.
+const IGNORED_FIELDS: [&str; 4] = ["id", "tws", "twa", "sail"];
+
+pub fn diff(a: &Polar, b: &Polar) -> anyhow::Result<PolarDiff> {
+    let mut fields = Vec::new();
+    scalar_changes("", &serde_json::to_value(a)?, &serde_json::to_value(b)?, &mut fields);
+
+    let grid = GridDiff {
+        twa_added: missing(&b.twa, &a.twa),
+        twa_removed: missing(&a.twa, &b.twa),
+        tws_added: missing(&b.tws, &a.tws),
+        tws_removed: missing(&a.tws, &b.tws),
+    };
+
+    let a_sails: Vec<u8> = a.sail.iter().map(|s| s.id).collect();
+    let b_sails: Vec<u8> = b.sail.iter().map(|s| s.id).collect();
+
+    let renamed = a.sail.iter()
+        .filter_map(|sa| b.sail.iter().find(|sb| sb.id == sa.id && sb.name != sa.name).map(|sb| (sa, sb)))
+        .map(|(sa, sb)| FieldChange {
+            path: format!("/sail/{}/name", sa.id),
+            before: Some(Value::from(sa.name.clone())),
+            after: Some(Value::from(sb.name.clone())),
+        })
+        .collect();
+
+    let speed = a.speed_diff(b);
+    let summary = a_sails.iter()
+        .filter(|id| b_sails.contains(id))
+        .map(|id| {
+            let deltas: Vec<f64> = speed.cells.iter().filter(|c| c.sail == *id).map(|c| c.delta.abs()).collect();
+            SailSummary {
+                sail: *id,
+                changed_cells: deltas.len(),
+                max_delta: deltas.iter().copied().fold(0.0, f64::max),
+                mean_delta: if deltas.is_empty() { 0.0 } else { deltas.iter().sum::<f64>() / deltas.len() as f64 },
+            }
+        })
+        .collect();
+
+    let mut same_matrix = Vec::new();
+    for sb in &b.sail {
+        // the sail with the same id first, as it is the expected match
+        let sa = a.sail.iter().filter(|sa| sa.speed == sb.speed).min_by_key(|sa| sa.id != sb.id);
+        if let Some(sa) = sa {
+            same_matrix.push(SameMatrix { sail: sb.id, same_as: sa.id, hash: polar::matrix_hash(&serde_json::to_value(&sb.speed)?)? });
+        }
+    }
+
+    let sails = SailsDiff {
+        added: missing(&b_sails, &a_sails),
+        removed: missing(&a_sails, &b_sails),
+        renamed,
+        summary,
+        same_matrix,
+        speed,
+    };
+
+    Ok(PolarDiff { fields, grid, sails })
+}
+
+/// Values of `a` which are not in `b`.
+fn missing(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().filter(|x| !b.contains(x)).copied().collect()
+}
+
+fn scalar_changes(path: &str, a: &Value, b: &Value, res: &mut Vec<FieldChange>) {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            for key in a.keys().chain(b.keys().filter(|k| !a.contains_key(*k))) {
+                if path.is_empty() && IGNORED_FIELDS.contains(&key.as_str()) {
+                    continue;
+                }
+                let child = format!("{}/{}", path, key);
+                match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => scalar_changes(&child, a, b, res),
+                    (a, b) => res.push(FieldChange { path: child, before: a.cloned(), after: b.cloned() }),
+                }
+            }
+        }
+        (a, b) if a != b => res.push(FieldChange { path: path.to_string(), before: Some(a.clone()), after: Some(b.clone()) }),
+        _ => {}
+    }
+}
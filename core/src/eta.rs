@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+
+use crate::compute;
+use crate::penalty::{self, Maneuver, WinchKind};
+use crate::polar::Polar;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Leg {
+    pub distance_nm: f64,
+    /// in either angle convention
+    #[serde(deserialize_with = "compute::signed_twa")]
+    pub twa: f64,
+    pub tws: f64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LegTime {
+    pub distance_nm: f64,
+    pub twa: f64,
+    pub tws: f64,
+    pub sail: u8,
+    pub speed: f64,
+    /// maneuver done at the start of the leg
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maneuver: Option<Maneuver>,
+    /// time lost by the maneuver
+    pub penalty_sec: f64,
+    pub elapsed_sec: f64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Eta {
+    pub legs: Vec<LegTime>,
+    pub total_sec: f64,
+}
+
+/// Time to sail `legs` with the best sail. With `winch`, the maneuver between two legs
+/// (tack, gybe or sail change) is taken into account with the penalties of that winch pack.
+pub fn eta(polar: &Polar, legs: &[Leg], winch: Option<WinchKind>) -> Eta {
+    let mut times: Vec<LegTime> = Vec::with_capacity(legs.len());
+
+    for leg in legs {
+        let (sail, speed) = polar.best_sail(leg.twa, leg.tws)
+            .map_or((0, 0.0), |(sail, speed)| (sail, speed * polar.speed_ratio(false)));
+
+        let maneuver = match (winch, times.last()) {
+            (Some(_), Some(previous)) => {
+                let kind = Maneuver::between(previous.twa, leg.twa);
+                (kind != Maneuver::SailChange || previous.sail != sail).then_some(kind)
+            }
+            _ => None,
+        };
+        let penalty_sec = match (winch, maneuver) {
+            (Some(winch), Some(kind)) if speed > 0.0 => {
+                penalty::timeline(polar, kind, winch, leg.tws, times.last().map_or(leg.twa, |p| p.twa), leg.twa)
+                    .map_or(0.0, |timeline| timeline.distance_lost / speed * 3600.0)
+            }
+            _ => 0.0,
+        };
+
+        let elapsed_sec = if speed > 0.0 { leg.distance_nm / speed * 3600.0 + penalty_sec } else { f64::INFINITY };
+        times.push(LegTime {
+            distance_nm: leg.distance_nm,
+            twa: leg.twa,
+            tws: leg.tws,
+            sail,
+            speed,
+            maneuver,
+            penalty_sec,
+            elapsed_sec,
+        });
+    }
+
+    let total_sec = times.iter().map(|t| t.elapsed_sec).sum();
+    Eta { legs: times, total_sec }
+}
+
+/// Race profile : a route of legs, or a histogram of the wind conditions over a distance.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Scenario {
+    #[serde(default)]
+    pub legs: Vec<Leg>,
+    #[serde(default)]
+    pub histogram: Vec<WindBin>,
+    /// distance split between the bins of the histogram
+    #[serde(default)]
+    pub distance_nm: f64,
+    /// winch pack used to account for the maneuvers between legs, none when absent
+    pub winch: Option<WinchKind>,
+}
+
+/// Wind conditions met over `weight` of the distance, weights being relative to their sum.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct WindBin {
+    /// in either angle convention
+    #[serde(deserialize_with = "compute::signed_twa")]
+    pub twa: f64,
+    pub tws: f64,
+    pub weight: f64,
+}
+
+impl Scenario {
+    /// Legs sailing the share of the distance of each bin of the histogram.
+    pub fn bins(&self) -> Vec<Leg> {
+        let total: f64 = self.histogram.iter().map(|bin| bin.weight.max(0.0)).sum();
+        self.histogram.iter()
+            .filter(|_| total > 0.0)
+            .map(|bin| Leg { distance_nm: self.distance_nm * bin.weight.max(0.0) / total, twa: bin.twa, tws: bin.tws })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScenarioResult {
+    pub id: Option<String>,
+    pub label: String,
+    pub total_sec: f64,
+    /// time lost on the fastest polar
+    pub behind_sec: f64,
+}
+
+/// Time of each of `polars` over the legs then the histogram of `scenario`, fastest first.
+/// Maneuvers are accounted for between the route legs when the scenario has a winch, never
+/// between the bins of the histogram.
+pub fn evaluate(polars: &[Polar], scenario: &Scenario) -> Vec<ScenarioResult> {
+    let bins = scenario.bins();
+    let mut results: Vec<ScenarioResult> = polars.iter()
+        .map(|polar| ScenarioResult {
+            id: polar.id.clone(),
+            label: polar.label.clone(),
+            total_sec: eta(polar, &scenario.legs, scenario.winch).total_sec + eta(polar, &bins, None).total_sec,
+            behind_sec: 0.0,
+        })
+        .collect();
+    results.sort_by(|a, b| a.total_sec.total_cmp(&b.total_sec));
+    if let Some(fastest) = results.first().map(|r| r.total_sec) {
+        for result in results.iter_mut() {
+            result.behind_sec = result.total_sec - fastest;
+        }
+    }
+    results
+}
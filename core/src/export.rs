@@ -0,0 +1,318 @@
+#[cfg(feature = "rocket")]
+use rocket::form::FromFormField;
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use anyhow::Result;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use crate::compute::{self, Options};
+use crate::matrix;
+use crate::polar::{canonical_yaml, Polar};
+use crate::stats;
+use crate::table::Table;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rocket", derive(FromFormField))]
+pub enum ExportFormat {
+    /// fixed layout little endian binary, see `binary`
+    Bin,
+    /// downsampled grid with quantized speeds, see `lite`
+    Lite,
+    /// tab separated table of the Adrena navigation software
+    Adrena,
+    /// semicolon separated table of MaxSea TimeZero
+    TimeZero,
+    /// upwind and downwind targets per tws as CSV, see `targets`
+    Targets,
+    /// polar table of the B&G H5000 and Zeus processors, see `bandg`
+    BandG,
+    /// best sail per cell of the grid as CSV, see `crossover`
+    Crossover,
+    /// best sail per cell of the grid as JSON, see `crossover`
+    #[cfg_attr(feature = "rocket", field(value = "crossover-json"))]
+    CrossoverJson,
+}
+
+/// Grid steps of the lite export, in degrees and knots.
+#[derive(Debug, Clone, Copy)]
+pub struct Steps {
+    pub twa: u8,
+    pub tws: u8,
+}
+
+impl Default for Steps {
+    fn default() -> Self {
+        Steps { twa: 10, tws: 4 }
+    }
+}
+
+/// Magic number at the start of binary exports.
+pub(crate) const MAGIC: &[u8; 4] = b"NPOL";
+pub(crate) const VERSION: u16 = 1;
+/// Flag of the binary exports of polars with port tack speeds, which only hold the starboard ones.
+pub(crate) const PORT_SPEEDS: u16 = 1;
+
+/// Effective polar in `format`, binary for `Bin`, JSON for `Lite` and `CrossoverJson` and text
+/// for the others. `None` when the extrapolation of `options` is `Error` and the format samples
+/// speeds off the grid.
+pub fn export(polar: &Polar, table: &Table, options: &Options, format: ExportFormat, steps: Steps) -> Option<Vec<u8>> {
+    Some(match format {
+        ExportFormat::Bin => binary(polar, table, options)?,
+        ExportFormat::Lite => serde_json::to_vec(&lite(polar, table, options, steps)?).unwrap_or_default(),
+        ExportFormat::Adrena => text_table(polar, table, options, "\t", "\r\n")?.into_bytes(),
+        ExportFormat::TimeZero => text_table(polar, table, options, ";", "\r\n")?.into_bytes(),
+        ExportFormat::Targets => targets(polar).into_bytes(),
+        ExportFormat::BandG => bandg(polar, table, options)?.into_bytes(),
+        ExportFormat::Crossover => crossover(polar, table, options)?.csv().into_bytes(),
+        ExportFormat::CrossoverJson => serde_json::to_vec(&crossover(polar, table, options)?).unwrap_or_default(),
+    })
+}
+
+/// Sail crossover table : the fastest sail on every cell of the grid.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CrossoverTable {
+    pub twa: Vec<u8>,
+    pub tws: Vec<u8>,
+    /// sail ids, a row per twa
+    pub sail: Vec<Vec<u8>>,
+    /// names of the sails, by id
+    pub names: BTreeMap<u8, String>,
+}
+
+impl CrossoverTable {
+    /// `TWA\TWS` table of the sail names, their ids when unnamed, as pasted in crossover charts.
+    pub fn csv(&self) -> String {
+        let mut out = String::from("TWA\\TWS");
+        for tws in &self.tws {
+            out.push_str(&format!(",{}", tws));
+        }
+        out.push('\n');
+        for (twa, row) in self.twa.iter().zip(&self.sail) {
+            out.push_str(&twa.to_string());
+            for sail in row {
+                match self.names.get(sail).filter(|name| !name.is_empty()) {
+                    Some(name) => out.push_str(&format!(",{}", name.replace(',', " "))),
+                    None => out.push_str(&format!(",{}", sail)),
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Fastest sail on every cell of the grid of `polar`, with the options applied.
+pub fn crossover(polar: &Polar, table: &Table, options: &Options) -> Option<CrossoverTable> {
+    let points: Vec<(f64, f64)> = polar.twa.iter()
+        .flat_map(|twa| polar.tws.iter().map(move |tws| (*twa as f64, *tws as f64)))
+        .collect();
+    let sail = compute::effective_speeds(polar, table, options, &points)?
+        .chunks(polar.tws.len().max(1))
+        .map(|row| row.iter().map(|(sail, _)| *sail).collect())
+        .collect();
+
+    Some(CrossoverTable {
+        twa: polar.twa.clone(),
+        tws: polar.tws.clone(),
+        sail,
+        names: polar.sail.iter().map(|s| (s.id, s.name.clone())).collect(),
+    })
+}
+
+/// Tws columns of the B&G polar tables, in knots.
+const BANDG_TWS: [u8; 10] = [4, 6, 8, 10, 12, 14, 16, 20, 25, 30];
+
+/// Effective polar as the CSV polar table loaded by the B&G H5000 and Zeus processors : a
+/// `Polar` line naming it, a header line of the fixed `BANDG_TWS` columns, then a line per
+/// twa of the grid with the boat speeds in knots.
+pub fn bandg(polar: &Polar, table: &Table, options: &Options) -> Option<String> {
+    let mut out = format!("Polar,{}\r\nTWA\\TWS", polar.label.replace(',', " "));
+    for tws in BANDG_TWS {
+        out.push_str(&format!(",{}", tws));
+    }
+    out.push_str("\r\n");
+
+    let points: Vec<(f64, f64)> = polar.twa.iter()
+        .flat_map(|twa| BANDG_TWS.iter().map(move |tws| (*twa as f64, *tws as f64)))
+        .collect();
+    let speeds = compute::effective_speeds(polar, table, options, &points)?;
+    for (twa, row) in polar.twa.iter().zip(speeds.chunks(BANDG_TWS.len())) {
+        out.push_str(&twa.to_string());
+        for (_, speed) in row {
+            out.push_str(&format!(",{:.2}", speed));
+        }
+        out.push_str("\r\n");
+    }
+    Some(out)
+}
+
+/// Target angles, boat speeds and vmg of the best upwind and downwind vmg for each tws of the
+/// grid, as the CSV of the instrument configuration tools. Speeds in knots, angles in degrees.
+pub fn targets(polar: &Polar) -> String {
+    let mut out = String::from("TWS,Upwind TWA,Upwind BSP,Upwind VMG,Downwind TWA,Downwind BSP,Downwind VMG\n");
+    for tws in &polar.tws {
+        if let Some((upwind, downwind)) = stats::vmg(polar, *tws as f64) {
+            out.push_str(&format!("{},{:.0},{:.2},{:.2},{:.0},{:.2},{:.2}\n",
+                tws, upwind.twa, upwind.speed, upwind.vmg, downwind.twa, downwind.speed, downwind.vmg));
+        }
+    }
+    out
+}
+
+/// Effective speeds on the grid of `polar` as a `TWA\TWS` table : a header line of the tws,
+/// then a line per twa starting with it, speeds in knots with two decimals.
+fn text_table(polar: &Polar, table: &Table, options: &Options, separator: &str, newline: &str) -> Option<String> {
+    let mut out = String::from("TWA\\TWS");
+    for tws in &polar.tws {
+        out.push_str(&format!("{}{}", separator, tws));
+    }
+    out.push_str(newline);
+
+    let points: Vec<(f64, f64)> = polar.twa.iter()
+        .flat_map(|twa| polar.tws.iter().map(move |tws| (*twa as f64, *tws as f64)))
+        .collect();
+    let speeds = compute::effective_speeds(polar, table, options, &points)?;
+    for (twa, row) in polar.twa.iter().zip(speeds.chunks(polar.tws.len().max(1))) {
+        out.push_str(&twa.to_string());
+        for (_, speed) in row {
+            out.push_str(&format!("{}{:.2}", separator, speed));
+        }
+        out.push_str(newline);
+    }
+    Some(out)
+}
+
+/// Gzip compressed tar of the `polars` as canonical YAML files named after their ids.
+pub fn archive(polars: &[Polar]) -> Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for polar in polars {
+        let mut value = serde_json::to_value(polar)?;
+        // what was read from the listed file is not part of its content
+        if let Some(map) = value.as_object_mut() {
+            map.remove("file");
+            map.remove("verified");
+        }
+        let content = canonical_yaml(&value)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(polar.modified.unwrap_or_default());
+        header.set_cksum();
+        builder.append_data(&mut header, format!("{}.yaml", polar.id.as_deref().unwrap_or_default()), content.as_bytes())?;
+    }
+    Ok(builder.into_inner()?.finish()?)
+}
+
+/// Effective polar in a fixed layout, all values little endian :
+///
+/// - magic `NPOL`, version `u16`, twa count `u16`, tws count `u16`, flags `u16` : `PORT_SPEEDS`
+/// - twa then tws values as `f32`
+/// - speeds in knots as `f32`, row major : `speed[twa_index * tws_count + tws_index]`
+/// - fastest sail ids as `u8` in the same order, zero padded to a multiple of 4 bytes
+pub fn binary(polar: &Polar, table: &Table, options: &Options) -> Option<Vec<u8>> {
+    let cells = polar.twa.len() * polar.tws.len();
+    let mut out = Vec::with_capacity(12 + 4 * (polar.twa.len() + polar.tws.len() + cells) + cells + 3);
+
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&(polar.twa.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(polar.tws.len() as u16).to_le_bytes());
+    let flags = if polar.sail.iter().any(|s| s.port_speed.is_some()) { PORT_SPEEDS } else { 0 };
+    out.extend_from_slice(&flags.to_le_bytes());
+
+    for value in polar.twa.iter().chain(&polar.tws) {
+        out.extend_from_slice(&(*value as f32).to_le_bytes());
+    }
+
+    let points: Vec<(f64, f64)> = polar.twa.iter()
+        .flat_map(|twa| polar.tws.iter().map(move |tws| (*twa as f64, *tws as f64)))
+        .collect();
+    let speeds = compute::effective_speeds(polar, table, options, &points)?;
+    for (_, speed) in &speeds {
+        out.extend_from_slice(&(*speed as f32).to_le_bytes());
+    }
+    out.extend(speeds.iter().map(|(sail, _)| *sail));
+    out.resize(out.len().next_multiple_of(4), 0);
+
+    Some(out)
+}
+
+/// Speeds of the lite export are integers in hundredths of knot.
+const LITE_SCALE: f64 = 0.01;
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LitePolar {
+    /// knots per speed unit
+    pub scale: f64,
+    pub twa: Vec<u8>,
+    pub tws: Vec<u8>,
+    pub speed: Vec<Vec<u16>>,
+}
+
+/// Effective polar resampled every `steps` over the grid bounds, speeds quantized to `LITE_SCALE`.
+pub fn lite(polar: &Polar, table: &Table, options: &Options, steps: Steps) -> Option<LitePolar> {
+    let twa = resample(&polar.twa, steps.twa);
+    let tws = resample(&polar.tws, steps.tws);
+
+    let points: Vec<(f64, f64)> = twa.iter()
+        .flat_map(|a| tws.iter().map(move |w| (*a as f64, *w as f64)))
+        .collect();
+    let speed = compute::effective_speeds(polar, table, options, &points)?
+        .chunks(tws.len().max(1))
+        .map(|row| row.iter().map(|(_, speed)| (speed / LITE_SCALE).round().clamp(0.0, u16::MAX as f64) as u16).collect())
+        .collect();
+
+    Some(LitePolar { scale: LITE_SCALE, twa, tws, speed })
+}
+
+/// Values of `axis` every `step` from its first to its last one, both included.
+fn resample(axis: &[u8], step: u8) -> Vec<u8> {
+    let (Some(first), Some(last)) = (axis.first(), axis.last()) else { return Vec::new() };
+    let mut values: Vec<u8> = (*first..=*last).step_by(step.max(1) as usize).chain(std::iter::once(*last)).collect();
+    values.dedup();
+    values
+}
+
+/// Speed surface on a regular grid : `speed` is flat, row major with a row per twa, so that it
+/// can be loaded as is in a `Float32Array` for WebGL or reshaped to `shape` for plotly.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Surface {
+    pub twa: Vec<u8>,
+    pub tws: Vec<u8>,
+    /// rows and columns of `speed`
+    pub shape: [usize; 2],
+    pub speed: Vec<f32>,
+}
+
+/// Effective speeds of `sail`, of the fastest sail when not given, resampled every `steps` over
+/// the grid bounds. `None` when the polar has no such sail.
+pub fn surface(polar: &Polar, table: &Table, options: &Options, sail: Option<u8>, steps: Steps) -> Option<Surface> {
+    let twa = resample(&polar.twa, steps.twa);
+    let tws = resample(&polar.tws, steps.tws);
+    let points: Vec<(f64, f64)> = twa.iter()
+        .flat_map(|a| tws.iter().map(move |w| (*a as f64, *w as f64)))
+        .collect();
+
+    let speed = match sail {
+        None => compute::effective_speeds(polar, table, options, &points)?.into_iter().map(|(_, speed)| speed as f32).collect(),
+        Some(sail) => {
+            let sail = polar.sail.iter().find(|s| s.id == sail)?;
+            let extrapolation = options.extrapolation.unwrap_or_default();
+            let ratio = polar.speed_ratio(options.in_ice);
+            points.iter()
+                .map(|(a, w)| {
+                    let speed = matrix::interpolate_with(&polar.twa, &polar.tws, &sail.speed, *a, *w, extrapolation, options.method.unwrap_or_default());
+                    (speed * ratio * compute::foil(polar, options.foil, *a, *w)) as f32
+                })
+                .collect()
+        }
+    };
+
+    Some(Surface { shape: [twa.len(), tws.len()], twa, tws, speed })
+}
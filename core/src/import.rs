@@ -0,0 +1,119 @@
+#[cfg(feature = "rocket")]
+use rocket::form::FromFormField;
+
+use anyhow::{anyhow, Result};
+
+use crate::polar::{Polar, Sail};
+use crate::template::{Template, TemplateParams};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rocket", derive(FromFormField))]
+pub enum ImportFormat {
+    /// semicolon separated `TWA\TWS` table of SailGrib WR
+    SailGrib,
+    /// tab separated `TWA\TWS` table of Weather4D
+    Weather4D,
+}
+
+impl ImportFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "sailgrib" => Some(ImportFormat::SailGrib),
+            "weather4d" => Some(ImportFormat::Weather4D),
+            _ => None,
+        }
+    }
+
+    fn separator(&self) -> char {
+        match self {
+            ImportFormat::SailGrib => ';',
+            ImportFormat::Weather4D => '\t',
+        }
+    }
+}
+
+/// Polar `id` from the `content` of a file in `format` : a header line of the tws, then a line
+/// per twa starting with it, speeds in knots. The table becomes a single sail, the other fields
+/// are those of a scaffolded monohull, to be completed before publishing the polar.
+pub fn import(content: &str, format: ImportFormat, id: &str) -> Result<Polar> {
+    let mut lines = content.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+    let cells = |line: &str| -> Vec<String> { line.split(format.separator()).map(|c| c.trim().to_string()).collect() };
+
+    let (header_number, header) = lines.next().ok_or_else(|| anyhow!("empty polar file"))?;
+    let tws = cells(header).iter().skip(1).map(|cell| angle_or_speed(cell)).collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| anyhow!("line {} : tws must be numbers from 0 to 255", header_number))?;
+    // rounded to the knot, close tws may end up duplicated
+    if let Some(w) = tws.windows(2).find(|w| w[0] >= w[1]) {
+        return Err(anyhow!("line {} : tws {} after {}, tws must be strictly ascending", header_number, w[1], w[0]));
+    }
+
+    let mut twa = Vec::new();
+    let mut speed = Vec::new();
+    for (number, line) in lines {
+        let cells = cells(line);
+        let angle = angle_or_speed(&cells[0]).ok_or_else(|| anyhow!("line {} : twa must be a number from 0 to 255", number))?;
+        let row = cells[1..].iter().map(|cell| cell.replace(',', ".").parse::<f64>().ok()).collect::<Option<Vec<f64>>>()
+            .ok_or_else(|| anyhow!("line {} : speeds must be numbers", number))?;
+        if row.len() != tws.len() {
+            return Err(anyhow!("line {} : {} speeds for {} tws", number, row.len(), tws.len()));
+        }
+        if let Some(&previous) = twa.last().filter(|&&previous| previous >= angle) {
+            return Err(anyhow!("line {} : twa {} after {}, twa must be strictly ascending", number, angle, previous));
+        }
+        twa.push(angle);
+        speed.push(row);
+    }
+
+    let params = TemplateParams {
+        template: Template::Monohull,
+        id: id.to_string(),
+        polar_id: 0,
+        label: None,
+        sails: Some(1),
+        twa_step: None,
+        tws_step: None,
+        tws_max: None,
+        base_speed: None,
+    };
+    let mut polar = params.scaffold();
+    polar.max_speed = 0.0;
+    polar.sail = vec![Sail { id: 1, name: String::new(), catalog: None, option: None, speed, port_speed: None, bounds: None }];
+    polar.twa = twa;
+    polar.tws = tws;
+    Ok(polar)
+}
+
+/// Twa or tws of a table header, rounded to the degree or knot.
+fn angle_or_speed(cell: &str) -> Option<u8> {
+    let value: f64 = cell.replace(',', ".").parse().ok()?;
+    (0.0..=255.0).contains(&value).then(|| value.round() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_a_table() {
+        let polar = import("twa;6;10\n45;5,5;7\n90;6.5;8\n", ImportFormat::SailGrib, "imported").unwrap();
+        assert_eq!(polar.tws, vec![6, 10]);
+        assert_eq!(polar.twa, vec![45, 90]);
+        assert_eq!(polar.sail[0].speed, vec![vec![5.5, 7.0], vec![6.5, 8.0]]);
+    }
+
+    #[test]
+    fn rejects_axes_out_of_order() {
+        let format = ImportFormat::SailGrib;
+        let error = import("twa;10;6\n45;5;7\n", format, "imported").unwrap_err();
+        assert_eq!(error.to_string(), "line 1 : tws 6 after 10, tws must be strictly ascending");
+
+        let error = import("twa;6;10\n\n90;5;7\n# comment\n90;6;8\n", format, "imported").unwrap_err();
+        assert_eq!(error.to_string(), "line 5 : twa 90 after 90, twa must be strictly ascending");
+
+        let error = import("twa;6.2;6.4\n45;5;7\n", format, "imported").unwrap_err();
+        assert!(error.to_string().starts_with("line 1 : tws 6 after 6"));
+    }
+}
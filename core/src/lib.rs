@@ -0,0 +1,62 @@
+//! Polar model, storage and computations of the polars service, usable without the web server.
+//!
+//! The `rocket` feature derives the Rocket form traits of the query types, as used by the server.
+
+#![feature(path_file_prefix)]
+
+/// Audit log of the administrative changes.
+pub mod audit;
+/// Shared sail definitions and winch profiles.
+pub mod catalog;
+/// Speed comparison of several polars.
+pub mod compare;
+/// Speed evaluation : options, foils, auto sail, set sail and current.
+pub mod compute;
+/// Field and grid differences between two polars.
+pub mod diff;
+/// Time to sail a route of legs, maneuvers included.
+pub mod eta;
+/// Binary and lite exports of the effective polar.
+pub mod export;
+/// Polars from the table files of other routing apps.
+pub mod import;
+/// Consistency checks of the speed matrices.
+pub mod lint;
+/// Memory mapped binary polars.
+pub mod mapped;
+/// Speed matrices : interpolation, smoothing and regridding.
+pub mod matrix;
+/// Winch penalties of tacks, gybes and sail changes.
+pub mod penalty;
+/// Schema versions of the polar files and their migrations.
+pub mod migrate;
+/// Polar model : sails, foils, winches and their evaluation.
+pub mod polar;
+/// Generic class polars bundled with the service.
+pub mod preset;
+/// Automatic fixes of the lint findings.
+pub mod repair;
+/// `_id`s reserved while polars are created.
+pub mod reservation;
+/// Impact of perturbed polar fields on vmg and leg times.
+pub mod sensitivity;
+/// Polars served from the store, `PolarService`.
+pub mod service;
+/// Ed25519 detached signatures of official polars.
+pub mod signature;
+/// Speed statistics of a polar and of the catalog.
+pub mod stats;
+/// Polar files : directories, compression, checksums, parents and revisions.
+pub mod store;
+/// Precomputed evaluation tables.
+pub mod table;
+/// Polar scaffolds from templates.
+pub mod template;
+/// Scaling and reshaping of polars.
+pub mod transform;
+/// Speed units and formatting.
+pub mod units;
+/// Usage counters and history.
+pub mod usage;
+/// Structural validation of incoming polars, errors located by JSON pointers.
+pub mod validate;
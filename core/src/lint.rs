@@ -0,0 +1,240 @@
+use serde::Serialize;
+
+use crate::polar::Polar;
+
+/// Speed drop along tws tolerated before being reported, in knots.
+const DECREASE_TOLERANCE: f64 = 0.05;
+/// Relative difference between adjacent cells above which a jump is reported.
+pub const JUMP_RATIO: f64 = 0.3;
+/// Absolute difference between adjacent cells below which nothing is reported, in knots.
+pub const JUMP_MIN: f64 = 1.0;
+/// Relative deviation from the value expected from the neighbours above which a cell is an outlier.
+const OUTLIER_RATIO: f64 = 0.25;
+/// Absolute deviation from the value expected from the neighbours below which nothing is reported, in knots.
+const OUTLIER_MIN: f64 = 1.0;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FindingKind {
+    /// speed decreases when tws increases
+    DecreasingWithTws,
+    /// large difference with an adjacent cell
+    Jump,
+    /// speed above `maxSpeed`
+    AboveMaxSpeed,
+    /// sail never fastest anywhere in the grid
+    UnusedSail,
+    /// field unknown to the model, e.g. a typo
+    UnknownField,
+    /// cell deviating from the line through its neighbours along both axes, e.g. a transcription error
+    Outlier,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Finding {
+    pub kind: FindingKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sail: Option<u8>,
+    /// path of the field, for unknown fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub twa: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tws: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+    /// replacement value, for outliers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggested: Option<f64>,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LintReport {
+    pub findings: Vec<Finding>,
+}
+
+pub fn lint(polar: &Polar) -> LintReport {
+    let mut findings = Vec::new();
+
+    for sail in &polar.sail {
+        let cell = |i: usize, j: usize| sail.speed.get(i).and_then(|row| row.get(j)).copied();
+        for (i, twa) in polar.twa.iter().enumerate() {
+            for (j, tws) in polar.tws.iter().enumerate() {
+                let Some(v) = cell(i, j) else { continue };
+                let finding = |kind, message| Finding { kind, sail: Some(sail.id), field: None, twa: Some(*twa), tws: Some(*tws), value: Some(v), suggested: None, message };
+
+                if polar.max_speed > 0.0 && v > polar.max_speed {
+                    findings.push(finding(FindingKind::AboveMaxSpeed, format!("{} is above max speed {}", v, polar.max_speed)));
+                }
+
+                if let Some(next) = cell(i, j + 1) {
+                    if next < v - DECREASE_TOLERANCE {
+                        findings.push(finding(FindingKind::DecreasingWithTws, format!("speed drops to {} at tws {}", next, polar.tws[j + 1])));
+                    }
+                }
+
+                if let Some(expected) = outlier(&polar.twa, &polar.tws, &sail.speed, i, j) {
+                    findings.push(Finding {
+                        suggested: Some((expected * 100.0).round() / 100.0),
+                        ..finding(FindingKind::Outlier, format!("{} deviates from the {:.2} expected from its neighbours", v, expected))
+                    });
+                }
+
+                for (neighbour, at) in [(cell(i + 1, j), "next twa"), (cell(i, j + 1), "next tws")] {
+                    if let Some(n) = neighbour {
+                        let diff = (n - v).abs();
+                        if diff > JUMP_MIN && diff > JUMP_RATIO * n.abs().max(v.abs()) {
+                            findings.push(finding(FindingKind::Jump, format!("jump of {:.2} to the {} cell", diff, at)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut used = Vec::new();
+    for twa in &polar.twa {
+        for tws in &polar.tws {
+            if let Some((sail, speed)) = polar.best_sail(*twa as f64, *tws as f64) {
+                if speed > 0.0 && !used.contains(&sail) {
+                    used.push(sail);
+                }
+            }
+        }
+    }
+    for sail in polar.sail.iter().filter(|s| !used.contains(&s.id)) {
+        findings.push(Finding {
+            kind: FindingKind::UnusedSail,
+            sail: Some(sail.id),
+            field: None,
+            twa: None,
+            tws: None,
+            value: None,
+            suggested: None,
+            message: format!("{} is never the fastest sail", sail.name),
+        });
+    }
+
+    LintReport { findings }
+}
+
+/// Value expected at `(i, j)` of the `speed` matrix on the `twa` x `tws` grid when the cell is an
+/// outlier : it deviates from the line through its neighbours (second difference) along both axes
+/// it has neighbours on, in the same direction. The expected value is the mean of the values on
+/// those lines. Outliers are reported by `lint` and replaced by the `repair` fix.
+pub fn outlier(twa: &[u8], tws: &[u8], speed: &[Vec<f64>], i: usize, j: usize) -> Option<f64> {
+    let cell = |i: usize, j: usize| speed.get(i).and_then(|row| row.get(j)).copied();
+    let v = cell(i, j)?;
+    let on_line = |axis: &[u8], k: usize, before: Option<f64>, after: Option<f64>| -> Option<f64> {
+        let (before, after) = (before?, after?);
+        let (a, x, b) = (axis[k.checked_sub(1)?] as f64, axis[k] as f64, *axis.get(k + 1)? as f64);
+        (b > a).then(|| before + (after - before) * (x - a) / (b - a))
+    };
+    let expected: Vec<f64> = [
+        on_line(twa, i, i.checked_sub(1).and_then(|i| cell(i, j)), cell(i + 1, j)),
+        on_line(tws, j, j.checked_sub(1).and_then(|j| cell(i, j)), cell(i, j + 1)),
+    ].into_iter().flatten().collect();
+    if expected.is_empty() {
+        return None;
+    }
+
+    let deviates = |e: &f64| (v - e).abs() > OUTLIER_MIN && (v - e).abs() > OUTLIER_RATIO * e.abs().max(v.abs());
+    let same_direction = expected.iter().all(|e| v > *e) || expected.iter().all(|e| v < *e);
+    (expected.iter().all(deviates) && same_direction).then(|| expected.iter().sum::<f64>() / expected.len() as f64)
+}
+
+impl Finding {
+    /// Finding of the unknown field at `path`.
+    pub fn unknown_field(path: String) -> Self {
+        Finding {
+            kind: FindingKind::UnknownField,
+            sail: None,
+            twa: None,
+            tws: None,
+            value: None,
+            suggested: None,
+            message: format!("{} is not a known field", path),
+            field: Some(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repair::{self, Fix};
+    use crate::template::test_polar;
+
+    const TWA: [u8; 4] = [40, 60, 90, 120];
+    const TWS: [u8; 4] = [6, 10, 16, 20];
+
+    /// Speeds linear along both axes, without outliers.
+    fn linear() -> Vec<Vec<f64>> {
+        TWA.iter().map(|twa| TWS.iter().map(|tws| 2.0 + 0.05 * *twa as f64 + 0.3 * *tws as f64).collect()).collect()
+    }
+
+    #[test]
+    fn linear_speeds_have_no_outlier() {
+        let speed = linear();
+        for i in 0..TWA.len() {
+            for j in 0..TWS.len() {
+                assert_eq!(outlier(&TWA, &TWS, &speed, i, j), None, "({}, {})", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn spike_is_an_outlier_with_its_expected_value() {
+        let mut speed = linear();
+        let expected = speed[1][2];
+        speed[1][2] += 6.0;
+        assert!((outlier(&TWA, &TWS, &speed, 1, 2).unwrap() - expected).abs() < 1e-9);
+        speed[1][2] = expected - 6.0;
+        assert!((outlier(&TWA, &TWS, &speed, 1, 2).unwrap() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn small_or_one_sided_deviations_are_not_outliers() {
+        let mut speed = linear();
+        speed[1][2] += 0.5;
+        assert_eq!(outlier(&TWA, &TWS, &speed, 1, 2), None);
+
+        // the whole row is shifted : the cell is still on the line along tws
+        let mut speed = linear();
+        speed[1].iter_mut().for_each(|v| *v += 6.0);
+        assert_eq!(outlier(&TWA, &TWS, &speed, 1, 2), None);
+    }
+
+    #[test]
+    fn corner_has_no_line_to_deviate_from() {
+        let mut speed = linear();
+        speed[0][0] += 6.0;
+        assert_eq!(outlier(&TWA, &TWS, &speed, 0, 0), None);
+    }
+
+    #[test]
+    fn repair_replaces_the_outliers_of_both_tacks() {
+        let mut polar = test_polar("outlier", 1, 5, 2);
+        polar.twa = TWA.to_vec();
+        polar.tws = TWS.to_vec();
+        polar.max_speed = 0.0;
+        polar.sail[0].speed = linear();
+        polar.sail[0].speed[2][1] += 6.0;
+        let mut port_speed = linear();
+        port_speed[1][2] -= 6.0;
+        polar.sail[0].port_speed = Some(port_speed);
+
+        let outliers: Vec<Finding> = lint(&polar).findings.into_iter().filter(|f| f.kind == FindingKind::Outlier).collect();
+        assert_eq!(outliers.len(), 1);
+        assert_eq!((outliers[0].twa, outliers[0].tws), (Some(90), Some(10)));
+
+        let applied = repair::repair(&mut polar, &[Fix::InterpolateOutliers]);
+        assert_eq!(applied.len(), 2);
+        assert!((polar.sail[0].speed[2][1] - linear()[2][1]).abs() < 1e-9);
+        assert!(polar.sail[0].port_speed.as_ref().is_some_and(|port_speed| (port_speed[1][2] - linear()[1][2]).abs() < 1e-9));
+    }
+}
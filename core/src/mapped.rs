@@ -0,0 +1,159 @@
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use memmap2::Mmap;
+
+use crate::compute::AngleConvention;
+use crate::export::{MAGIC, PORT_SPEEDS, VERSION};
+use crate::matrix::{self, Extrapolation};
+
+/// Effective polar memory mapped from a file in the layout of `export::binary` : only the grids
+/// are copied, speeds and sails are read from the mapped pages when evaluated.
+#[derive(Debug)]
+pub struct MappedPolar {
+    map: Mmap,
+    twa: Vec<u8>,
+    tws: Vec<u8>,
+    port_speeds: bool,
+}
+
+impl MappedPolar {
+    /// Maps the file at `path`, failing when it isn't a complete binary export.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        // binary files are replaced by renaming new ones, never written in place
+        let map = unsafe { Mmap::map(&file)? };
+
+        if map.len() < HEADER || &map[..4] != MAGIC || u16_at(&map, 4) != VERSION {
+            return Err(anyhow!("{:?} is not a binary polar", path));
+        }
+        let (twa_count, tws_count) = (u16_at(&map, 6) as usize, u16_at(&map, 8) as usize);
+        let cells = twa_count * tws_count;
+        let size = HEADER + 4 * (twa_count + tws_count + cells) + cells;
+        if map.len() < size {
+            return Err(anyhow!("{:?} is truncated : {} bytes instead of {}", path, map.len(), size));
+        }
+
+        let axis = |from: usize, count: usize| (0..count).map(|i| f32_at(&map, HEADER + 4 * (from + i)) as u8).collect();
+        let twa = axis(0, twa_count);
+        let tws = axis(twa_count, tws_count);
+        let port_speeds = u16_at(&map, 10) & PORT_SPEEDS != 0;
+        Ok(MappedPolar { map, twa, tws, port_speeds })
+    }
+
+    pub fn twa(&self) -> &[u8] {
+        &self.twa
+    }
+
+    pub fn tws(&self) -> &[u8] {
+        &self.tws
+    }
+
+    /// Whether the polar has port tack speeds : the export only holds the starboard ones.
+    pub fn has_port_speeds(&self) -> bool {
+        self.port_speeds
+    }
+
+    /// Effective speed and fastest sail of the grid cell `(i, j)`.
+    pub fn cell(&self, i: usize, j: usize) -> (u8, f64) {
+        let index = i * self.tws.len() + j;
+        let speeds = HEADER + 4 * (self.twa.len() + self.tws.len());
+        let sails = speeds + 4 * self.twa.len() * self.tws.len();
+        (self.map[sails + index], f32_at(&self.map, speeds + 4 * index) as f64)
+    }
+
+    /// Effective speed at `(twa, tws)`, twa in either convention, bilinear between the cells of
+    /// the grid and extrapolated as `extrapolation` tells, with the fastest sail of the nearest
+    /// cell. The starboard speeds are used on both tacks, see `has_port_speeds`. `None` without
+    /// grid or off the grid with `Error`.
+    pub fn speed(&self, twa: f64, tws: f64, extrapolation: Extrapolation) -> Option<(u8, f64)> {
+        let twa = AngleConvention::normalize(twa).abs();
+        if self.twa.is_empty() || self.tws.is_empty() {
+            return None;
+        }
+        if extrapolation == Extrapolation::Error && !(matrix::contains(&self.twa, twa) && matrix::contains(&self.tws, tws)) {
+            return None;
+        }
+        let linear = extrapolation == Extrapolation::Linear;
+        let (i, u) = matrix::bracket(&self.twa, twa, linear);
+        let (j, v) = matrix::bracket(&self.tws, tws, linear);
+        let i1 = (i + 1).min(self.twa.len() - 1);
+        let j1 = (j + 1).min(self.tws.len() - 1);
+
+        let speed = |i: usize, j: usize| self.cell(i, j).1;
+        let value = speed(i, j) * (1.0 - u) * (1.0 - v) + speed(i1, j) * u * (1.0 - v)
+            + speed(i, j1) * (1.0 - u) * v + speed(i1, j1) * u * v;
+        let sail = self.cell(if u < 0.5 { i } else { i1 }, if v < 0.5 { j } else { j1 }).0;
+        Some((sail, if linear { value.max(0.0) } else { value }))
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+/// Magic, version, grid sizes and reserved field.
+const HEADER: usize = 12;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::{self, Options};
+    use crate::export;
+    use crate::polar::Polar;
+    use crate::table::Table;
+    use crate::template::test_polar;
+
+    /// Binary export of the default options of `polar`, mapped from a temporary file.
+    fn mapped(polar: &Polar, name: &str) -> MappedPolar {
+        let path = std::env::temp_dir().join(format!("nav-polars-mapped-{}-{}.npol", name, std::process::id()));
+        let bytes = export::binary(polar, &Table::new(polar), &Options::default()).unwrap();
+        std::fs::write(&path, bytes).unwrap();
+        let mapped = MappedPolar::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        mapped
+    }
+
+    #[test]
+    fn speeds_match_the_effective_polar_on_the_grid() {
+        let polar = test_polar("mapped", 3, 10, 4);
+        let mapped = mapped(&polar, "grid");
+        let table = Table::new(&polar);
+        for point in [(40.0, 8.0), (90.0, 20.0), (180.0, 40.0)] {
+            let expected = compute::effective_speeds(&polar, &table, &Options::default(), &[point]).unwrap()[0];
+            let (sail, speed) = mapped.speed(point.0, point.1, Extrapolation::Clamp).unwrap();
+            assert_eq!(sail, expected.0);
+            assert!((speed - expected.1).abs() < 1e-4, "speed at {:?}", point);
+        }
+    }
+
+    #[test]
+    fn twa_are_read_in_either_convention() {
+        let mapped = mapped(&test_polar("mapped", 2, 10, 4), "angles");
+        let starboard = mapped.speed(160.0, 15.0, Extrapolation::Clamp);
+        assert_eq!(mapped.speed(-160.0, 15.0, Extrapolation::Clamp), starboard);
+        assert_eq!(mapped.speed(200.0, 15.0, Extrapolation::Clamp), starboard);
+    }
+
+    #[test]
+    fn error_refuses_points_off_the_grid() {
+        let mapped = mapped(&test_polar("mapped", 2, 10, 4), "error");
+        assert!(mapped.speed(90.0, 40.0, Extrapolation::Error).is_some());
+        assert!(mapped.speed(90.0, 45.0, Extrapolation::Error).is_none());
+        let clamped = mapped.speed(90.0, 45.0, Extrapolation::Clamp).unwrap();
+        assert!(mapped.speed(90.0, 45.0, Extrapolation::Linear).unwrap().1 > clamped.1);
+    }
+
+    #[test]
+    fn port_speeds_are_flagged() {
+        let mut polar = test_polar("mapped", 2, 10, 4);
+        assert!(!mapped(&polar, "starboard").has_port_speeds());
+        polar.sail[1].port_speed = Some(polar.sail[1].speed.clone());
+        assert!(mapped(&polar, "port").has_port_speeds());
+    }
+}
@@ -0,0 +1,437 @@
+#[cfg(feature = "rocket")]
+use rocket::form::FromFormField;
+use serde::{Deserialize, Serialize};
+
+/// Speed matrices are stored row by row : `speed[twa_index][tws_index]`.
+pub type Matrix = Vec<Vec<f64>>;
+
+/// Contiguous copy of a `Matrix`, row major, used by evaluation loops.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Flat {
+    pub(crate) rows: usize,
+    pub(crate) columns: usize,
+    values: Vec<f64>,
+}
+
+impl Flat {
+    /// `rows` x `columns` copy of `matrix`, missing cells being 0.
+    pub(crate) fn new(matrix: &Matrix, rows: usize, columns: usize) -> Self {
+        let mut values = vec![0.0; rows * columns];
+        for (row, values) in matrix.iter().zip(values.chunks_mut(columns.max(1))) {
+            for (v, value) in row.iter().zip(values.iter_mut()) {
+                *value = *v;
+            }
+        }
+        Flat { rows, columns, values }
+    }
+
+    pub(crate) fn get(&self, i: usize, j: usize) -> f64 {
+        if i < self.rows && j < self.columns { self.values[i * self.columns + j] } else { 0.0 }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum Axis {
+    Twa,
+    Tws,
+    #[default]
+    Both,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(tag = "method", rename_all = "camelCase")]
+pub enum Smoothing {
+    /// Centered moving average, `window` cells on each side.
+    MovingAverage { window: usize },
+    /// Discrete smoothing spline (Whittaker smoother) on second differences.
+    /// The higher `lambda`, the smoother the result.
+    Spline { lambda: f64 },
+}
+
+impl Smoothing {
+    fn apply(&self, values: &[f64]) -> Vec<f64> {
+        match self {
+            Smoothing::MovingAverage { window } => moving_average(values, *window),
+            Smoothing::Spline { lambda } => whittaker(values, *lambda),
+        }
+    }
+}
+
+pub fn smooth(matrix: &Matrix, smoothing: &Smoothing, axis: Axis) -> Matrix {
+    match axis {
+        Axis::Twa => smooth_columns(matrix, smoothing),
+        Axis::Tws => smooth_rows(matrix, smoothing),
+        Axis::Both => smooth_rows(&smooth_columns(matrix, smoothing), smoothing),
+    }
+}
+
+fn smooth_rows(matrix: &Matrix, smoothing: &Smoothing) -> Matrix {
+    matrix.iter().map(|row| smoothing.apply(row)).collect()
+}
+
+fn smooth_columns(matrix: &Matrix, smoothing: &Smoothing) -> Matrix {
+    let mut res = matrix.clone();
+    let width = matrix.iter().map(|row| row.len()).min().unwrap_or_default();
+    for j in 0..width {
+        let column: Vec<f64> = matrix.iter().map(|row| row[j]).collect();
+        for (i, v) in smoothing.apply(&column).into_iter().enumerate() {
+            res[i][j] = v;
+        }
+    }
+    res
+}
+
+fn moving_average(values: &[f64], window: usize) -> Vec<f64> {
+    // a wider window than the values averages them all anyway
+    let window = window.min(values.len());
+    (0..values.len())
+        .map(|i| {
+            let from = i.saturating_sub(window);
+            let to = (i + window + 1).min(values.len());
+            values[from..to].iter().sum::<f64>() / (to - from) as f64
+        })
+        .collect()
+}
+
+/// Solves `(I + lambda * D'D) z = y` where `D` is the second order difference operator.
+fn whittaker(values: &[f64], lambda: f64) -> Vec<f64> {
+    let n = values.len();
+    if n < 3 || lambda <= 0.0 {
+        return values.to_vec();
+    }
+
+    let mut a = vec![vec![0.0; n]; n];
+    for (i, row) in a.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    let d = [1.0, -2.0, 1.0];
+    for k in 0..n - 2 {
+        for (p, dp) in d.iter().enumerate() {
+            for (q, dq) in d.iter().enumerate() {
+                a[k + p][k + q] += lambda * dp * dq;
+            }
+        }
+    }
+
+    solve(a, values.to_vec())
+}
+
+/// Gaussian elimination. `a` is symmetric positive definite here so no pivoting is needed.
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Vec<f64> {
+    let n = b.len();
+    for k in 0..n {
+        let (top, bottom) = a.split_at_mut(k + 1);
+        let pivot = &top[k];
+        for (i, row) in bottom.iter_mut().enumerate() {
+            let f = row[k] / pivot[k];
+            if f != 0.0 {
+                for (x, p) in row[k..].iter_mut().zip(&pivot[k..]) {
+                    *x -= f * p;
+                }
+                b[k + 1 + i] -= f * b[k];
+            }
+        }
+    }
+    let mut x = vec![0.0; n];
+    for i in (0..n).rev() {
+        let s: f64 = (i + 1..n).map(|j| a[i][j] * x[j]).sum();
+        x[i] = (b[i] - s) / a[i][i];
+    }
+    x
+}
+
+/// Behavior of evaluations outside of the grid.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "rocket", derive(FromFormField))]
+#[serde(rename_all = "camelCase")]
+pub enum Extrapolation {
+    /// use the value at the edge of the grid
+    #[default]
+    Clamp,
+    /// extend the first or last segment of the grid
+    Linear,
+    /// refuse to evaluate
+    Error,
+}
+
+/// Index of the lower bound and interpolation fraction of `x` on `axis`.
+/// Values outside the axis are clamped to its edges, unless `linear`.
+pub(crate) fn bracket(axis: &[u8], x: f64, linear: bool) -> (usize, f64) {
+    if axis.len() < 2 {
+        return (0, 0.0);
+    }
+    let fraction = |i: usize| {
+        let (a, b) = (axis[i] as f64, axis[i + 1] as f64);
+        if b > a { (x - a) / (b - a) } else { 0.0 }
+    };
+    if x <= axis[0] as f64 {
+        return (0, if linear { fraction(0) } else { 0.0 });
+    }
+    for i in 0..axis.len() - 1 {
+        if x <= axis[i + 1] as f64 {
+            return (i, fraction(i));
+        }
+    }
+    let last = axis.len() - 2;
+    (last, if linear { fraction(last) } else { 1.0 })
+}
+
+/// Whether `x` is within the bounds of `axis`.
+pub fn contains(axis: &[u8], x: f64) -> bool {
+    match (axis.first(), axis.last()) {
+        (Some(first), Some(last)) => x >= *first as f64 && x <= *last as f64,
+        _ => false,
+    }
+}
+
+/// Bilinear interpolation of `matrix` at `(twa, tws)`, clamped to the grid.
+pub fn bilinear(twa_axis: &[u8], tws_axis: &[u8], matrix: &Matrix, twa: f64, tws: f64) -> f64 {
+    interpolate(twa_axis, tws_axis, matrix, twa, tws, Extrapolation::Clamp)
+}
+
+/// Bilinear interpolation of `matrix` at `(twa, tws)`. Out of grid values are clamped unless
+/// `extrapolation` is `Linear`, in which case the result is never negative.
+pub fn interpolate(twa_axis: &[u8], tws_axis: &[u8], matrix: &Matrix, twa: f64, tws: f64, extrapolation: Extrapolation) -> f64 {
+    let linear = extrapolation == Extrapolation::Linear;
+    let value = |i: usize, j: usize| matrix.get(i).and_then(|row| row.get(j)).copied().unwrap_or_default();
+    let (i, u) = bracket(twa_axis, twa, linear);
+    let (j, v) = bracket(tws_axis, tws, linear);
+    let (i1, j1) = ((i + 1).min(twa_axis.len().saturating_sub(1)), (j + 1).min(tws_axis.len().saturating_sub(1)));
+
+    let low = value(i, j) * (1.0 - v) + value(i, j1) * v;
+    let high = value(i1, j) * (1.0 - v) + value(i1, j1) * v;
+    let res = low * (1.0 - u) + high * u;
+    if linear { res.max(0.0) } else { res }
+}
+
+/// Interpolation scheme of evaluations between the cells of the grid.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "rocket", derive(FromFormField))]
+#[serde(rename_all = "camelCase")]
+pub enum Interpolation {
+    /// linear along each axis, as the game engine does : the speeds it computes
+    #[default]
+    Bilinear,
+    /// cubic Hermite spline with finite difference tangents (Catmull-Rom)
+    Cubic,
+    /// Akima spline, cubic without the overshoots around abrupt changes of slope
+    Akima,
+}
+
+/// Interpolation of `matrix` at `(twa, tws)` with `method`, along tws then twa for the splines.
+/// Out of grid values extend the edge segments linearly when `extrapolation` is `Linear` and are
+/// clamped otherwise. Spline results are never negative, they may overshoot near zero speeds.
+pub fn interpolate_with(twa_axis: &[u8], tws_axis: &[u8], matrix: &Matrix, twa: f64, tws: f64, extrapolation: Extrapolation, method: Interpolation) -> f64 {
+    if method == Interpolation::Bilinear {
+        return interpolate(twa_axis, tws_axis, matrix, twa, tws, extrapolation);
+    }
+    let linear = extrapolation == Extrapolation::Linear;
+    let value = |i: usize, j: usize| matrix.get(i).and_then(|row| row.get(j)).copied().unwrap_or_default();
+    let column: Vec<f64> = (0..twa_axis.len())
+        .map(|i| {
+            let row: Vec<f64> = (0..tws_axis.len()).map(|j| value(i, j)).collect();
+            spline(tws_axis, &row, tws, linear, method)
+        })
+        .collect();
+    spline(twa_axis, &column, twa, linear, method).max(0.0)
+}
+
+/// Spline through `values` on `axis` at `x`, linear beyond the edges as `bracket` is.
+fn spline(axis: &[u8], values: &[f64], x: f64, linear: bool, method: Interpolation) -> f64 {
+    match values.len() {
+        0 => return 0.0,
+        1 => return values[0],
+        _ => {}
+    }
+    let (k, t) = bracket(axis, x, linear);
+    if !(0.0..=1.0).contains(&t) {
+        return values[k] + (values[k + 1] - values[k]) * t;
+    }
+    let tangents = tangents(axis, values, method);
+    let h = axis[k + 1] as f64 - axis[k] as f64;
+    let (t2, t3) = (t * t, t * t * t);
+    (2.0 * t3 - 3.0 * t2 + 1.0) * values[k]
+        + (t3 - 2.0 * t2 + t) * h * tangents[k]
+        + (-2.0 * t3 + 3.0 * t2) * values[k + 1]
+        + (t3 - t2) * h * tangents[k + 1]
+}
+
+/// Tangents of the spline at each point of `axis`.
+fn tangents(axis: &[u8], values: &[f64], method: Interpolation) -> Vec<f64> {
+    let n = values.len();
+    let secant = |i: usize| {
+        let h = axis[i + 1] as f64 - axis[i] as f64;
+        if h > 0.0 { (values[i + 1] - values[i]) / h } else { 0.0 }
+    };
+    let secants: Vec<f64> = (0..n - 1).map(secant).collect();
+
+    match method {
+        Interpolation::Akima => {
+            // two secants extrapolated on each side so that the edge points have four neighbours
+            let first = secants[0];
+            let second = secants.get(1).copied().unwrap_or(first);
+            let last = secants[n - 2];
+            let before_last = if n > 2 { secants[n - 3] } else { last };
+            let mut m = vec![3.0 * first - 2.0 * second, 2.0 * first - second];
+            m.extend(&secants);
+            m.extend([2.0 * last - before_last, 3.0 * last - 2.0 * before_last]);
+            (0..n)
+                .map(|i| {
+                    let (w1, w2) = ((m[i + 3] - m[i + 2]).abs(), (m[i + 1] - m[i]).abs());
+                    if w1 + w2 > 0.0 {
+                        (w1 * m[i + 1] + w2 * m[i + 2]) / (w1 + w2)
+                    } else {
+                        (m[i + 1] + m[i + 2]) / 2.0
+                    }
+                })
+                .collect()
+        }
+        _ => (0..n)
+            .map(|i| match i {
+                0 => secants[0],
+                i if i == n - 1 => secants[n - 2],
+                i => {
+                    let h = axis[i + 1] as f64 - axis[i - 1] as f64;
+                    if h > 0.0 { (values[i + 1] - values[i - 1]) / h } else { 0.0 }
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Resamples `matrix` from its axes onto `new_twa` x `new_tws`.
+pub fn regrid(twa_axis: &[u8], tws_axis: &[u8], matrix: &Matrix, new_twa: &[u8], new_tws: &[u8]) -> Matrix {
+    new_twa.iter()
+        .map(|twa| new_tws.iter().map(|tws| bilinear(twa_axis, tws_axis, matrix, *twa as f64, *tws as f64)).collect())
+        .collect()
+}
+
+/// Sorted union of two axes.
+pub fn union(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut res: Vec<u8> = a.iter().chain(b).copied().collect();
+    res.sort_unstable();
+    res.dedup();
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(values: &[f64], expected: &[f64]) {
+        assert_eq!(values.len(), expected.len());
+        for (v, e) in values.iter().zip(expected) {
+            assert!((v - e).abs() < 1e-9, "{:?} != {:?}", values, expected);
+        }
+    }
+
+    #[test]
+    fn moving_average_spreads_a_spike() {
+        let smoothed = moving_average(&[0.0, 0.0, 3.0, 0.0, 0.0], 1);
+        assert_close(&smoothed, &[0.0, 1.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn moving_average_window_is_clamped_to_the_values() {
+        let values = [1.0, 2.0, 6.0];
+        assert_close(&moving_average(&values, usize::MAX), &[3.0, 3.0, 3.0]);
+        assert_close(&moving_average(&values, 0), &values);
+        assert!(moving_average(&[], 2).is_empty());
+    }
+
+    #[test]
+    fn spline_keeps_linear_speeds() {
+        let values: Vec<f64> = (0..8).map(|i| 2.0 + 0.5 * i as f64).collect();
+        assert_close(&whittaker(&values, 10.0), &values);
+    }
+
+    #[test]
+    fn spline_flattens_a_spike_and_keeps_the_sum() {
+        let values = [1.0, 1.0, 1.0, 5.0, 1.0, 1.0, 1.0];
+        let smoothed = whittaker(&values, 2.0);
+        assert!(smoothed[3] < 5.0 && smoothed[3] > 1.0);
+        assert!((smoothed.iter().sum::<f64>() - values.iter().sum::<f64>()).abs() < 1e-9);
+    }
+
+    const TWA: [u8; 2] = [40, 60];
+    const TWS: [u8; 2] = [10, 20];
+
+    fn square() -> Matrix {
+        vec![vec![1.0, 2.0], vec![3.0, 4.0]]
+    }
+
+    #[test]
+    fn clamp_uses_the_edges_of_the_grid() {
+        assert_eq!(interpolate(&TWA, &TWS, &square(), 50.0, 15.0, Extrapolation::Clamp), 2.5);
+        assert_eq!(interpolate(&TWA, &TWS, &square(), 50.0, 30.0, Extrapolation::Clamp), 3.0);
+        assert_eq!(interpolate(&TWA, &TWS, &square(), 20.0, 0.0, Extrapolation::Clamp), 1.0);
+    }
+
+    #[test]
+    fn linear_extends_the_edge_segments_above_zero() {
+        assert_eq!(interpolate(&TWA, &TWS, &square(), 50.0, 15.0, Extrapolation::Linear), 2.5);
+        assert_eq!(interpolate(&TWA, &TWS, &square(), 50.0, 30.0, Extrapolation::Linear), 4.0);
+        assert_eq!(interpolate(&TWA, &TWS, &square(), 20.0, 0.0, Extrapolation::Linear), 0.0);
+    }
+
+    #[test]
+    fn contains_includes_the_bounds() {
+        assert!(contains(&TWS, 10.0) && contains(&TWS, 20.0));
+        assert!(!contains(&TWS, 9.9) && !contains(&TWS, 20.1));
+        assert!(!contains(&[], 0.0));
+    }
+
+    #[test]
+    fn twa_axis_smooths_the_columns_only() {
+        let matrix = vec![vec![0.0, 0.0], vec![3.0, 9.0], vec![0.0, 0.0]];
+        let smoothed = smooth(&matrix, &Smoothing::MovingAverage { window: 1 }, Axis::Twa);
+        assert_eq!(smoothed, vec![vec![1.5, 4.5], vec![1.0, 3.0], vec![1.5, 4.5]]);
+        let smoothed = smooth(&matrix, &Smoothing::MovingAverage { window: 1 }, Axis::Tws);
+        assert_eq!(smoothed, vec![vec![0.0, 0.0], vec![6.0, 6.0], vec![0.0, 0.0]]);
+    }
+
+    const AXIS: [u8; 6] = [0, 10, 20, 30, 40, 50];
+
+    /// Single row matrix of `values` along tws, evaluated at `tws`.
+    fn along_tws(values: &[f64], tws: f64, extrapolation: Extrapolation, method: Interpolation) -> f64 {
+        interpolate_with(&[90], &AXIS, &vec![values.to_vec()], 90.0, tws, extrapolation, method)
+    }
+
+    #[test]
+    fn splines_go_through_the_grid() {
+        let values = [0.0, 4.0, 9.0, 11.0, 12.0, 12.5];
+        for method in [Interpolation::Bilinear, Interpolation::Cubic, Interpolation::Akima] {
+            for (tws, value) in AXIS.iter().zip(values) {
+                assert!((along_tws(&values, *tws as f64, Extrapolation::Clamp, method) - value).abs() < 1e-9, "{:?} at {}", method, tws);
+            }
+        }
+    }
+
+    #[test]
+    fn splines_keep_linear_speeds() {
+        let values: Vec<f64> = AXIS.iter().map(|tws| 2.0 + 0.3 * *tws as f64).collect();
+        for method in [Interpolation::Cubic, Interpolation::Akima] {
+            for tws in [3.0, 15.5, 27.0, 44.0] {
+                assert!((along_tws(&values, tws, Extrapolation::Clamp, method) - (2.0 + 0.3 * tws)).abs() < 1e-9, "{:?} at {}", method, tws);
+            }
+        }
+    }
+
+    #[test]
+    fn akima_does_not_overshoot_steps() {
+        let values = [0.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+        assert!(along_tws(&values, 35.0, Extrapolation::Clamp, Interpolation::Cubic) > 1.0);
+        assert!((along_tws(&values, 35.0, Extrapolation::Clamp, Interpolation::Akima) - 1.0).abs() < 1e-9);
+        assert!(along_tws(&values, 15.0, Extrapolation::Clamp, Interpolation::Akima).abs() < 1e-9);
+    }
+
+    #[test]
+    fn splines_extrapolate_the_edge_segments() {
+        let values = [0.0, 4.0, 9.0, 11.0, 12.0, 12.5];
+        for method in [Interpolation::Cubic, Interpolation::Akima] {
+            assert!((along_tws(&values, 60.0, Extrapolation::Clamp, method) - 12.5).abs() < 1e-9);
+            assert!((along_tws(&values, 60.0, Extrapolation::Linear, method) - 13.0).abs() < 1e-9);
+        }
+    }
+}
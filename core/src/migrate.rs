@@ -0,0 +1,115 @@
+use serde_json::Value;
+
+/// Layout version of the polars written by this version, stored as `schemaVersion`.
+/// Files without it are version 0.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Upgrades from the version of their index to the next one.
+const MIGRATIONS: [fn(&mut Value); SCHEMA_VERSION as usize] = [camel_case_keys];
+
+/// Version of the polar file content `value`.
+pub fn version(value: &Value) -> u32 {
+    value.get("schemaVersion").and_then(Value::as_u64).map_or(0, |v| v as u32)
+}
+
+/// Upgrades the polar file content `value` to `SCHEMA_VERSION`, returns whether it changed.
+/// Contents from a newer version are left as they are.
+pub fn migrate(value: &mut Value) -> bool {
+    let from = version(value);
+    if from >= SCHEMA_VERSION || !value.is_object() {
+        return false;
+    }
+    for migration in &MIGRATIONS[from as usize..] {
+        migration(value);
+    }
+    if let Some(map) = value.as_object_mut() {
+        map.insert("schemaVersion".to_string(), Value::from(SCHEMA_VERSION));
+    }
+    true
+}
+
+/// 0 to 1 : snake_case keys, and `timerSec` of the winch penalties, are renamed to the
+/// camelCase fields of the model. The keys of `labels` are languages and kept.
+fn camel_case_keys(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            let keys: Vec<String> = map.keys().filter(|k| (k.contains('_') && k.as_str() != "_id") || k.as_str() == "timerSec").cloned().collect();
+            for key in keys {
+                let renamed = match key.as_str() {
+                    "polar_id" => "_id".to_string(),
+                    "timerSec" | "timer_sec" => "timer".to_string(),
+                    _ => camel_case(&key),
+                };
+                if let Some(v) = map.remove(&key) {
+                    map.entry(renamed).or_insert(v);
+                }
+            }
+            for (key, v) in map.iter_mut() {
+                if key != "labels" {
+                    camel_case_keys(v);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(camel_case_keys),
+        _ => {}
+    }
+}
+
+fn camel_case(key: &str) -> String {
+    let mut parts = key.split('_').filter(|p| !p.is_empty());
+    let mut camel = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            camel.extend(first.to_uppercase());
+            camel.push_str(chars.as_str());
+        }
+    }
+    camel
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn renames_snake_case_keys() {
+        let mut value = json!({
+            "polar_id": 3,
+            "label": "Imoca",
+            "labels": { "en_GB": "Imoca" },
+            "global_speed_ratio": 1.0,
+            "winch": { "tack": { "std": { "lw": { "ratio": 0.5, "timerSec": 60 }, "hw": { "ratio": 0.5, "timer_sec": 40 } } } },
+            "sail": [{ "id": 1, "port_speed": [[1.0]] }],
+        });
+        assert!(migrate(&mut value));
+        assert_eq!(value, json!({
+            "schemaVersion": SCHEMA_VERSION,
+            "_id": 3,
+            "label": "Imoca",
+            "labels": { "en_GB": "Imoca" },
+            "globalSpeedRatio": 1.0,
+            "winch": { "tack": { "std": { "lw": { "ratio": 0.5, "timer": 60 }, "hw": { "ratio": 0.5, "timer": 40 } } } },
+            "sail": [{ "id": 1, "portSpeed": [[1.0]] }],
+        }));
+    }
+
+    #[test]
+    fn keeps_the_camel_case_key_of_both() {
+        let mut value = json!({ "maxSpeed": 30.0, "max_speed": 25.0 });
+        migrate(&mut value);
+        assert_eq!(value, json!({ "schemaVersion": SCHEMA_VERSION, "maxSpeed": 30.0 }));
+    }
+
+    #[test]
+    fn leaves_current_and_newer_versions() {
+        for version in [SCHEMA_VERSION, SCHEMA_VERSION + 1] {
+            let mut value = json!({ "schemaVersion": version, "max_speed": 25.0 });
+            let expected = value.clone();
+            assert!(!migrate(&mut value));
+            assert_eq!(value, expected);
+        }
+    }
+}
@@ -0,0 +1,323 @@
+#[cfg(feature = "rocket")]
+use rocket::form::FromFormField;
+use serde::{Deserialize, Serialize};
+
+use crate::polar::{Penalty, PenaltyBoundaries, PenaltyCase, Polar, Winch};
+use crate::stats::{self, Vmg};
+
+/// Winch pack of the boat.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "rocket", derive(FromFormField))]
+#[serde(rename_all = "camelCase")]
+pub enum WinchKind {
+    #[default]
+    Std,
+    Pro,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rocket", derive(FromFormField))]
+#[serde(rename_all = "camelCase")]
+pub enum Maneuver {
+    Tack,
+    Gybe,
+    #[cfg_attr(feature = "rocket", field(value = "sail_change"))]
+    SailChange,
+}
+
+impl Maneuver {
+    /// Maneuver needed to go from `twa_from` to `twa_to` : crossing the wind upwind is a tack,
+    /// downwind a gybe, staying on the same tack a sail change.
+    pub fn between(twa_from: f64, twa_to: f64) -> Self {
+        if twa_from.signum() == twa_to.signum() {
+            Maneuver::SailChange
+        } else if twa_from.abs() + twa_to.abs() <= 180.0 {
+            Maneuver::Tack
+        } else {
+            Maneuver::Gybe
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Penalties {
+    pub winch: WinchKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tws: Option<f64>,
+    /// how the timers and ratios were interpolated between the wind boundaries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formula: Option<String>,
+    pub tack: ManeuverPenalty,
+    pub gybe: ManeuverPenalty,
+    pub sail_change: ManeuverPenalty,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ManeuverPenalty {
+    pub timer_sec: u16,
+    pub ratio: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boundaries: Option<PenaltyBoundaries>,
+}
+
+impl PenaltyCase {
+    /// Timer, ratio and boundaries of this maneuver for the `winch` pack.
+    pub fn select(&self, winch: WinchKind) -> ManeuverPenalty {
+        match winch {
+            WinchKind::Std => ManeuverPenalty { timer_sec: self.std_timer_sec, ratio: self.std_ratio, boundaries: self.std.clone() },
+            WinchKind::Pro => ManeuverPenalty { timer_sec: self.pro_timer_sec, ratio: self.pro_ratio, boundaries: self.pro.clone() },
+        }
+    }
+}
+
+impl ManeuverPenalty {
+    /// Replaces the timer and ratio by the boundaries interpolated at `tws`, when there are some.
+    fn interpolate(&mut self, lws: f64, hws: f64, tws: f64) -> bool {
+        let Some(boundaries) = &self.boundaries else { return false };
+        let penalty = boundaries.at(lws, hws, tws);
+        self.timer_sec = penalty.timer;
+        self.ratio = penalty.ratio;
+        true
+    }
+}
+
+impl PenaltyBoundaries {
+    /// Penalty at `tws` : the low wind values up to `lws`, the high wind ones from `hws`,
+    /// linearly blended in between.
+    pub fn at(&self, lws: f64, hws: f64, tws: f64) -> Penalty {
+        let k = if hws <= lws {
+            if tws < hws { 0.0 } else { 1.0 }
+        } else {
+            ((tws - lws) / (hws - lws)).clamp(0.0, 1.0)
+        };
+        let timer = self.lw.timer as f64 + (self.hw.timer as f64 - self.lw.timer as f64) * k;
+        Penalty {
+            ratio: self.lw.ratio + (self.hw.ratio - self.lw.ratio) * k,
+            timer: timer.round() as u16,
+        }
+    }
+}
+
+/// Maneuver penalties of `winch` for the `kind` pack, interpolated at `tws` when given
+/// and the polar has wind boundaries.
+pub fn penalties(winch: &Winch, kind: WinchKind, tws: Option<f64>) -> Penalties {
+    let mut penalties = Penalties {
+        winch: kind,
+        tws,
+        formula: None,
+        tack: winch.tack.select(kind),
+        gybe: winch.gybe.select(kind),
+        sail_change: winch.sail_change.select(kind),
+    };
+
+    if let (Some(tws), Some(lws), Some(hws)) = (tws, winch.lws, winch.hws) {
+        let (lws, hws) = (lws as f64, hws as f64);
+        let mut interpolated = false;
+        for penalty in [&mut penalties.tack, &mut penalties.gybe, &mut penalties.sail_change] {
+            interpolated |= penalty.interpolate(lws, hws, tws);
+        }
+        if interpolated {
+            penalties.formula = Some(format!(
+                "k = clamp((tws - lws) / (hws - lws), 0, 1) with lws = {} and hws = {}; value = lw + (hw - lw) * k, timer rounded to the second",
+                lws, hws));
+        }
+    }
+
+    penalties
+}
+
+impl Penalties {
+    pub fn of(&self, maneuver: Maneuver) -> &ManeuverPenalty {
+        match maneuver {
+            Maneuver::Tack => &self.tack,
+            Maneuver::Gybe => &self.gybe,
+            Maneuver::SailChange => &self.sail_change,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Timeline {
+    pub kind: Maneuver,
+    pub winch: WinchKind,
+    pub tws: f64,
+    pub twa_from: f64,
+    pub twa_to: f64,
+    pub timer_sec: u16,
+    pub ratio: f64,
+    /// boat speed before the maneuver
+    pub speed_from: f64,
+    /// boat speed once the maneuver is over
+    pub speed_to: f64,
+    /// boat speed during each second of the maneuver
+    pub profile: Vec<f64>,
+    /// distance lost compared to sailing at `speedTo` without penalty, in nautical miles
+    pub distance_lost: f64,
+}
+
+/// Speed profile of a `kind` maneuver from `twa_from` to `twa_to` at `tws` : the new heading
+/// speed is reduced by the penalty ratio for the whole timer. `None` when the polar has no winch.
+pub fn timeline(polar: &Polar, kind: Maneuver, winch: WinchKind, tws: f64, twa_from: f64, twa_to: f64) -> Option<Timeline> {
+    let penalties = penalties(polar.winch.as_ref()?, winch, Some(tws));
+    let penalty = penalties.of(kind);
+
+    let speed_from = polar.speed(twa_from, tws);
+    let speed_to = polar.speed(twa_to, tws);
+    let profile = vec![speed_to * penalty.ratio; penalty.timer_sec as usize];
+    let distance_lost = (1.0 - penalty.ratio) * speed_to * penalty.timer_sec as f64 / 3600.0;
+
+    Some(Timeline {
+        kind,
+        winch,
+        tws,
+        twa_from,
+        twa_to,
+        timer_sec: penalty.timer_sec,
+        ratio: penalty.ratio,
+        speed_from,
+        speed_to,
+        profile,
+        distance_lost,
+    })
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ManeuverTable {
+    pub winch: WinchKind,
+    pub rows: Vec<ManeuverAngles>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ManeuverAngles {
+    pub tws: u8,
+    pub tack: ManeuverCost,
+    pub gybe: ManeuverCost,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ManeuverCost {
+    /// angle turned through between the two optimal vmg headings
+    pub angle: f64,
+    #[serde(flatten)]
+    pub vmg: Vmg,
+    pub timer_sec: u16,
+    pub ratio: f64,
+    /// distance lost during the maneuver, in nautical miles
+    pub distance_lost: f64,
+    /// time needed to sail the lost distance
+    pub time_lost_sec: f64,
+}
+
+impl ManeuverCost {
+    fn new(polar: &Polar, kind: Maneuver, winch: WinchKind, tws: f64, vmg: Vmg) -> Option<Self> {
+        let timeline = timeline(polar, kind, winch, tws, vmg.twa, -vmg.twa)?;
+        let time_lost_sec = if vmg.speed > 0.0 { timeline.distance_lost / vmg.speed * 3600.0 } else { 0.0 };
+        Some(ManeuverCost {
+            angle: match kind {
+                Maneuver::Gybe => 2.0 * (180.0 - vmg.twa),
+                _ => 2.0 * vmg.twa,
+            },
+            vmg,
+            timer_sec: timeline.timer_sec,
+            ratio: timeline.ratio,
+            distance_lost: timeline.distance_lost,
+            time_lost_sec,
+        })
+    }
+}
+
+/// Optimal tack and gybe angles for each tws of the grid, with the cost of the maneuver.
+pub fn maneuver_table(polar: &Polar, winch: WinchKind) -> ManeuverTable {
+    let rows = polar.tws.iter()
+        .filter_map(|tws| {
+            let (upwind, downwind) = stats::vmg(polar, *tws as f64)?;
+            Some(ManeuverAngles {
+                tws: *tws,
+                tack: ManeuverCost::new(polar, Maneuver::Tack, winch, *tws as f64, upwind)?,
+                gybe: ManeuverCost::new(polar, Maneuver::Gybe, winch, *tws as f64, downwind)?,
+            })
+        })
+        .collect();
+
+    ManeuverTable { winch, rows }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::test_polar;
+
+    fn boundaries() -> PenaltyBoundaries {
+        PenaltyBoundaries { lw: Penalty { ratio: 0.5, timer: 60 }, hw: Penalty { ratio: 0.8, timer: 31 } }
+    }
+
+    /// Scaffold with std tack boundaries blended between 10 and 20 knots.
+    fn polar() -> Polar {
+        let mut polar = test_polar("penalty", 2, 5, 2);
+        if let Some(winch) = polar.winch.as_mut() {
+            winch.tack.std = Some(boundaries());
+            winch.lws = Some(10);
+            winch.hws = Some(20);
+        }
+        polar
+    }
+
+    #[test]
+    fn boundaries_blend_between_the_wind_speeds() {
+        let boundaries = boundaries();
+        assert_eq!(boundaries.at(10.0, 20.0, 5.0).timer, 60);
+        assert_eq!(boundaries.at(10.0, 20.0, 25.0).timer, 31);
+        let middle = boundaries.at(10.0, 20.0, 15.0);
+        assert_eq!(middle.timer, 46);
+        assert!((middle.ratio - 0.65).abs() < 1e-9);
+        // without a blending band the high wind values apply from hws
+        assert_eq!(boundaries.at(20.0, 20.0, 19.0).timer, 60);
+        assert_eq!(boundaries.at(20.0, 20.0, 20.0).timer, 31);
+    }
+
+    #[test]
+    fn penalties_are_interpolated_at_the_wind_speed() {
+        let polar = polar();
+        let winch = polar.winch.as_ref().unwrap();
+        let blended = penalties(winch, WinchKind::Std, Some(15.0));
+        assert_eq!(blended.tack.timer_sec, 46);
+        assert!(blended.formula.is_some());
+
+        let plain = penalties(winch, WinchKind::Std, None);
+        assert_eq!(plain.tack.timer_sec, winch.tack.std_timer_sec);
+        assert!(plain.formula.is_none());
+    }
+
+    #[test]
+    fn maneuvers_between_headings() {
+        assert_eq!(Maneuver::between(45.0, -45.0), Maneuver::Tack);
+        assert_eq!(Maneuver::between(-150.0, 150.0), Maneuver::Gybe);
+        assert_eq!(Maneuver::between(60.0, 120.0), Maneuver::SailChange);
+        assert_eq!(Maneuver::between(-60.0, -120.0), Maneuver::SailChange);
+    }
+
+    #[test]
+    fn timeline_applies_the_penalty_for_the_whole_timer() {
+        let polar = polar();
+        let timeline = timeline(&polar, Maneuver::Tack, WinchKind::Std, 15.0, 45.0, -45.0).unwrap();
+        assert_eq!(timeline.timer_sec, 46);
+        assert!((timeline.ratio - 0.65).abs() < 1e-9);
+        assert!((timeline.speed_to - polar.speed(-45.0, 15.0)).abs() < 1e-9);
+        assert_eq!(timeline.profile.len(), 46);
+        assert!(timeline.profile.iter().all(|speed| (speed - timeline.speed_to * 0.65).abs() < 1e-9));
+        assert!((timeline.distance_lost - 0.35 * timeline.speed_to * 46.0 / 3600.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn timeline_needs_a_winch() {
+        let mut polar = polar();
+        polar.winch = None;
+        assert!(timeline(&polar, Maneuver::Gybe, WinchKind::Pro, 15.0, 150.0, -150.0).is_none());
+    }
+}
@@ -0,0 +1,781 @@
+#[cfg(feature = "rocket")]
+use rocket::form::FromFormField;
+use serde::{Deserialize, Serialize};
+use serde::de::{self, Deserializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use log::warn;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::matrix::{self, Extrapolation, Interpolation, Matrix};
+use crate::units;
+use crate::validate::ValidationError;
+
+#[derive(Error, Debug)]
+pub enum PolarError {
+    #[error("Polar {0} already exists.")]
+    AlreadyExists(String),
+    #[error("Polar {0} does not exist.")]
+    NotFound(String),
+    #[error("Signature of polar {0} is not valid for any trusted key.")]
+    InvalidSignature(String),
+    #[error("Id is mandatory")]
+    IdIsMandatory(),
+    #[error("Every _id is used or reserved")]
+    NoFreeId(),
+    #[error("_id {0} is used or reserved")]
+    PolarIdTaken(u8),
+    #[error("No sail id left for {0} more sails")]
+    NoFreeSailId(usize),
+    #[error("Invalid polar : {}", .0.iter().map(|e| format!("{} {}", e.pointer, e.message)).collect::<Vec<_>>().join(", "))]
+    Invalid(Vec<ValidationError>),
+    #[error("Still in use : {0}")]
+    InUse(String),
+    #[error("Invalid parent polar : {0}")]
+    InvalidParent(String),
+}
+
+/// Paths of the fields of `value` unknown to `Polar`, e.g. `foil.speedRatoi`.
+pub fn unknown_fields(value: &Value) -> Vec<String> {
+    let mut unknown = Vec::new();
+    let _: Option<Polar> = serde_ignored::deserialize(value, |path| unknown.push(path.to_string())).ok();
+    unknown
+}
+
+/// Address of the sail matrix `matrix` in the matrix store : the hex SHA-256 of its canonical YAML.
+pub fn matrix_hash(matrix: &Value) -> Result<String> {
+    Ok(hex::encode(Sha256::digest(canonical_yaml(matrix)?.as_bytes())))
+}
+
+/// Hex FNV-1a hash of `bytes`.
+pub fn content_hash(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3));
+    format!("{:016x}", hash)
+}
+
+/// Canonical YAML of the polar file content `value` : keys sorted at every level, no negative
+/// zeros and a trailing newline, so that saving an unchanged polar gives the same bytes.
+pub fn canonical_yaml(value: &Value) -> Result<String> {
+    let mut yaml = serde_yaml::to_string(&canonical(value.clone()))?;
+    if !yaml.ends_with('\n') {
+        yaml.push('\n');
+    }
+    Ok(yaml)
+}
+
+fn canonical(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(entries.into_iter().map(|(key, value)| (key, canonical(value))).collect())
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(canonical).collect()),
+        Value::Number(n) if n.is_f64() && n.as_f64() == Some(0.0) => Value::from(0.0),
+        value => value,
+    }
+}
+
+/// URL safe version of `label` : lowercase ascii letters and digits separated by dashes.
+pub fn slug(label: &str) -> String {
+    label.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Fields of a polar left out of its fingerprint.
+const COSMETIC_FIELDS: [&str; 12] = ["schemaVersion", "id", "extends", "_id", "verified", "file", "label", "labels", "class", "tags", "default", "winchProfile"];
+
+/// Hex digits of a fingerprint.
+const FINGERPRINT_LENGTH: usize = 12;
+
+/// Scope where a polar is the default one.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rocket", derive(FromFormField))]
+#[serde(rename_all = "camelCase")]
+pub enum DefaultScope {
+    Global,
+    /// default of the polars sharing its class
+    Class,
+}
+
+/// What to do on save with matrix values exceeding `max_speed`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum MaxSpeedPolicy {
+    #[default]
+    Ignore,
+    Reject,
+    Clamp,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Polar {
+    /// layout version of the file, see `migrate`
+    #[serde(default)]
+    pub schema_version: u32,
+    pub id: Option<String>,
+    /// id of the polar this one derives from, only the overridden fields are stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    #[serde(rename = "_id", alias = "polar_id")]
+    pub polar_id: u8,
+    #[serde(default, skip_serializing)]
+    pub archived: bool,
+    /// last modification of the file, in seconds since epoch
+    #[serde(skip)]
+    pub modified: Option<u64>,
+    /// whether the file is signed with a trusted key, unknown when no key is trusted
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub verified: Option<bool>,
+    /// file the polar was listed from
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub file: Option<FileMeta>,
+    /// file the polar was read from, its evaluation table is cached by file
+    #[serde(skip)]
+    pub(crate) source: Option<PathBuf>,
+    pub label: String,
+    /// translations of `label` by language
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<DefaultScope>,
+    #[serde(alias = "global_speed_ratio")]
+    pub global_speed_ratio: f64,
+    #[serde(alias = "ice_speed_ratio")]
+    pub ice_speed_ratio: f64,
+    #[serde(alias = "auto_sail_change_tolerance")]
+    pub auto_sail_change_tolerance: f64,
+    #[serde(alias = "bad_sail_tolerance")]
+    pub bad_sail_tolerance: f64,
+    #[serde(alias = "max_speed")]
+    pub max_speed: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "max_speed_policy")]
+    pub max_speed_policy: Option<MaxSpeedPolicy>,
+    pub foil: Foil,
+    pub hull: Hull,
+    /// inline winch, or the one of `winchProfile` once resolved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub winch: Option<Winch>,
+    /// name of a shared winch profile
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "winch_profile")]
+    pub winch_profile: Option<String>,
+    pub tws: Vec<u8>,
+    pub twa: Vec<u8>,
+    pub sail: Vec<Sail>,
+}
+
+/// Polar file as listed.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileMeta {
+    /// in bytes
+    pub size: u64,
+    /// in seconds since epoch
+    pub modified: Option<u64>,
+    /// hash of the file content, as in its checksum file
+    pub checksum: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Foil {
+    #[serde(alias = "speed_ratio")]
+    pub speed_ratio: f64,
+    #[serde(alias = "twa_min")]
+    pub twa_min: f64,
+    #[serde(alias = "twa_max")]
+    pub twa_max: f64,
+    #[serde(alias = "twa_merge")]
+    pub twa_merge: f64,
+    #[serde(alias = "tws_min")]
+    pub tws_min: f64,
+    #[serde(alias = "tws_max")]
+    pub tws_max: f64,
+    #[serde(alias = "tws_merge")]
+    pub tws_merge: f64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Hull {
+    #[serde(alias = "speed_ratio")]
+    pub speed_ratio: f64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Winch {
+    pub tack: PenaltyCase,
+    pub gybe: PenaltyCase,
+    #[serde(alias = "sail_change")]
+    pub sail_change: PenaltyCase,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lws: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hws: Option<u8>,
+}
+
+/// Named winch shared by several polars.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WinchProfile {
+    pub name: String,
+    #[serde(flatten)]
+    pub winch: Winch,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PenaltyCase {
+    #[serde(alias = "std_timer_sec")]
+    pub std_timer_sec: u16,
+    #[serde(alias = "std_ratio")]
+    pub std_ratio: f64,
+    #[serde(alias = "pro_timer_sec")]
+    pub pro_timer_sec: u16,
+    #[serde(alias = "pro_ratio")]
+    pub pro_ratio: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub std: Option<PenaltyBoundaries>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pro: Option<PenaltyBoundaries>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PenaltyBoundaries {
+    pub lw: Penalty,
+    pub hw: Penalty,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Penalty {
+    pub ratio: f64,
+    /// also read as `timerSec`, the spelling of older winch files
+    #[serde(alias = "timerSec", alias = "timer_sec")]
+    pub timer: u16
+}
+
+/// Option pack a boat can be fitted with.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum BoatOption {
+    Foil,
+    /// light wind sails
+    Light,
+    /// reaching sails
+    Reach,
+    /// heavy wind sails
+    Heavy,
+}
+
+impl BoatOption {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "foil" => Some(BoatOption::Foil),
+            "light" => Some(BoatOption::Light),
+            "reach" => Some(BoatOption::Reach),
+            "heavy" => Some(BoatOption::Heavy),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BoatOption::Foil => "foil",
+            BoatOption::Light => "light",
+            BoatOption::Reach => "reach",
+            BoatOption::Heavy => "heavy",
+        }
+    }
+}
+
+/// Sail of the shared catalog, sails of polars reference it by id with `catalog`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SailDefinition {
+    /// e.g. `light-gnk`, numbers of older catalogs are read as strings
+    #[serde(deserialize_with = "string_or_number")]
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+}
+
+/// String, or number read as its decimal string.
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<String, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(s) => Ok(s),
+        Value::Number(n) => Ok(n.to_string()),
+        other => Err(de::Error::custom(format!("expected a string or a number, got {}", other))),
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Sail {
+    pub id: u8,
+    /// name from the sail catalog when the sail references a definition there
+    #[serde(default)]
+    pub name: String,
+    /// id of the definition of the sail in the sail catalog
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catalog: Option<String>,
+    /// option needed to use this sail, always available when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub option: Option<BoatOption>,
+    pub speed: Vec<Vec<f64>>,
+    /// speeds on port tack (negative twa) for asymmetric polars, `speed` is mirrored when absent
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "port_speed")]
+    pub port_speed: Option<Vec<Vec<f64>>>,
+    /// conditions the sail can be used in, anywhere on the grid when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<SailBounds>,
+}
+
+impl Sail {
+    /// Speed matrix for the tack of `twa`.
+    pub fn matrix(&self, twa: f64) -> &Vec<Vec<f64>> {
+        match &self.port_speed {
+            Some(port_speed) if twa < 0.0 => port_speed,
+            _ => &self.speed,
+        }
+    }
+
+    /// Whether the sail can be used at `(twa, tws)`, whatever the tack.
+    pub fn usable(&self, twa: f64, tws: f64) -> bool {
+        self.bounds.is_none_or(|bounds| bounds.contains(twa, tws))
+    }
+}
+
+/// Usable range of a sail, e.g. no storm jib in light wind, each bound being optional.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SailBounds {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub twa_min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub twa_max: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tws_min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tws_max: Option<f64>,
+}
+
+impl SailBounds {
+    /// Whether `(twa, tws)` is within the bounds, `twa` on either tack.
+    pub fn contains(&self, twa: f64, tws: f64) -> bool {
+        let within = |x: f64, min: Option<f64>, max: Option<f64>| min.is_none_or(|min| x >= min) && max.is_none_or(|max| x <= max);
+        within(twa.abs(), self.twa_min, self.twa_max) && within(tws, self.tws_min, self.tws_max)
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CellDelta {
+    pub sail: u8,
+    pub twa: u8,
+    pub tws: u8,
+    pub before: f64,
+    pub after: f64,
+    pub delta: f64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeedDiff {
+    pub max_delta: f64,
+    pub mean_delta: f64,
+    pub cells: Vec<CellDelta>,
+}
+
+impl SpeedDiff {
+    pub fn new(cells: Vec<CellDelta>) -> Self {
+        let max_delta = cells.iter().map(|c| c.delta.abs()).fold(0.0, f64::max);
+        let mean_delta = if cells.is_empty() {
+            0.0
+        } else {
+            cells.iter().map(|c| c.delta.abs()).sum::<f64>() / cells.len() as f64
+        };
+
+        SpeedDiff { max_delta, mean_delta, cells }
+    }
+}
+
+impl Polar {
+
+    /// Changed cells between two polars, compared on the grid points they have in common.
+    /// Sails are matched by id.
+    pub fn speed_diff(&self, other: &Polar) -> SpeedDiff {
+        let mut cells = Vec::new();
+        for sail in &self.sail {
+            if let Some(other_sail) = other.sail.iter().find(|s| s.id == sail.id) {
+                for (i, twa) in self.twa.iter().enumerate() {
+                    let Some(k) = other.twa.iter().position(|x| x == twa) else { continue };
+                    for (j, tws) in self.tws.iter().enumerate() {
+                        let Some(l) = other.tws.iter().position(|x| x == tws) else { continue };
+                        let before = sail.speed.get(i).and_then(|row| row.get(j));
+                        let after = other_sail.speed.get(k).and_then(|row| row.get(l));
+                        if let (Some(before), Some(after)) = (before, after) {
+                            let delta = after - before;
+                            if delta.abs() > f64::EPSILON {
+                                cells.push(CellDelta { sail: sail.id, twa: *twa, tws: *tws, before: *before, after: *after, delta });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        SpeedDiff::new(cells)
+    }
+
+    /// Fastest sail and its interpolated table speed at `(twa, tws)`, among the sails usable there.
+    /// A negative `twa` is on port tack.
+    pub fn best_sail(&self, twa: f64, tws: f64) -> Option<(u8, f64)> {
+        self.best_sail_extrapolated(twa, tws, Extrapolation::Clamp)
+    }
+
+    pub fn best_sail_extrapolated(&self, twa: f64, tws: f64, extrapolation: Extrapolation) -> Option<(u8, f64)> {
+        self.best_sail_interpolated(twa, tws, extrapolation, Interpolation::Bilinear)
+    }
+
+    /// Fastest sail usable at `(twa, tws)` and its table speed, interpolated with `method`.
+    pub fn best_sail_interpolated(&self, twa: f64, tws: f64, extrapolation: Extrapolation, method: Interpolation) -> Option<(u8, f64)> {
+        self.sail.iter()
+            .filter(|s| s.usable(twa, tws))
+            .map(|s| (s.id, matrix::interpolate_with(&self.twa, &self.tws, s.matrix(twa), twa.abs(), tws, extrapolation, method)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// Uses the `lang` translation of the label when there is one.
+    pub fn localize(&mut self, lang: &str) {
+        if let Some(label) = self.labels.get(lang) {
+            self.label = label.clone();
+        }
+    }
+
+    /// FNV-1a hash of the resolved polar, stable across restarts.
+    pub fn hash(&self) -> String {
+        content_hash(&serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    /// Short hash of what the speeds computed with the polar depend on : the same for two
+    /// polars differing only by their ids, labels, classification or sail and winch names.
+    pub fn fingerprint(&self) -> String {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(map) = value.as_object_mut() {
+            for key in COSMETIC_FIELDS {
+                map.remove(key);
+            }
+            for sail in map.get_mut("sail").and_then(Value::as_array_mut).into_iter().flatten() {
+                if let Some(sail) = sail.as_object_mut() {
+                    sail.remove("name");
+                }
+            }
+        }
+        let mut hash = content_hash(&serde_json::to_vec(&canonical(value)).unwrap_or_default());
+        hash.truncate(FINGERPRINT_LENGTH);
+        hash
+    }
+
+    /// Names the sails referencing a definition of the catalog after it.
+    pub fn resolve_sails(&mut self, catalog: &[SailDefinition]) {
+        for sail in self.sail.iter_mut() {
+            let Some(id) = &sail.catalog else { continue };
+            if let Some(definition) = catalog.iter().find(|d| &d.id == id) {
+                sail.name = definition.name.clone();
+            }
+        }
+    }
+
+    /// Replaces the winch by its shared profile when the polar references one.
+    pub fn resolve_winch(&mut self, profiles: &[WinchProfile]) {
+        if let Some(name) = &self.winch_profile {
+            match profiles.iter().find(|w| &w.name == name) {
+                Some(profile) => self.winch = Some(profile.winch.clone()),
+                None => warn!("Winch profile {} of polar {:?} does not exist", name, self.id),
+            }
+        }
+    }
+
+    /// Restricts this polar to a boat fitted with `options` : sails needing another option are
+    /// removed and the foil has no effect without the foil option.
+    pub fn with_options(&mut self, options: &[BoatOption]) {
+        self.sail.retain(|s| s.option.is_none_or(|o| options.contains(&o)));
+        if !options.contains(&BoatOption::Foil) {
+            self.foil.speed_ratio = 1.0;
+        }
+    }
+
+    /// Standalone polar of this one fitted with `options`, with an id and label derived from
+    /// them, e.g. `imoca-foil-heavy`, or `imoca-bare` without options. The options are named in
+    /// their declaration order, once, so that any order of the same options gives the same id.
+    /// Its sails need no option, see `detach`.
+    pub fn variant(&self, options: &[BoatOption]) -> Polar {
+        let mut options = options.to_vec();
+        options.sort();
+        options.dedup();
+
+        let mut res = self.clone();
+        res.with_options(&options);
+        res.sail.iter_mut().for_each(|s| s.option = None);
+        res.detach();
+
+        let names: Vec<&str> = options.iter().map(|o| o.name()).collect();
+        let id = self.id.clone().unwrap_or_default();
+        res.id = Some(if names.is_empty() { format!("{}-bare", id) } else { format!("{}-{}", id, names.join("-")) });
+        res.label = if names.is_empty() { format!("{} (bare)", self.label) } else { format!("{} ({})", self.label, names.join(", ")) };
+        res
+    }
+
+    /// Boat speed at `(twa, tws)` : best sail speed with the global speed ratio applied.
+    pub fn speed(&self, twa: f64, tws: f64) -> f64 {
+        self.best_sail(twa, tws).map_or(0.0, |(_, speed)| speed * self.speed_ratio(false))
+    }
+
+    /// Ratio applied to table speeds, including the ice speed ratio when sailing in ice zones.
+    pub fn speed_ratio(&self, in_ice: bool) -> f64 {
+        if in_ice {
+            self.global_speed_ratio * self.ice_speed_ratio
+        } else {
+            self.global_speed_ratio
+        }
+    }
+
+    /// Whether `(twa, tws)` is within the grid, whatever the tack.
+    pub fn contains(&self, twa: f64, tws: f64) -> bool {
+        matrix::contains(&self.twa, twa.abs()) && matrix::contains(&self.tws, tws)
+    }
+
+    /// Rounds every speed to `precision` decimals.
+    pub fn round(&mut self, precision: u8) {
+        for sail in self.sail.iter_mut() {
+            for row in sail.speed.iter_mut().chain(sail.port_speed.iter_mut().flatten()) {
+                row.iter_mut().for_each(|v| *v = units::round(*v, precision));
+            }
+        }
+    }
+
+    /// Same polar resampled onto the given grid.
+    pub fn regrid(&self, twa: &[u8], tws: &[u8]) -> Polar {
+        let mut res = self.clone();
+        for sail in res.sail.iter_mut() {
+            sail.speed = matrix::regrid(&self.twa, &self.tws, &sail.speed, twa, tws);
+            sail.port_speed = sail.port_speed.as_ref().map(|m| matrix::regrid(&self.twa, &self.tws, m, twa, tws));
+        }
+        res.twa = twa.to_vec();
+        res.tws = tws.to_vec();
+        res
+    }
+
+    /// Clears what ties the polar to the stored polar it was built from : `_id`, parent,
+    /// default scope, translated labels, archiving, signature and file.
+    pub fn detach(&mut self) {
+        self.polar_id = 0;
+        self.extends = None;
+        self.default = None;
+        self.labels.clear();
+        self.archived = false;
+        self.verified = None;
+        self.file = None;
+        self.source = None;
+    }
+
+    /// New polar made of this polar and the `sails` of `other` (all of them when empty), see
+    /// `detach`. Both polars are resampled on the union of their grids and the added sails get
+    /// new ids, failing when they don't fit in a sail id.
+    pub fn merge(&self, other: &Polar, sails: &[u8]) -> Result<Polar> {
+        let mut res = self.append_sails(other, sails)?.0;
+        res.detach();
+        Ok(res)
+    }
+
+    /// Same as `merge`, the added sails named after their original name suffixed by a number when
+    /// a sail already has it, e.g. `Jib 2`. Also returns how the added sails were renumbered.
+    pub fn append_sails(&self, other: &Polar, sails: &[u8]) -> Result<(Polar, Vec<SailRemap>)> {
+        let added = other.sail.iter().filter(|s| sails.is_empty() || sails.contains(&s.id)).count();
+        let first_id = self.sail.iter().map(|s| s.id).max().map_or(1, |id| id as usize + 1);
+        if first_id + added > u8::MAX as usize + 1 {
+            return Err(PolarError::NoFreeSailId(added).into());
+        }
+
+        let twa = matrix::union(&self.twa, &other.twa);
+        let tws = matrix::union(&self.tws, &other.tws);
+
+        let mut res = self.regrid(&twa, &tws);
+        let other = other.regrid(&twa, &tws);
+
+        let mut remapped = Vec::new();
+        let mut next_id = first_id as u8;
+        for sail in other.sail.into_iter().filter(|s| sails.is_empty() || sails.contains(&s.id)) {
+            let taken = |name: &str| res.sail.iter().any(|s| s.name == name);
+            let name = if sail.name.is_empty() || !taken(&sail.name) {
+                sail.name.clone()
+            } else {
+                (2..).map(|n| format!("{} {}", sail.name, n)).find(|name| !taken(name)).unwrap_or_default()
+            };
+            remapped.push(SailRemap { from: sail.id, to: next_id, name: name.clone() });
+            res.sail.push(Sail { id: next_id, name, ..sail });
+            next_id = next_id.wrapping_add(1);
+        }
+        Ok((res, remapped))
+    }
+}
+
+/// New id and name of a sail appended to another polar.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SailRemap {
+    pub from: u8,
+    pub to: u8,
+    pub name: String,
+}
+
+/// Union of the grids of `polars`, twa then tws.
+pub fn common_grid(polars: &[&Polar]) -> (Vec<u8>, Vec<u8>) {
+    let twa = polars.iter().fold(Vec::new(), |axis, polar| matrix::union(&axis, &polar.twa));
+    let tws = polars.iter().fold(Vec::new(), |axis, polar| matrix::union(&axis, &polar.tws));
+    (twa, tws)
+}
+
+/// `polars` resampled onto the same `twa` x `tws` grid.
+pub fn regrid_all(polars: &[&Polar], twa: &[u8], tws: &[u8]) -> Vec<Polar> {
+    polars.iter().map(|polar| polar.regrid(twa, tws)).collect()
+}
+
+/// Weighted average of the `sources` polars and their weights, on the union of their grids.
+/// Sails are matched by id and averaged over the sources having them, the other fields are
+/// those of the first source, detached from it. `None` without two sources or with a weight
+/// that isn't positive.
+pub fn blend(sources: &[(&Polar, f64)]) -> Option<Polar> {
+    if sources.len() < 2 || sources.iter().any(|(_, weight)| !weight.is_finite() || *weight <= 0.0) {
+        return None;
+    }
+    let polars: Vec<&Polar> = sources.iter().map(|(polar, _)| *polar).collect();
+    let (twa, tws) = common_grid(&polars);
+    let sources: Vec<(Polar, f64)> = regrid_all(&polars, &twa, &tws).into_iter()
+        .zip(sources.iter().map(|(_, weight)| *weight))
+        .collect();
+
+    let mean = |matrices: &[(&Matrix, f64)]| -> Matrix {
+        let total: f64 = matrices.iter().map(|(_, weight)| weight).sum();
+        (0..twa.len())
+            .map(|i| (0..tws.len())
+                .map(|j| matrices.iter().map(|(m, weight)| m[i][j] * weight).sum::<f64>() / total)
+                .collect())
+            .collect()
+    };
+
+    let ids: BTreeSet<u8> = sources.iter().flat_map(|(polar, _)| polar.sail.iter().map(|s| s.id)).collect();
+    let sail = ids.into_iter()
+        .map(|id| {
+            let sails: Vec<(&Sail, f64)> = sources.iter()
+                .filter_map(|(polar, weight)| polar.sail.iter().find(|s| s.id == id).map(|s| (s, *weight)))
+                .collect();
+            let speed = mean(&sails.iter().map(|(s, weight)| (&s.speed, *weight)).collect::<Vec<_>>());
+            let port_speed = sails.iter().any(|(s, _)| s.port_speed.is_some())
+                .then(|| mean(&sails.iter().map(|(s, weight)| (s.port_speed.as_ref().unwrap_or(&s.speed), *weight)).collect::<Vec<_>>()));
+            Sail { speed, port_speed, ..sails[0].0.clone() }
+        })
+        .collect();
+
+    let mut res = sources[0].0.clone();
+    res.detach();
+    res.sail = sail;
+    res.twa = twa;
+    res.tws = tws;
+    Some(res)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::template::test_polar;
+
+    fn scaffold(id: &str, polar_id: u8, sails: u8, twa_step: u8) -> Polar {
+        let mut polar = test_polar(id, sails, twa_step, 5);
+        polar.polar_id = polar_id;
+        polar
+    }
+
+    #[test]
+    fn canonical_yaml_sorts_keys_and_drops_negative_zeros() {
+        let value = json!({ "b": 1, "a": { "d": -0.0, "c": [2, 1] } });
+        let yaml = canonical_yaml(&value).unwrap();
+        assert!(yaml.ends_with('\n'));
+        assert!(yaml.find("a:") < yaml.find("b:"));
+        assert!(yaml.find("c:") < yaml.find("d:"));
+        assert!(!yaml.contains("-0"));
+        assert_eq!(serde_yaml::from_str::<Value>(&yaml).unwrap(), value);
+    }
+
+    #[test]
+    fn canonical_yaml_is_stable() {
+        let value = json!({ "label": "Imoca", "tws": [0, 10], "sail": [{ "id": 1, "speed": [[0.0, 5.5]] }] });
+        let yaml = canonical_yaml(&value).unwrap();
+        assert_eq!(canonical_yaml(&serde_yaml::from_str(&yaml).unwrap()).unwrap(), yaml);
+    }
+
+    #[test]
+    fn appended_sails_get_the_next_ids_and_free_names() {
+        let base = scaffold("base", 1, 3, 10);
+        let other = scaffold("other", 2, 2, 15);
+        let (polar, remapped) = base.append_sails(&other, &[]).unwrap();
+
+        assert_eq!(polar.twa, matrix::union(&base.twa, &other.twa));
+        assert_eq!(polar.sail.iter().map(|s| (s.id, s.name.as_str())).collect::<Vec<_>>(),
+            [(1, "Jib"), (2, "Spi"), (3, "Staysail"), (4, "Jib 2"), (5, "Spi 2")]);
+        assert_eq!(remapped.iter().map(|r| (r.from, r.to)).collect::<Vec<_>>(), [(1, 4), (2, 5)]);
+        assert!(polar.sail.iter().all(|s| s.speed.len() == polar.twa.len()));
+        assert_eq!(polar.polar_id, 1);
+    }
+
+    #[test]
+    fn appends_the_selected_sails() {
+        let base = scaffold("base", 1, 2, 10);
+        let other = scaffold("other", 2, 3, 10);
+        let (polar, remapped) = base.append_sails(&other, &[3]).unwrap();
+        assert_eq!(polar.sail.iter().map(|s| (s.id, s.name.as_str())).collect::<Vec<_>>(), [(1, "Jib"), (2, "Spi"), (3, "Staysail")]);
+        assert_eq!(remapped.iter().map(|r| (r.from, r.to)).collect::<Vec<_>>(), [(3, 3)]);
+    }
+
+    #[test]
+    fn sail_ids_must_fit() {
+        let mut base = scaffold("base", 1, 1, 10);
+        let other = scaffold("other", 2, 2, 10);
+        base.sail[0].id = 254;
+        let err = base.append_sails(&other, &[]).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PolarError>(), Some(PolarError::NoFreeSailId(2))));
+        assert!(base.merge(&other, &[]).is_err());
+
+        let (polar, _) = base.append_sails(&other, &[2]).unwrap();
+        assert_eq!(polar.sail.last().map(|s| s.id), Some(255));
+    }
+
+    #[test]
+    fn merged_polar_is_detached() {
+        let mut base = scaffold("base", 1, 2, 10);
+        base.extends = Some("parent".to_string());
+        let merged = base.merge(&scaffold("other", 2, 2, 10), &[]).unwrap();
+        assert_eq!(merged.polar_id, 0);
+        assert_eq!(merged.extends, None);
+        assert_eq!(merged.sail.len(), 4);
+    }
+}
@@ -0,0 +1,61 @@
+#[cfg(feature = "rocket")]
+use rocket::form::FromFormField;
+
+use crate::polar::{Foil, Polar};
+use crate::template::{Template, TemplateParams};
+
+/// Generic polar of a race class, bundled with the service to start from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rocket", derive(FromFormField))]
+pub enum Preset {
+    Imoca,
+    Class40,
+    Ultime,
+}
+
+/// Tag of the polars created from presets.
+pub const PRESET_TAG: &str = "preset";
+
+impl Preset {
+    pub const ALL: [Preset; 3] = [Preset::Imoca, Preset::Class40, Preset::Ultime];
+
+    /// Id of the polar of the preset.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Preset::Imoca => "preset-imoca",
+            Preset::Class40 => "preset-class40",
+            Preset::Ultime => "preset-ultime",
+        }
+    }
+
+    /// Polar of the preset, with the `_id` 0 : a scaffold shaped after the speeds and foil of
+    /// the class, not the certificate of an actual boat.
+    pub fn polar(&self) -> Polar {
+        let (template, label, class, base_speed, foil) = match self {
+            Preset::Imoca => (Template::Monohull, "IMOCA 60 (generic)", "IMOCA", 15.0,
+                Some(Foil { speed_ratio: 1.04, twa_min: 80.0, twa_max: 160.0, twa_merge: 10.0, tws_min: 16.0, tws_max: 35.0, tws_merge: 5.0 })),
+            Preset::Class40 => (Template::Monohull, "Class40 (generic)", "Class40", 11.0, None),
+            Preset::Ultime => (Template::Multihull, "Ultime (generic)", "Ultime", 30.0,
+                Some(Foil { speed_ratio: 1.04, twa_min: 70.0, twa_max: 170.0, twa_merge: 10.0, tws_min: 10.0, tws_max: 40.0, tws_merge: 5.0 })),
+        };
+        let params = TemplateParams {
+            template,
+            id: self.id().to_string(),
+            polar_id: 0,
+            label: Some(label.to_string()),
+            sails: Some(7),
+            twa_step: None,
+            tws_step: None,
+            tws_max: None,
+            base_speed: Some(base_speed),
+        };
+
+        let mut polar = params.scaffold();
+        polar.class = Some(class.to_string());
+        polar.tags = vec![PRESET_TAG.to_string()];
+        if let Some(foil) = foil {
+            polar.foil = foil;
+        }
+        polar
+    }
+}
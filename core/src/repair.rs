@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+use crate::lint;
+use crate::polar::Polar;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Fix {
+    /// sort `twa` and `tws` ascending, reordering the speed matrices accordingly
+    SortGrids,
+    /// replace the outlier cells reported by lint by their expected value
+    InterpolateOutliers,
+    /// clamp speeds to `maxSpeed`
+    ClampMaxSpeed,
+}
+
+/// Fixes in the order they are applied.
+const ALL: [Fix; 3] = [Fix::SortGrids, Fix::InterpolateOutliers, Fix::ClampMaxSpeed];
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedFix {
+    pub fix: Fix,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sail: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub twa: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tws: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<f64>,
+}
+
+impl AppliedFix {
+    fn cell(fix: Fix, sail: u8, twa: u8, tws: u8, before: f64, after: f64) -> Self {
+        AppliedFix { fix, sail: Some(sail), twa: Some(twa), tws: Some(tws), before: Some(before), after: Some(after) }
+    }
+}
+
+/// Applies the `fixes` (all of them when empty) to `polar` and returns what was changed.
+pub fn repair(polar: &mut Polar, fixes: &[Fix]) -> Vec<AppliedFix> {
+    let mut applied = Vec::new();
+    for fix in ALL.iter().filter(|f| fixes.is_empty() || fixes.contains(f)) {
+        match fix {
+            Fix::SortGrids => sort_grids(polar, &mut applied),
+            Fix::InterpolateOutliers => interpolate_outliers(polar, &mut applied),
+            Fix::ClampMaxSpeed => clamp_max_speed(polar, &mut applied),
+        }
+    }
+    applied
+}
+
+fn sort_grids(polar: &mut Polar, applied: &mut Vec<AppliedFix>) {
+    let mut twa_order: Vec<usize> = (0..polar.twa.len()).collect();
+    twa_order.sort_by_key(|i| polar.twa[*i]);
+    let mut tws_order: Vec<usize> = (0..polar.tws.len()).collect();
+    tws_order.sort_by_key(|j| polar.tws[*j]);
+
+    let sorted = |order: &[usize]| order.iter().enumerate().all(|(k, i)| k == *i);
+    if sorted(&twa_order) && sorted(&tws_order) {
+        return;
+    }
+
+    polar.twa = twa_order.iter().map(|i| polar.twa[*i]).collect();
+    polar.tws = tws_order.iter().map(|j| polar.tws[*j]).collect();
+    let reorder = |matrix: &Vec<Vec<f64>>| -> Vec<Vec<f64>> {
+        twa_order.iter()
+            .filter_map(|i| matrix.get(*i))
+            .map(|row| tws_order.iter().filter_map(|j| row.get(*j)).copied().collect())
+            .collect()
+    };
+    for sail in polar.sail.iter_mut() {
+        sail.speed = reorder(&sail.speed);
+        sail.port_speed = sail.port_speed.as_ref().map(reorder);
+    }
+
+    applied.push(AppliedFix { fix: Fix::SortGrids, sail: None, twa: None, tws: None, before: None, after: None });
+}
+
+fn interpolate_outliers(polar: &mut Polar, applied: &mut Vec<AppliedFix>) {
+    for sail in polar.sail.iter_mut() {
+        let id = sail.id;
+        for matrix in std::iter::once(&mut sail.speed).chain(sail.port_speed.as_mut()) {
+            // outliers are detected on the original values, not the ones already replaced
+            let original = matrix.clone();
+            for (i, twa) in polar.twa.iter().enumerate() {
+                for (j, tws) in polar.tws.iter().enumerate() {
+                    if let Some(expected) = lint::outlier(&polar.twa, &polar.tws, &original, i, j) {
+                        applied.push(AppliedFix::cell(Fix::InterpolateOutliers, id, *twa, *tws, original[i][j], expected));
+                        matrix[i][j] = expected;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn clamp_max_speed(polar: &mut Polar, applied: &mut Vec<AppliedFix>) {
+    if polar.max_speed <= 0.0 {
+        return;
+    }
+    for sail in polar.sail.iter_mut() {
+        let id = sail.id;
+        for matrix in std::iter::once(&mut sail.speed).chain(sail.port_speed.as_mut()) {
+            for (row, twa) in matrix.iter_mut().zip(&polar.twa) {
+                for (v, tws) in row.iter_mut().zip(&polar.tws) {
+                    if *v > polar.max_speed {
+                        applied.push(AppliedFix::cell(Fix::ClampMaxSpeed, id, *twa, *tws, *v, polar.max_speed));
+                        *v = polar.max_speed;
+                    }
+                }
+            }
+        }
+    }
+}
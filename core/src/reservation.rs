@@ -0,0 +1,126 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+
+use crate::polar::PolarError;
+
+/// Reserved `_id`s with their expiry, in seconds since epoch, and token. They are only kept in
+/// memory, and lost on restart.
+#[derive(Default)]
+pub struct Reservations {
+    reserved: Mutex<HashMap<u8, (u64, String)>>,
+}
+
+impl Reservations {
+    /// Reserves the lowest `_id` neither `used` nor reserved for `ttl` seconds, at most
+    /// `MAX_RESERVATION_TTL`, or until it is released.
+    pub fn reserve(&self, used: &[u8], ttl: u64) -> Result<Reservation> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut reserved = self.reserved.lock().unwrap_or_else(|e| e.into_inner());
+        reserved.retain(|_, (expires_at, _)| *expires_at > now.as_secs());
+        let polar_id = (1..=u8::MAX)
+            .find(|id| !used.contains(id) && !reserved.contains_key(id))
+            .ok_or(PolarError::NoFreeId())?;
+        let expires_at = now.as_secs().saturating_add(ttl.min(MAX_RESERVATION_TTL));
+        let token = token()?;
+        reserved.insert(polar_id, (expires_at, token.clone()));
+        Ok(Reservation { polar_id, expires_at, token })
+    }
+
+    /// `_id` reserved with `token`, if not expired.
+    pub fn reserved_id(&self, token: &str) -> Option<u8> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let reserved = self.reserved.lock().unwrap_or_else(|e| e.into_inner());
+        reserved.iter()
+            .find(|(_, (expires_at, reserved))| reserved == token && *expires_at > now)
+            .map(|(polar_id, _)| *polar_id)
+    }
+
+    /// Fails when `polar_id` is reserved with another token than `token`.
+    pub fn check(&self, polar_id: u8, token: Option<&str>) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let mut reserved = self.reserved.lock().unwrap_or_else(|e| e.into_inner());
+        reserved.retain(|_, (expires_at, _)| *expires_at > now);
+        match reserved.get(&polar_id) {
+            Some((_, reserved)) if Some(reserved.as_str()) != token => Err(PolarError::PolarIdTaken(polar_id).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Ends the reservation of `polar_id`, e.g. once a polar is created with it.
+    pub fn release(&self, polar_id: u8) {
+        self.reserved.lock().unwrap_or_else(|e| e.into_inner()).remove(&polar_id);
+    }
+}
+
+/// Hex encoded random token from the OS CSPRNG, which can't be guessed from the reserved `_id`
+/// and the time of the reservation.
+fn token() -> Result<String> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    getrandom::getrandom(&mut bytes).map_err(|e| anyhow!("no random token : {}", e))?;
+    Ok(hex::encode(bytes))
+}
+
+/// Random bytes of a reservation token.
+const TOKEN_BYTES: usize = 16;
+
+/// Longest reservation of an `_id`, in seconds.
+pub const MAX_RESERVATION_TTL: u64 = 7 * 86_400;
+
+/// `_id` reserved until `expires_at`, in seconds since epoch, for the holder of `token`.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Reservation {
+    #[serde(rename = "_id")]
+    pub polar_id: u8,
+    pub expires_at: u64,
+    pub token: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserves_the_lowest_free_ids() {
+        let reservations = Reservations::default();
+        let first = reservations.reserve(&[1, 2], 60).unwrap();
+        let second = reservations.reserve(&[1, 2], 60).unwrap();
+        assert_eq!((first.polar_id, second.polar_id), (3, 4));
+        assert_ne!(first.token, second.token);
+        assert_eq!(first.token.len(), 2 * TOKEN_BYTES);
+    }
+
+    #[test]
+    fn fails_without_free_id() {
+        let used: Vec<u8> = (1..=u8::MAX).collect();
+        let error = Reservations::default().reserve(&used, 60).unwrap_err();
+        assert!(matches!(error.downcast_ref::<PolarError>(), Some(PolarError::NoFreeId())));
+    }
+
+    #[test]
+    fn only_the_holder_of_the_token_can_use_the_id() {
+        let reservations = Reservations::default();
+        let reservation = reservations.reserve(&[], 60).unwrap();
+        assert_eq!(reservations.reserved_id(&reservation.token), Some(reservation.polar_id));
+
+        let error = reservations.check(reservation.polar_id, None).unwrap_err();
+        assert!(matches!(error.downcast_ref::<PolarError>(), Some(PolarError::PolarIdTaken(1))));
+        assert!(reservations.check(reservation.polar_id, Some("guess")).is_err());
+        assert!(reservations.check(reservation.polar_id, Some(&reservation.token)).is_ok());
+
+        reservations.release(reservation.polar_id);
+        assert!(reservations.check(reservation.polar_id, None).is_ok());
+        assert_eq!(reservations.reserved_id(&reservation.token), None);
+    }
+
+    #[test]
+    fn caps_the_ttl() {
+        let reservation = Reservations::default().reserve(&[], u64::MAX).unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        assert!(reservation.expires_at <= now + MAX_RESERVATION_TTL);
+    }
+}
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+
+use crate::compute::{self, FoilMode};
+use crate::eta::Leg;
+use crate::polar::Polar;
+use crate::stats;
+
+/// Numeric field of a polar that can be perturbed.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Parameter {
+    GlobalSpeedRatio,
+    FoilSpeedRatio,
+    FoilTwaMin,
+    FoilTwaMax,
+    FoilTwsMin,
+    FoilTwsMax,
+}
+
+impl Parameter {
+    fn field(self, polar: &mut Polar) -> &mut f64 {
+        match self {
+            Parameter::GlobalSpeedRatio => &mut polar.global_speed_ratio,
+            Parameter::FoilSpeedRatio => &mut polar.foil.speed_ratio,
+            Parameter::FoilTwaMin => &mut polar.foil.twa_min,
+            Parameter::FoilTwaMax => &mut polar.foil.twa_max,
+            Parameter::FoilTwsMin => &mut polar.foil.tws_min,
+            Parameter::FoilTwsMax => &mut polar.foil.tws_max,
+        }
+    }
+}
+
+/// `parameter` moved up and down by `delta` times its value, e.g. 0.02 for ±2%.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct Perturbation {
+    pub parameter: Parameter,
+    pub delta: f64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Sensitivity {
+    pub parameter: Parameter,
+    /// value of the parameter in the polar
+    pub value: f64,
+    /// impacts of the increased then decreased value
+    pub impacts: Vec<Impact>,
+}
+
+/// Changes of the routing outcomes with a perturbed value, in percent of the unperturbed ones.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Impact {
+    pub value: f64,
+    pub vmg: Vec<VmgImpact>,
+    /// change of the time to sail the legs, none without legs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta: Option<f64>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VmgImpact {
+    pub tws: f64,
+    pub upwind: f64,
+    pub downwind: f64,
+}
+
+/// Impacts of each of `perturbations` on the best vmg at every tws of the grid and on the time
+/// to sail `legs`, foil included. Maneuvers are left out of leg times.
+pub fn sensitivity(polar: &Polar, perturbations: &[Perturbation], legs: &[Leg]) -> Vec<Sensitivity> {
+    let vmg = outcomes(polar);
+    let eta = (!legs.is_empty()).then(|| eta(polar, legs));
+
+    perturbations.iter()
+        .map(|perturbation| {
+            let value = *perturbation.parameter.field(&mut polar.clone());
+            let impacts = [1.0, -1.0].iter()
+                .map(|sign| {
+                    let mut perturbed = polar.clone();
+                    let field = perturbation.parameter.field(&mut perturbed);
+                    *field = value * (1.0 + sign * perturbation.delta);
+                    let value = *field;
+                    Impact {
+                        value,
+                        vmg: vmg.iter().zip(outcomes(&perturbed))
+                            .map(|((tws, upwind, downwind), (_, perturbed_upwind, perturbed_downwind))| VmgImpact {
+                                tws: *tws,
+                                upwind: change(*upwind, perturbed_upwind),
+                                downwind: change(*downwind, perturbed_downwind),
+                            })
+                            .collect(),
+                        eta: eta.map(|eta| change(eta, self::eta(&perturbed, legs))),
+                    }
+                })
+                .collect();
+            Sensitivity { parameter: perturbation.parameter, value, impacts }
+        })
+        .collect()
+}
+
+/// Change from `before` to `after`, in percent.
+fn change(before: f64, after: f64) -> f64 {
+    if before.is_finite() && before != 0.0 { (after - before) / before * 100.0 } else { 0.0 }
+}
+
+/// Effective speed of the best sail at `(twa, tws)`, foil included.
+fn speed(polar: &Polar, twa: f64, tws: f64) -> f64 {
+    polar.speed(twa, tws) * compute::foil(polar, FoilMode::Auto, twa, tws)
+}
+
+/// Best upwind and downwind vmg at every tws of the grid.
+fn outcomes(polar: &Polar) -> Vec<(f64, f64, f64)> {
+    let angles = stats::sweep(polar);
+    polar.tws.iter()
+        .map(|tws| {
+            let tws = *tws as f64;
+            let (upwind, downwind) = angles.iter()
+                .map(|twa| speed(polar, *twa, tws) * twa.to_radians().cos())
+                .fold((0.0f64, 0.0f64), |(up, down), vmg| (up.max(vmg), down.max(-vmg)));
+            (tws, upwind, downwind)
+        })
+        .collect()
+}
+
+/// Time to sail `legs` in seconds, infinite when one can't be sailed.
+fn eta(polar: &Polar, legs: &[Leg]) -> f64 {
+    legs.iter()
+        .map(|leg| {
+            let speed = speed(polar, leg.twa, leg.tws);
+            if speed > 0.0 { leg.distance_nm / speed * 3600.0 } else { f64::INFINITY }
+        })
+        .sum()
+}
@@ -0,0 +1,1570 @@
+#[cfg(feature = "rocket")]
+use rocket::form::{FromForm, FromFormField};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::ffi::OsStr;
+use std::fs;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use log::error;
+use ed25519_dalek::VerifyingKey;
+use flate2::read::GzDecoder;
+
+use crate::audit::Audit;
+use crate::catalog::Catalog;
+use crate::compute::{AngleConvention, Options};
+use crate::export;
+use crate::lint::{self, Finding};
+use crate::mapped::MappedPolar;
+use crate::matrix::Extrapolation;
+use crate::migrate::{self, SCHEMA_VERSION};
+use crate::polar::{canonical_yaml, content_hash, unknown_fields, BoatOption, DefaultScope, FileMeta, MaxSpeedPolicy, Polar, PolarError, SailDefinition, WinchProfile};
+use crate::preset::Preset;
+use crate::repair::{self, Fix};
+use crate::reservation::{Reservation, Reservations};
+use crate::signature;
+use crate::store::{self, file_stamp, Store};
+use crate::table::{Table, TableCache};
+use crate::transform::{self, BulkResult, BulkUpdate};
+use crate::usage::{self, Usage};
+use crate::validate::{self, ValidationError};
+
+/// Polars of a `Store` as the service exposes them : parsed and checked, with their sails and
+/// winch resolved from the catalogs, their `_id`s reserved while they are created, and their
+/// evaluation tables cached.
+pub struct PolarService {
+    store: Store,
+    max_speed_policy: MaxSpeedPolicy,
+    precision: Option<u8>,
+    extrapolation: Extrapolation,
+    angles: AngleConvention,
+    sails: Catalog<SailDefinition>,
+    winches: Catalog<WinchProfile>,
+    strict: bool,
+    /// days without use after which active polars are reported, then archived
+    archive_inactive_days: Option<u64>,
+    /// days between the report of an inactive polar and its archiving
+    archive_notice_days: u64,
+    /// first report of each inactive polar, kept across restarts
+    reports_file: PathBuf,
+    /// keys of the signatures of official polars
+    trusted_keys: Vec<VerifyingKey>,
+    tables: TableCache,
+    usage: Usage,
+    audit: Audit,
+    reservations: Reservations,
+    /// binary effective polars memory mapped by `mapped`, not kept when `None`
+    binary_dir: Option<PathBuf>,
+}
+
+impl PolarService {
+
+    pub fn new<P: Into<PathBuf>, Q: Into<PathBuf>>(polars_dir: P, archived_dir: Q) -> Self {
+        let polars_dir: PathBuf = polars_dir.into();
+        PolarService {
+            reports_file: polars_dir.join(REPORTS_FILE),
+            store: Store::new(polars_dir, archived_dir),
+            max_speed_policy: MaxSpeedPolicy::default(),
+            precision: None,
+            extrapolation: Extrapolation::default(),
+            angles: AngleConvention::default(),
+            sails: Catalog::new("sails.yaml"),
+            winches: Catalog::new("winches.yaml"),
+            strict: false,
+            archive_inactive_days: None,
+            archive_notice_days: ARCHIVE_NOTICE_DAYS,
+            trusted_keys: Vec::new(),
+            tables: TableCache::default(),
+            usage: Usage::default(),
+            audit: Audit::default(),
+            reservations: Reservations::default(),
+            binary_dir: None,
+        }
+    }
+
+    /// Default behavior for speeds above `max_speed` on save, polars can override it.
+    pub fn max_speed_policy(mut self, max_speed_policy: MaxSpeedPolicy) -> Self {
+        self.max_speed_policy = max_speed_policy;
+        self
+    }
+
+    /// Number of decimals of the speeds written on disk, unchanged when `None`.
+    pub fn precision(mut self, precision: Option<u8>) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Default behavior of speed evaluations outside of the grid.
+    pub fn extrapolation(mut self, extrapolation: Extrapolation) -> Self {
+        self.extrapolation = extrapolation;
+        self
+    }
+
+    /// Default convention of the twa returned by the speed evaluations.
+    pub fn angles(mut self, angles: AngleConvention) -> Self {
+        self.angles = angles;
+        self
+    }
+
+    /// File of the shared sail catalog, unchanged when `None`.
+    pub fn sails_file<P: Into<PathBuf>>(mut self, sails_file: Option<P>) -> Self {
+        if let Some(sails_file) = sails_file {
+            self.sails = Catalog::new(sails_file);
+        }
+        self
+    }
+
+    /// File of the shared winch profiles, unchanged when `None`.
+    pub fn winches_file<P: Into<PathBuf>>(mut self, winches_file: Option<P>) -> Self {
+        if let Some(winches_file) = winches_file {
+            self.winches = Catalog::new(winches_file);
+        }
+        self
+    }
+
+    /// File of the usage history, kept in memory only when `None`.
+    pub fn usage_file<P: Into<PathBuf>>(mut self, usage_file: Option<P>) -> Self {
+        if let Some(usage_file) = usage_file {
+            self.usage = Usage::load(usage_file);
+        }
+        self
+    }
+
+    /// File of the audit log, not kept when `None`.
+    pub fn audit_file<P: Into<PathBuf>>(mut self, audit_file: Option<P>) -> Self {
+        if let Some(audit_file) = audit_file {
+            self.audit = Audit::new(audit_file);
+        }
+        self
+    }
+
+    /// See `Store::compress`.
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.store = self.store.compress(compress);
+        self
+    }
+
+    /// See `Store::compress_archived`.
+    pub fn compress_archived(mut self, compress_archived: bool) -> Self {
+        self.store = self.store.compress_archived(compress_archived);
+        self
+    }
+
+    /// See `Store::cold_dir`.
+    pub fn cold_dir<P: Into<PathBuf>>(mut self, cold_dir: Option<P>) -> Self {
+        self.store = self.store.cold_dir(cold_dir);
+        self
+    }
+
+    /// See `Store::cold_after_days`.
+    pub fn cold_after_days(mut self, cold_after_days: Option<u64>) -> Self {
+        self.store = self.store.cold_after_days(cold_after_days);
+        self
+    }
+
+    /// Days without being fetched, evaluated nor modified after which active polars are
+    /// reported by `inactive`, never when `None`. Fails above the 90 days covered by the usage
+    /// history, as no polar would ever be reported.
+    pub fn archive_inactive_days(mut self, archive_inactive_days: Option<u64>) -> Result<Self> {
+        if let Some(days) = archive_inactive_days.filter(|days| *days > usage::RETENTION_DAYS) {
+            return Err(anyhow!("archive_inactive_days is {} but the usage history only covers {} days", days, usage::RETENTION_DAYS));
+        }
+        self.archive_inactive_days = archive_inactive_days;
+        Ok(self)
+    }
+
+    /// Days between the first report of an inactive polar and its archiving by
+    /// `archive_inactive`, unchanged when `None`.
+    pub fn archive_notice_days(mut self, archive_notice_days: Option<u64>) -> Self {
+        if let Some(archive_notice_days) = archive_notice_days {
+            self.archive_notice_days = archive_notice_days;
+        }
+        self
+    }
+
+    /// Rejects incoming polars with fields unknown to the model instead of ignoring them.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// See `Store::rewrite_migrated`.
+    pub fn rewrite_migrated(mut self, rewrite_migrated: bool) -> Self {
+        self.store = self.store.rewrite_migrated(rewrite_migrated);
+        self
+    }
+
+    /// See `Store::matrices_dir`.
+    pub fn matrices_dir<P: Into<PathBuf>>(mut self, matrices_dir: Option<P>) -> Self {
+        self.store = self.store.matrices_dir(matrices_dir);
+        self
+    }
+
+    /// See `Store::revisions_dir`.
+    pub fn revisions_dir<P: Into<PathBuf>>(mut self, revisions_dir: Option<P>) -> Self {
+        self.store = self.store.revisions_dir(revisions_dir);
+        self
+    }
+
+    /// Directory of the binary effective polars evaluated memory mapped by `mapped`.
+    pub fn binary_dir<P: Into<PathBuf>>(mut self, binary_dir: Option<P>) -> Self {
+        if let Some(binary_dir) = binary_dir {
+            let binary_dir = binary_dir.into();
+            Store::create_dir(&binary_dir);
+            self.binary_dir = Some(binary_dir);
+        }
+        self
+    }
+
+    /// Hex encoded public keys of the signatures of official polars, whose polars are flagged
+    /// `verified` when read. Fails on the first key which is not a valid public key.
+    pub fn trusted_keys(mut self, trusted_keys: &[String]) -> Result<Self> {
+        self.trusted_keys = trusted_keys.iter()
+            .map(|key| signature::verifying_key(key).map_err(|e| anyhow!("Invalid trusted key {} : {}", key, e)))
+            .collect::<Result<_>>()?;
+        Ok(self)
+    }
+
+    /// Incoming polar from `value`, migrated when of an older schema version and failing in
+    /// strict mode when it has unknown fields, with `PolarError::Invalid` locating the faulty
+    /// fields. Stored files are always read leniently, their
+    /// unknown fields are reported by `unknown_fields`.
+    pub fn parse(&self, mut value: Value) -> Result<Polar> {
+        migrate::migrate(&mut value);
+        if self.strict {
+            let unknown = unknown_fields(&value);
+            if !unknown.is_empty() {
+                return Err(PolarError::Invalid(unknown.iter().map(|path| ValidationError::unknown_field(path)).collect()).into());
+            }
+        }
+        self.parse_lenient(value)
+    }
+
+    /// Same as `parse` whatever the strict mode, e.g. to report the unknown fields of a dry run
+    /// instead of failing on them.
+    pub fn parse_lenient(&self, mut value: Value) -> Result<Polar> {
+        migrate::migrate(&mut value);
+        serde_path_to_error::deserialize(value).map_err(|e| {
+            let segments: Vec<String> = e.path().iter().map(|segment| match segment {
+                serde_path_to_error::Segment::Seq { index } => index.to_string(),
+                serde_path_to_error::Segment::Map { key } => key.clone(),
+                serde_path_to_error::Segment::Enum { variant } => variant.clone(),
+                serde_path_to_error::Segment::Unknown => "?".to_string(),
+            }).collect();
+            let error = ValidationError::malformed(segments.iter().map(String::as_str), e.inner().to_string());
+            PolarError::Invalid(vec![error]).into()
+        })
+    }
+
+    /// Fields of the file of `polar_id`, parents included, unknown to the model.
+    pub async fn unknown_fields(&self, polar_id: &str) -> Result<Option<Vec<String>>> {
+        let Some(path) = self.store.path_of(polar_id) else { return Ok(None) };
+        Ok(Some(unknown_fields(&self.store.read_value(&path, false)?)))
+    }
+
+    pub fn default_extrapolation(&self) -> Extrapolation {
+        self.extrapolation
+    }
+
+    pub fn default_angles(&self) -> AngleConvention {
+        self.angles
+    }
+
+    /// Files of the polars.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Shared sail definitions, see `delete_sail` to delete one.
+    pub fn sails(&self) -> &Catalog<SailDefinition> {
+        &self.sails
+    }
+
+    /// Shared winch profiles, see `delete_winch` to delete one.
+    pub fn winches(&self) -> &Catalog<WinchProfile> {
+        &self.winches
+    }
+
+    /// Usage counters of the polars and history of the requests.
+    pub fn usage(&self) -> &Usage {
+        &self.usage
+    }
+
+    /// Log of the administrative changes, such as `_id` remaps.
+    pub fn audit(&self) -> &Audit {
+        &self.audit
+    }
+
+    /// Evaluation table of `polar`, built on first use and cached while its content is unchanged.
+    /// The table of a polar read from the store is keyed by its files and sails, its speeds
+    /// are only hashed otherwise : they must not be changed in between.
+    pub fn table(&self, polar: &Polar) -> Arc<Table> {
+        let key = self.store.files_stamp(polar).map_or_else(|| Table::key(polar), |stamp| Table::file_key(&stamp, polar));
+        self.tables.get(key, polar)
+    }
+
+    /// Effective polar `polar_id` with the default evaluation options, memory mapped from its
+    /// binary export in `binary_dir`. The export is written on first use and whenever the files
+    /// of the polar changed since. `None` without such polar or without `binary_dir`.
+    pub async fn mapped(&self, polar_id: String) -> Result<Option<MappedPolar>> {
+        let Some(dir) = &self.binary_dir else { return Ok(None) };
+        let Some(path) = self.store.path_of(&polar_id) else { return Ok(None) };
+        let binary = dir.join(format!("{}.{}", polar_id, BINARY_EXTENSION));
+        let stamp_path = dir.join(format!("{}.{}{}", polar_id, BINARY_EXTENSION, STAMP_SUFFIX));
+
+        // the stamp lists the files the export was made from, the polar file first
+        let fresh = fs::read_to_string(&stamp_path).is_ok_and(|stamp| {
+            stamp.lines().next() == file_stamp(&path).as_deref()
+                && stamp.lines().all(|line| line.rsplitn(3, ':').nth(2).and_then(|path| file_stamp(Path::new(path))).as_deref() == Some(line))
+        });
+        if !fresh || !binary.exists() {
+            let Some(polar) = self.get(polar_id.clone()).await? else { return Ok(None) };
+            let stamp = self.store.files_stamp(&polar).ok_or_else(|| anyhow!("files of polar {} changed while reading them", polar_id))?;
+            let options = Options { extrapolation: Some(self.extrapolation), ..Options::default() };
+            let bytes = export::binary(&polar, &self.table(&polar), &options)
+                .ok_or_else(|| anyhow!("polar {} can't be evaluated on its grid", polar_id))?;
+            // mapped files are replaced, never truncated under the readers
+            let tmp = dir.join(format!("{}.{}.{}.tmp", polar_id, BINARY_EXTENSION, thread_id()));
+            fs::write(&tmp, bytes)?;
+            fs::rename(&tmp, &binary)?;
+            fs::write(&stamp_path, stamp)?;
+        }
+        Ok(Some(MappedPolar::open(&binary)?))
+    }
+
+    pub async fn list(&self, archived: Option<bool>) -> Result<Vec<Polar>> {
+        self.scan(archived, false, None).await
+    }
+
+    /// Polars of `selection`, without their speed matrices with `summary` as `summaries`.
+    pub async fn select(&self, selection: &Selection, summary: bool) -> Result<Vec<Polar>> {
+        let mut polars = self.scan(selection.archived, summary, selection.modified_since).await?;
+        polars.retain(|polar| selection.matches(polar));
+        Ok(polars)
+    }
+
+    /// Id, content hash and last modification of the polars of `selection`, without reading
+    /// their speed matrices. The hash of a derived polar covers its parents.
+    pub async fn manifest(&self, selection: &Selection) -> Result<Vec<ManifestEntry>> {
+        // parents outside of the selection count in the hashes too
+        let polars = self.scan(selection.archived, true, None).await?;
+        let files: HashMap<&str, (&str, Option<&str>)> = polars.iter()
+            .filter_map(|p| Some((p.id.as_deref()?, (p.file.as_ref()?.checksum.as_str(), p.extends.as_deref()))))
+            .collect();
+
+        let hash = |id: &str| {
+            let mut checksums = Vec::new();
+            let mut next = Some(id);
+            while let Some((checksum, parent)) = next.and_then(|id| files.get(id)).filter(|_| checksums.len() <= store::MAX_EXTENDS_DEPTH) {
+                checksums.push(*checksum);
+                next = *parent;
+            }
+            match checksums.as_slice() {
+                [checksum] => checksum.to_string(),
+                checksums => content_hash(checksums.concat().as_bytes()),
+            }
+        };
+        Ok(polars.iter()
+            .filter(|p| selection.matches(p))
+            .filter_map(|p| p.id.as_deref().map(|id| ManifestEntry { id: id.to_string(), hash: hash(id), modified: p.modified }))
+            .collect())
+    }
+
+    /// Drops the cached evaluation tables and rescans both directories, e.g. after files
+    /// were pushed to them.
+    pub async fn reload(&self) -> Result<Reload> {
+        self.tables.clear();
+        Ok(Reload {
+            polars: self.summaries(None).await?.len(),
+            archived: self.summaries(Some(true)).await?.len(),
+        })
+    }
+
+    /// Same as `list` but without parsing the speed matrices : `twa`, `tws` and `sail` are left empty.
+    pub async fn summaries(&self, archived: Option<bool>) -> Result<Vec<Polar>> {
+        self.scan(archived, true, None).await
+    }
+
+    /// Polars of the active or `archived` directories, only the files modified at or after
+    /// `modified_since` being read when given.
+    async fn scan(&self, archived: Option<bool>, summary: bool, modified_since: Option<u64>) -> Result<Vec<Polar>> {
+        let archived = archived == Some(true);
+        let sails = self.sails.list()?;
+        let winches = self.winches.list()?;
+        let files = self.store.files(archived, modified_since)?;
+
+        // the runtime worker hands its other tasks over while the files are read
+        blocking(|| self.parse_files(&files, archived, summary, &sails, &winches))
+    }
+
+    /// Polars of the `files`, parsed by contiguous chunks on scoped threads keeping their order.
+    /// A file failing to parse is left out, a panicking parse fails the whole scan.
+    fn parse_files(&self, files: &[(PathBuf, fs::Metadata)], archived: bool, summary: bool, sails: &[SailDefinition], winches: &[WinchProfile]) -> Result<Vec<Polar>> {
+        let workers = thread::available_parallelism().map_or(1, |n| n.get()).min(MAX_SCAN_WORKERS);
+        let chunk = files.len().div_ceil(workers).max(1);
+        thread::scope(|scope| {
+            let handles: Vec<_> = files.chunks(chunk)
+                .map(|files| scope.spawn(move || {
+                    let mut res = Vec::with_capacity(files.len());
+                    for (path, metadata) in files {
+                        match self.read_polar(path, summary) {
+                            Ok(None) => {}
+                            Ok(Some(mut polar)) => {
+                                polar.id = Some(path.file_prefix().unwrap().to_string_lossy().to_string());
+                                polar.archived = archived;
+                                polar.modified = Store::modified(metadata);
+                                polar.file = Store::checksum(path).ok().map(|checksum| FileMeta { size: metadata.len(), modified: polar.modified, checksum });
+                                polar.resolve_sails(sails);
+                                polar.resolve_winch(winches);
+                                res.push(polar);
+                            },
+                            Err(e) => {
+                                error!("Error reading file {:?} : {:?}", path, e);
+                            }
+                        }
+                    }
+                    res
+                }))
+                .collect();
+
+            let mut res = Vec::with_capacity(files.len());
+            for handle in handles {
+                res.extend(handle.join().map_err(|_| anyhow!("a worker panicked while reading the polar files"))?);
+            }
+            Ok(res)
+        })
+    }
+
+    pub async fn get(&self, polar_id: String) -> Result<Option<Polar>> {
+
+        let (path, archived) = match self.store.find_active(&polar_id) {
+            Some(path) => (path, false),
+            None => match self.store.find_archived(&polar_id) {
+                Some(path) => (path, true),
+                None => return Ok(None),
+            },
+        };
+
+        let modified = Store::modified(&fs::metadata(&path)?);
+        let polar = self.read_polar(&path, false)?;
+        let sails = self.sails.list()?;
+        let winches = self.winches.list()?;
+        let polar = polar.map(|mut r: Polar| {
+            r.id = Some(polar_id);
+            r.archived = archived;
+            r.modified = modified;
+            r.resolve_sails(&sails);
+            r.resolve_winch(&winches);
+            r
+        });
+        Ok(polar)
+    }
+
+    pub async fn find_by_label(&self, label: &str) -> Result<Option<Polar>> {
+
+        match self.list(None).await?.into_iter().find(|x| x.label == label) {
+            Some(polar) => Ok(Some(polar)),
+            None => {
+                Ok(self.list(Some(true)).await?.into_iter().find(|x| x.label == label))
+            },
+        }
+    }
+
+    /// `slug`, suffixed with `-2`, `-3`... when a polar, active or archived, already uses it or
+    /// when it is one of the `RESERVED_IDS`.
+    pub fn available_id(&self, slug: &str) -> String {
+        (1..)
+            .map(|i| if i == 1 { slug.to_string() } else { format!("{}-{}", slug, i) })
+            .find(|id| !RESERVED_IDS.contains(&id.as_str()) && self.store.path_of(id).is_none())
+            .unwrap_or_default()
+    }
+
+    /// Identity, content hash and file timestamps of a polar, without its matrices.
+    pub async fn meta(&self, polar_id: String) -> Result<Option<PolarMeta>> {
+        let Some(path) = self.store.path_of(&polar_id) else { return Ok(None) };
+        let size = fs::metadata(&path)?.len();
+        Ok(self.get(polar_id).await?.map(|polar| PolarMeta {
+            hash: polar.hash(),
+            id: polar.id,
+            polar_id: polar.polar_id,
+            label: polar.label,
+            archived: polar.archived,
+            modified: polar.modified,
+            size,
+        }))
+    }
+
+    /// File counts and sizes of the polar directories and of the catalog files.
+    pub async fn storage(&self) -> Result<store::StorageReport> {
+        self.store.storage(&[self.sails.path(), self.winches.path()])
+    }
+
+    pub async fn find_by_polar_id(&self, polar_id: u8) -> Result<Option<Polar>> {
+
+        match self.list(None).await?.into_iter().find(|x| x.polar_id == polar_id) {
+            Some(polar) => Ok(Some(polar)),
+            None => {
+                Ok(self.list(Some(true)).await?.into_iter().find(|x| x.polar_id == polar_id))
+            },
+        }
+    }
+
+    /// Reads the polar file at `path`, resolving its `extends` chain.
+    /// With `summary`, the speed matrices are skipped and the polar has no sails.
+    fn read_polar(&self, path: &Path, summary: bool) -> Result<Option<Polar>> {
+        let mut value = self.store.read_value(path, summary)?;
+        if value.is_null() {
+            return Ok(None);
+        }
+        if let (true, Some(map)) = (summary, value.as_object_mut()) {
+            for key in store::SUMMARY_SKIPPED {
+                map.insert(key.to_string(), Value::Array(Vec::new()));
+            }
+        }
+        let mut polar: Polar = serde_json::from_value(value)?;
+        // checking the signature needs the whole content, summaries are never verified
+        polar.verified = if summary { None } else { self.verified(path) };
+        polar.source = Some(path.to_path_buf());
+        Ok(Some(polar))
+    }
+
+    /// Polar file content `value` merged over its stored parent when it extends one.
+    pub fn resolve(&self, value: Value) -> Result<Value> {
+        self.store.resolve(value)
+    }
+
+    fn get_id(&self, polar: &Polar) -> Result<String> {
+        match &polar.id {
+            Some(id) => {
+                Ok(id.clone())
+            }
+            None => {
+                Err(PolarError::IdIsMandatory().into())
+            }
+        }
+    }
+
+    /// `polar` once its speeds are checked against its max speed policy, failing with the
+    /// offending fields when its speed matrices don't match its axes.
+    fn check<'a>(&self, polar: &'a Polar) -> Result<Cow<'a, Polar>> {
+        let errors = validate::validate(polar);
+        if !errors.is_empty() {
+            return Err(PolarError::Invalid(errors).into());
+        }
+        match polar.max_speed_policy.unwrap_or(self.max_speed_policy) {
+            MaxSpeedPolicy::Ignore => Ok(Cow::Borrowed(polar)),
+            MaxSpeedPolicy::Reject => {
+                let errors = validate::above_max_speed(polar);
+                if errors.is_empty() {
+                    Ok(Cow::Borrowed(polar))
+                } else {
+                    Err(PolarError::Invalid(errors).into())
+                }
+            }
+            MaxSpeedPolicy::Clamp => {
+                let mut clamped = polar.clone();
+                repair::repair(&mut clamped, &[Fix::ClampMaxSpeed]);
+                Ok(Cow::Owned(clamped))
+            }
+        }
+    }
+
+    /// `polar` as `import` would save it and what it would do with it when a polar of the same
+    /// id exists, failing as `import` would, without writing anything.
+    pub fn check_import(&self, polar: &Polar, on_conflict: OnConflict) -> Result<(Polar, ImportOutcome)> {
+        let mut polar = self.check(polar)?.into_owned();
+        let id = self.get_id(&polar)?;
+        let outcome = match (self.store.find_active(&id).is_some(), on_conflict) {
+            (false, _) => ImportOutcome::Created,
+            (true, OnConflict::Fail) => return Err(PolarError::AlreadyExists(id).into()),
+            (true, OnConflict::Skip) => ImportOutcome::Skipped,
+            (true, OnConflict::Overwrite) => ImportOutcome::Overwritten,
+            (true, OnConflict::Rename) => {
+                polar.id = Some(self.available_id(&id));
+                ImportOutcome::Renamed
+            }
+        };
+        if let Some(parent) = &polar.extends {
+            self.store.parent_value(parent)?;
+        }
+        Ok((polar, outcome))
+    }
+
+    /// Dry run of the import of `polar`, whose file had the `unknown` fields : the polar as it
+    /// would be saved, the outcome of the import and the lint findings. Unknown fields are
+    /// findings too, that reject the import in strict mode.
+    pub fn preview(&self, polar: &Polar, unknown: Vec<String>, on_conflict: OnConflict) -> Result<ImportPreview> {
+        let (polar, outcome) = self.check_import(polar, on_conflict)?;
+        let rejected = self.strict && !unknown.is_empty();
+        let mut findings: Vec<Finding> = unknown.into_iter().map(Finding::unknown_field).collect();
+        findings.extend(lint::lint(&polar).findings);
+        Ok(ImportPreview { polar, outcome, findings, rejected })
+    }
+
+    /// Creates `polar`, resolving a conflict with an existing polar of the same id with `on_conflict`.
+    pub async fn import(&self, polar: &Polar, on_conflict: OnConflict) -> Result<ImportResult> {
+        self.import_reserved(polar, on_conflict, None).await
+    }
+
+    /// Same as `import`, `polar` may have the `_id` of the `reservation` token.
+    pub async fn import_reserved(&self, polar: &Polar, on_conflict: OnConflict, reservation: Option<&str>) -> Result<ImportResult> {
+        let (polar, outcome) = self.check_import(polar, on_conflict)?;
+        let id = self.get_id(&polar)?;
+        match outcome {
+            ImportOutcome::Skipped => {}
+            ImportOutcome::Overwritten => self.update(id.clone(), &polar).await?,
+            ImportOutcome::Created | ImportOutcome::Renamed => self.create_reserved(&polar, reservation).await?,
+        }
+        Ok(ImportResult { id, outcome })
+    }
+
+    /// Same as `import_reserved` for a polar without `_id` yet, e.g. imported from another app :
+    /// it gets the `_id` of the existing polar of its id when overwriting it, the one of
+    /// `reservation` or a free one.
+    pub async fn import_new(&self, polar: &Polar, on_conflict: OnConflict, reservation: Option<&str>) -> Result<ImportResult> {
+        let mut polar = polar.clone();
+        let mut token = reservation.map(str::to_string);
+        let reserved = reservation.and_then(|token| self.reservations.reserved_id(token));
+        // a renamed polar is a new one, it can't share the `_id` of the existing one
+        let existing = match on_conflict {
+            OnConflict::Rename => None,
+            _ => self.get(self.get_id(&polar)?).await?,
+        };
+        match (existing, reserved) {
+            (Some(existing), _) => polar.polar_id = existing.polar_id,
+            (None, Some(polar_id)) => polar.polar_id = polar_id,
+            (None, None) => {
+                let reservation = self.reserve_id(CREATE_RESERVATION_TTL).await?;
+                polar.polar_id = reservation.polar_id;
+                token = Some(reservation.token);
+            }
+        }
+        self.import_reserved(&polar, on_conflict, token.as_deref()).await
+    }
+
+    /// Reserves the lowest `_id` neither used by a polar, archived ones included, nor reserved,
+    /// for `ttl` seconds, at most `MAX_RESERVATION_TTL`, or until a polar is created with it.
+    /// Only the holder of the returned token can create a polar with that `_id` meanwhile.
+    /// Reservations are kept in memory, they are lost on restart.
+    pub async fn reserve_id(&self, ttl: u64) -> Result<Reservation> {
+        let mut used: Vec<u8> = self.summaries(None).await?.iter().map(|p| p.polar_id).collect();
+        used.extend(self.summaries(Some(true)).await?.iter().map(|p| p.polar_id));
+        self.reservations.reserve(&used, ttl)
+    }
+
+    /// Creates the polars of `presets` with free `_id`s, keeping the existing polars of the same ids.
+    pub async fn seed(&self, presets: &[Preset]) -> Result<Vec<ImportResult>> {
+        let mut results = Vec::with_capacity(presets.len());
+        for preset in presets {
+            let mut polar = preset.polar();
+            let mut token = None;
+            if self.store.find_active(preset.id()).is_none() {
+                let reservation = self.reserve_id(CREATE_RESERVATION_TTL).await?;
+                polar.polar_id = reservation.polar_id;
+                token = Some(reservation.token);
+            }
+            results.push(self.import_reserved(&polar, OnConflict::Skip, token.as_deref()).await?);
+        }
+        Ok(results)
+    }
+
+    /// Creates the polars of `archive`, a gzip compressed tar of polar files as written by
+    /// `export::archive`, keeping the existing polars of the same ids. Only its regular `.yaml`
+    /// files are read, up to `MAX_SEED_SIZE` bytes once decompressed.
+    pub async fn seed_archive(&self, archive: &[u8]) -> Result<Vec<ImportResult>> {
+        let mut values = Vec::new();
+        let mut size = 0;
+        for entry in tar::Archive::new(GzDecoder::new(archive).take(MAX_SEED_SIZE)).entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() || entry.path()?.extension() != Some(OsStr::new("yaml")) {
+                continue;
+            }
+            size += entry.size();
+            if size > MAX_SEED_SIZE {
+                return Err(anyhow!("seed archive is larger than {} bytes", MAX_SEED_SIZE));
+            }
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            let mut value: Value = serde_yaml::from_str(&content)?;
+            migrate::migrate(&mut value);
+            values.push(value);
+        }
+        // parents are created before the polars extending them, at any depth
+        let values = parents_first(values, |value| value.get("id").and_then(Value::as_str), |value| value.get("extends").and_then(Value::as_str));
+
+        let mut results = Vec::with_capacity(values.len());
+        for value in values {
+            let polar = self.parse(value)?;
+            results.push(self.import(&polar, OnConflict::Skip).await?);
+        }
+        Ok(results)
+    }
+
+    /// Creates the variants of `base` fitted with each of the option combinations of `options`,
+    /// resolving conflicts with existing polars of the same ids with `on_conflict`. Combinations
+    /// of the same options, in any order, are created once.
+    pub async fn create_variants(&self, base: &Polar, options: &[Vec<BoatOption>], on_conflict: OnConflict) -> Result<Vec<ImportResult>> {
+        let mut results = Vec::with_capacity(options.len());
+        let mut created = BTreeSet::new();
+        for options in options {
+            let variant = base.variant(options);
+            if created.insert(variant.id.clone()) {
+                results.push(self.import_new(&variant, on_conflict, None).await?);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Creates `polar`, failing when its `_id` is reserved.
+    pub async fn create(&self, polar: &Polar) -> Result<()> {
+        self.create_reserved(polar, None).await
+    }
+
+    /// Creates `polar`, failing when its `_id` is reserved with another token than `reservation`.
+    pub async fn create_reserved(&self, polar: &Polar, reservation: Option<&str>) -> Result<()> {
+        let polar = self.check(polar)?;
+        let polar = polar.as_ref();
+        let id = self.get_id(polar)?;
+        self.reservations.check(polar.polar_id, reservation)?;
+        if self.store.find_active(&id).is_some() {
+            Err(PolarError::AlreadyExists(id).into())
+        } else {
+            let path = self.store.new_path(&id);
+            match self.save_polar(&path, polar) {
+                Ok(()) => {
+                    self.reservations.release(polar.polar_id);
+                    Ok(())
+                }
+                Err(e) => {
+                    error!("Error saving polar {:?} : {}", path, e);
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    /// Applies the field changes of `update` to the polars of its filter, or only reports what
+    /// they would be with `dry_run`. A polar failing a change is left unchanged.
+    pub async fn bulk_update(&self, update: &BulkUpdate, dry_run: bool) -> Result<Vec<BulkResult>> {
+        let mut results = Vec::new();
+        for polar in self.select(&update.filter, false).await? {
+            let id = polar.id.clone().unwrap_or_default();
+            let res = match transform::change_fields(&polar, &update.changes) {
+                Ok((changed, updates)) if !dry_run => self.update(id.clone(), &changed).await.map(|_| updates),
+                res => res.map(|(_, updates)| updates),
+            };
+            match res {
+                Ok(updates) => {
+                    if !dry_run {
+                        let paths: Vec<&str> = updates.iter().map(|u| u.path.as_str()).collect();
+                        if let Err(e) = self.audit.record("bulk-update", &id, paths.join(", ")) {
+                            error!("Error recording the bulk update of {} : {}", id, e);
+                        }
+                    }
+                    results.push(BulkResult { id, updates, error: None });
+                }
+                Err(e) => results.push(BulkResult { id, updates: Vec::new(), error: Some(e.to_string()) }),
+            }
+        }
+        Ok(results)
+    }
+
+    /// Changes the `_id` of the active polar `polar_id` to `new_polar_id`, which must be neither
+    /// used by another polar nor reserved, and records it in the audit log.
+    pub async fn remap(&self, polar_id: String, new_polar_id: u8) -> Result<Polar> {
+        let Some(mut polar) = self.get(polar_id.clone()).await?.filter(|p| !p.archived) else {
+            return Err(PolarError::NotFound(polar_id).into())
+        };
+        let old_polar_id = polar.polar_id;
+        if old_polar_id == new_polar_id {
+            return Ok(polar);
+        }
+
+        let mut polars = self.summaries(None).await?;
+        polars.extend(self.summaries(Some(true)).await?);
+        let reserved = self.reservations.check(new_polar_id, None).is_err();
+        if reserved || polars.iter().any(|p| p.polar_id == new_polar_id) {
+            return Err(PolarError::PolarIdTaken(new_polar_id).into());
+        }
+
+        polar.polar_id = new_polar_id;
+        self.update(polar_id.clone(), &polar).await?;
+        if let Err(e) = self.audit.record("remap", &polar_id, format!("_id {} -> {}", old_polar_id, new_polar_id)) {
+            error!("Error recording the remap of {} : {}", polar_id, e);
+        }
+        Ok(polar)
+    }
+
+    /// Creates `polar` with the lowest free `_id`, e.g. a polar built from others, returns it
+    /// as created.
+    pub async fn create_new(&self, polar: &Polar) -> Result<Polar> {
+        let mut polar = polar.clone();
+        let reservation = self.reserve_id(CREATE_RESERVATION_TTL).await?;
+        polar.polar_id = reservation.polar_id;
+        self.create_reserved(&polar, Some(&reservation.token)).await?;
+        Ok(polar)
+    }
+
+    /// Replaces the active polar `polar_id` by `polar`, renamed when its id changes, failing when
+    /// another polar, active or archived, already has the new id.
+    pub async fn update(&self, polar_id: String, polar: &Polar) -> Result<()> {
+        let polar = self.check(polar)?;
+        let polar = polar.as_ref();
+        let Some(old_path) = self.store.find_active(&polar_id) else {
+            return Err(PolarError::NotFound(polar_id).into())
+        };
+        let id = polar.id.as_ref().unwrap_or(&polar_id);
+        if *id != polar_id && self.store.path_of(id).is_some() {
+            return Err(PolarError::AlreadyExists(id.clone()).into());
+        }
+
+        // the file is renamed when the id changes, and its extension follows the compression setting
+        let path = self.store.new_path(id);
+        if let Err(e) = self.save_polar(&path, polar) {
+            error!("Error saving polar {:?} : {}", path, e);
+            return Err(e);
+        }
+        if old_path != path {
+            if let Err(e) = Store::remove(&old_path) {
+                error!("Error removing file {:?} : {}", old_path, e);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Updates the active polar `polar_id` as `update` does, keeping its previous file as a new
+    /// revision. Returns the number of that revision, `None` when the file is unchanged.
+    pub async fn revise(&self, polar_id: String, polar: &Polar) -> Result<Option<u32>> {
+        let Some(old_path) = self.store.find_active(&polar_id) else {
+            return Err(PolarError::NotFound(polar_id).into())
+        };
+        let previous = fs::read(&old_path)?;
+        self.update(polar_id.clone(), polar).await?;
+        let path = self.store.new_path(polar.id.as_ref().unwrap_or(&polar_id));
+        if fs::read(&path).is_ok_and(|bytes| bytes == previous) {
+            return Ok(None);
+        }
+        Ok(Some(self.store.save_revision(&polar_id, &old_path, &previous)?))
+    }
+
+    /// Revisions kept of the polar `polar_id`, oldest first.
+    pub async fn revisions(&self, polar_id: &str) -> Result<Vec<store::Revision>> {
+        self.store.revisions(polar_id)
+    }
+
+    /// Polar `polar_id` as it was at `revision`, its parent and catalogs being the current ones.
+    pub async fn get_revision(&self, polar_id: String, revision: u32) -> Result<Option<Polar>> {
+        let Some(path) = self.store.find_revision(&polar_id, revision) else { return Ok(None) };
+        let modified = Store::modified(&fs::metadata(&path)?);
+        let sails = self.sails.list()?;
+        let winches = self.winches.list()?;
+        Ok(self.read_polar(&path, false)?.map(|mut polar| {
+            polar.id = Some(polar_id);
+            polar.modified = modified;
+            polar.verified = None;
+            polar.resolve_sails(&sails);
+            polar.resolve_winch(&winches);
+            polar
+        }))
+    }
+
+    pub async fn delete(&self, polar_id: String) -> Result<()> {
+        let Some(path) = self.store.path_of(&polar_id) else {
+            return Err(PolarError::NotFound(polar_id).into())
+        };
+
+        match Store::remove(&path) {
+            Ok(_) => {
+                if let Some(dir) = &self.binary_dir {
+                    let binary = dir.join(format!("{}.{}", polar_id, BINARY_EXTENSION));
+                    let stamp = dir.join(format!("{}.{}{}", polar_id, BINARY_EXTENSION, STAMP_SUFFIX));
+                    for path in [stamp, binary] {
+                        if let Err(e) = fs::remove_file(&path) {
+                            if e.kind() != std::io::ErrorKind::NotFound {
+                                error!("Error removing file {:?} : {}", path, e);
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Err(e) => {
+                error!("Error removing file {:?} : {}", path, e);
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn archive(&self, polar_id: String) -> Result<()> {
+        match self.store.find_active(&polar_id) {
+            None => Err(PolarError::NotFound(polar_id).into()),
+            Some(path) => self.store.archive(&path),
+        }
+    }
+
+    pub async fn restore(&self, polar_id: String) -> Result<()> {
+        match self.store.find_archived(&polar_id) {
+            None => Err(PolarError::NotFound(polar_id).into()),
+            Some(_) if self.store.find_active(&polar_id).is_some() => Err(PolarError::AlreadyExists(polar_id).into()),
+            Some(archived) => self.store.restore(&archived),
+        }
+    }
+
+    /// Active polars neither used nor modified for `archive_inactive_days`, with the time they
+    /// are archived at, `archive_notice_days` after they were first reported. Default polars are
+    /// kept, and nothing is reported until the usage history covers the period. The first reports
+    /// are persisted, a polar used again is forgotten.
+    pub async fn inactive(&self) -> Result<Vec<InactivePolar>> {
+        let Some(days) = self.archive_inactive_days else { return Ok(Vec::new()) };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let period = days * 86_400;
+        if self.usage.history_start().is_none_or(|start| start + period > now) {
+            return Ok(Vec::new());
+        }
+
+        let last_used = self.usage.last_used();
+        let previous: BTreeMap<String, u64> = match fs::read(&self.reports_file) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(_) => BTreeMap::new(),
+        };
+        let mut reports = BTreeMap::new();
+        let mut inactive = Vec::new();
+        for polar in self.summaries(None).await? {
+            let Some(id) = polar.id else { continue };
+            let last_active = last_used.get(&id).copied().max(polar.modified).unwrap_or_default();
+            if polar.default.is_none() && last_active + period <= now {
+                let reported_at = previous.get(&id).copied().unwrap_or(now);
+                reports.insert(id.clone(), reported_at);
+                let archive_at = reported_at.saturating_add(self.archive_notice_days.saturating_mul(86_400));
+                inactive.push(InactivePolar { id, last_active, reported_at, archive_at });
+            }
+        }
+        if reports != previous {
+            fs::write(&self.reports_file, serde_json::to_vec(&reports)?)?;
+        }
+        inactive.sort_by_key(|p| p.archive_at);
+        Ok(inactive)
+    }
+
+    /// Archives the inactive polars whose notice period is over, returns their ids.
+    pub async fn archive_inactive(&self) -> Result<Vec<String>> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let mut archived = Vec::new();
+        for polar in self.inactive().await?.into_iter().filter(|p| p.archive_at <= now) {
+            self.archive(polar.id.clone()).await?;
+            archived.push(polar.id);
+        }
+        Ok(archived)
+    }
+
+    /// Whether the signature next to the polar file at `path` was made with a trusted key,
+    /// `None` when no key is trusted.
+    fn verified(&self, path: &Path) -> Option<bool> {
+        if self.trusted_keys.is_empty() {
+            return None;
+        }
+        let Ok(signature) = Store::signature(path) else { return Some(false) };
+        match self.signed_content(path).and_then(|content| signature::verify(&content, &signature, &self.trusted_keys)) {
+            Ok(verified) => Some(verified),
+            Err(e) => {
+                error!("Error verifying the signature of {:?} : {}", path, e);
+                Some(false)
+            }
+        }
+    }
+
+    /// Content covered by the signature of the polar file at `path` : the canonical YAML of
+    /// the effective polar, merged over its parents with the matrices of the matrix store
+    /// inlined, its sails named from the sail catalog and its winch profile resolved. Changing a
+    /// parent or a catalog entry the polar relies on invalidates its signature.
+    fn signed_content(&self, path: &Path) -> Result<Vec<u8>> {
+        let mut polar: Polar = serde_json::from_value(self.store.read_value(path, false)?)?;
+        polar.resolve_sails(&self.sails.list()?);
+        polar.resolve_winch(&self.winches.list()?);
+        Ok(canonical_yaml(&serde_json::to_value(&polar)?)?.into_bytes())
+    }
+
+    /// Attaches the hex encoded detached `signature` to the file of `polar_id`, it must be
+    /// made with one of the trusted keys.
+    pub async fn attach_signature(&self, polar_id: String, signature: &str) -> Result<()> {
+        let Some(path) = self.store.path_of(&polar_id) else {
+            return Err(PolarError::NotFound(polar_id).into())
+        };
+        let content = self.signed_content(&path)?;
+        if !signature::verify(&content, signature, &self.trusted_keys).unwrap_or(false) {
+            return Err(PolarError::InvalidSignature(polar_id).into());
+        }
+        Store::write_signature(&path, signature)
+    }
+
+    /// Signs the file of `polar_id` with the hex encoded secret `key` and attaches the signature,
+    /// returned hex encoded.
+    pub async fn sign(&self, polar_id: String, key: &str) -> Result<String> {
+        let Some(path) = self.store.path_of(&polar_id) else {
+            return Err(PolarError::NotFound(polar_id).into())
+        };
+        let signature = signature::sign(&self.signed_content(&path)?, &signature::signing_key(key)?);
+        Store::write_signature(&path, &signature)?;
+        Ok(signature)
+    }
+
+    /// Default polar of `class`, falling back to the global default.
+    pub async fn get_default(&self, class: Option<&str>) -> Result<Option<Polar>> {
+        let mut polars = self.list(None).await?;
+        let class_default = class.and_then(|class| polars.iter()
+            .position(|p| p.default == Some(DefaultScope::Class) && p.class.as_deref() == Some(class)));
+        let position = class_default.or_else(|| polars.iter().position(|p| p.default == Some(DefaultScope::Global)));
+        Ok(position.map(|i| polars.swap_remove(i)))
+    }
+
+    /// Makes `polar_id` the default polar of `scope`, replacing the previous one. Polars are
+    /// saved as by `update`, the new default first.
+    pub async fn set_default(&self, polar_id: String, scope: DefaultScope) -> Result<()> {
+        let mut polars = self.list(None).await?;
+        let Some(position) = polars.iter().position(|p| p.id.as_ref() == Some(&polar_id)) else {
+            return Err(PolarError::NotFound(polar_id).into())
+        };
+        let mut polar = polars.swap_remove(position);
+        let class = polar.class.clone();
+        if polar.default != Some(scope) {
+            polar.default = Some(scope);
+            self.update(polar_id, &polar).await?;
+        }
+
+        for mut polar in polars {
+            if polar.default == Some(scope) && (scope == DefaultScope::Global || polar.class == class) {
+                polar.default = None;
+                self.update(polar.id.clone().unwrap_or_default(), &polar).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes a sail definition, refused while a sail of a polar, active or archived, still
+    /// references it.
+    pub async fn delete_sail(&self, sail_id: String) -> Result<()> {
+        if self.sails.get(&sail_id)?.is_none() {
+            return Err(PolarError::NotFound(format!("sail {}", sail_id)).into());
+        }
+
+        let mut users = Vec::new();
+        for archived in [false, true] {
+            users.extend(self.list(Some(archived)).await?.into_iter()
+                .filter(|p| p.sail.iter().any(|s| s.catalog.as_ref() == Some(&sail_id)))
+                .filter_map(|p| p.id));
+        }
+        if !users.is_empty() {
+            return Err(PolarError::InUse(format!("sail {} is used by {}", sail_id, users.join(", "))).into());
+        }
+        self.sails.delete(&sail_id)
+    }
+
+    /// Deletes a winch profile, refused while a polar, active or archived, still references it.
+    pub async fn delete_winch(&self, name: String) -> Result<()> {
+        if self.winches.get(&name)?.is_none() {
+            return Err(PolarError::NotFound(format!("winch {}", name)).into());
+        }
+
+        let mut users = Vec::new();
+        for archived in [false, true] {
+            users.extend(self.list(Some(archived)).await?.into_iter()
+                .filter(|p| p.winch_profile.as_ref() == Some(&name))
+                .filter_map(|p| p.id));
+        }
+        if !users.is_empty() {
+            return Err(PolarError::InUse(format!("winch {} is used by {}", name, users.join(", "))).into());
+        }
+        self.winches.delete(&name)
+    }
+
+    fn save_polar(&self, path: &Path, polar: &Polar) -> Result<()> {
+
+        let mut polar = Cow::Borrowed(polar);
+        if polar.schema_version != SCHEMA_VERSION {
+            polar.to_mut().schema_version = SCHEMA_VERSION;
+        }
+        if let Some(precision) = self.precision {
+            polar.to_mut().round(precision);
+        }
+        if polar.verified.is_some() || polar.file.is_some() {
+            polar.to_mut().verified = None;
+            polar.to_mut().file = None;
+        }
+        // a referenced winch profile is not copied in the file
+        if polar.winch_profile.is_some() && polar.winch.is_some() {
+            polar.to_mut().winch = None;
+        }
+        self.store.write_value(path, serde_json::to_value(polar.as_ref())?)
+    }
+}
+
+/// File of the first reports of the inactive polars in the polars directory.
+const REPORTS_FILE: &str = ".inactive.json";
+
+/// Extension of the binary effective polars in `binary_dir`.
+const BINARY_EXTENSION: &str = "npol";
+
+/// Suffix of the list of files a binary effective polar was made from.
+const STAMP_SUFFIX: &str = ".src";
+
+/// `items` ordered so that polars come after the polars of `items` they extend, at any depth,
+/// keeping their order otherwise, e.g. to import them. `id` and `parent` give the id of an item
+/// and of the polar it extends. Items of an `extends` cycle come last.
+pub fn parents_first<T>(items: Vec<T>, id: impl Fn(&T) -> Option<&str>, parent: impl Fn(&T) -> Option<&str>) -> Vec<T> {
+    let ids: BTreeSet<String> = items.iter().filter_map(|item| id(item).map(String::from)).collect();
+    let mut placed = BTreeSet::new();
+    let mut res = Vec::with_capacity(items.len());
+    let mut pending = items;
+    while !pending.is_empty() {
+        let (ready, rest): (Vec<T>, Vec<T>) = pending.into_iter()
+            .partition(|item| parent(item).is_none_or(|parent| !ids.contains(parent) || placed.contains(parent)));
+        if ready.is_empty() {
+            res.extend(rest);
+            break;
+        }
+        placed.extend(ready.iter().filter_map(|item| id(item).map(String::from)));
+        res.extend(ready);
+        pending = rest;
+    }
+    res
+}
+
+/// Upper bound of the threads parsing polar files when listing a directory.
+const MAX_SCAN_WORKERS: usize = 8;
+
+/// Default days between the report of an inactive polar and its archiving.
+const ARCHIVE_NOTICE_DAYS: u64 = 7;
+
+/// Seconds the `_id` of a polar created by the service, e.g. seeded, is reserved while it is created.
+const CREATE_RESERVATION_TTL: u64 = 60;
+
+/// Largest decompressed seed archive, in bytes.
+const MAX_SEED_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Ids shadowed by the routes under `/polars/`, e.g. `GET /polars/count`, never given to a polar
+/// from its label.
+const RESERVED_IDS: [&str; 15] = ["blend", "bulk-update", "compare", "count", "default", "evaluate-scenario", "export", "from-template", "import", "manifest", "merge", "rank", "regrid", "reserve-id", "stats"];
+
+/// Filters of the polars listed, counted, exported or updated, all the active polars when empty.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "rocket", derive(FromForm))]
+#[serde(rename_all = "camelCase")]
+pub struct Selection {
+    /// archived polars instead of the active ones
+    pub archived: Option<bool>,
+    pub tag: Option<String>,
+    pub class: Option<String>,
+    /// comma separated ids
+    pub ids: Option<String>,
+    /// polars modified at or after this time only, in seconds since epoch
+    pub modified_since: Option<u64>,
+}
+
+impl Selection {
+    pub fn matches(&self, polar: &Polar) -> bool {
+        self.tag.as_ref().is_none_or(|tag| polar.tags.contains(tag))
+            && self.class.as_ref().is_none_or(|class| polar.class.as_ref() == Some(class))
+            && self.ids.as_ref().is_none_or(|ids| {
+                polar.id.as_ref().is_some_and(|id| ids.split(',').any(|i| i.trim() == id))
+            })
+            && self.modified_since.is_none_or(|since| polar.modified.is_some_and(|m| m >= since))
+    }
+}
+
+/// Field the polars can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    Id,
+    PolarId,
+    Label,
+    Class,
+    MaxSpeed,
+    Modified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+impl SortKey {
+    /// Keys of the comma separated `sort_by`, e.g. `-maxSpeed,id`, a `-` prefix making a key
+    /// descending and a `+` prefix ascending. Keys without prefix are descending when
+    /// `descending`. `None` when a key is unknown.
+    pub fn parse_list(sort_by: &str, descending: bool) -> Option<Vec<SortKey>> {
+        sort_by.split(',').map(str::trim).filter(|key| !key.is_empty()).map(|key| {
+            let (name, descending) = match key.strip_prefix('-') {
+                Some(name) => (name, true),
+                None => key.strip_prefix('+').map_or((key, descending), |name| (name, false)),
+            };
+            let field = match name {
+                "id" => SortField::Id,
+                "_id" | "polarId" => SortField::PolarId,
+                "label" => SortField::Label,
+                "class" => SortField::Class,
+                "maxSpeed" => SortField::MaxSpeed,
+                "modified" => SortField::Modified,
+                _ => return None,
+            };
+            Some(SortKey { field, descending })
+        }).collect()
+    }
+
+    fn compare(&self, a: &Polar, b: &Polar) -> std::cmp::Ordering {
+        let ordering = match self.field {
+            SortField::Id => a.id.cmp(&b.id),
+            SortField::PolarId => a.polar_id.cmp(&b.polar_id),
+            SortField::Label => a.label.cmp(&b.label),
+            SortField::Class => a.class.cmp(&b.class),
+            SortField::MaxSpeed => a.max_speed.total_cmp(&b.max_speed),
+            SortField::Modified => a.modified.cmp(&b.modified),
+        };
+        if self.descending { ordering.reverse() } else { ordering }
+    }
+}
+
+/// Sorts `polars` by `keys` in turn, then by id so that polars equal on every key always
+/// come in the same order.
+pub fn sort(polars: &mut [Polar], keys: &[SortKey]) {
+    polars.sort_by(|a, b| {
+        keys.iter()
+            .map(|key| key.compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.id.cmp(&b.id))
+    });
+}
+
+/// What an import does when a polar of the same id exists.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "rocket", derive(FromFormField))]
+#[serde(rename_all = "camelCase")]
+pub enum OnConflict {
+    /// keeps the existing polar
+    Skip,
+    Overwrite,
+    /// imports under the next available id
+    Rename,
+    #[default]
+    Fail,
+}
+
+impl OnConflict {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "skip" => Some(OnConflict::Skip),
+            "overwrite" => Some(OnConflict::Overwrite),
+            "rename" => Some(OnConflict::Rename),
+            "fail" => Some(OnConflict::Fail),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportOutcome {
+    Created,
+    Skipped,
+    Overwritten,
+    /// created under another id, the existing polar having its id
+    Renamed,
+}
+
+/// Id of an imported polar and what the import did.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResult {
+    pub id: String,
+    pub outcome: ImportOutcome,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    pub id: String,
+    pub hash: String,
+    /// in seconds since epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+}
+
+/// Runs the blocking `f` without stalling the other tasks of the async runtime when served,
+/// which must be the multi-threaded runtime of Rocket.
+#[cfg(feature = "rocket")]
+fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    rocket::tokio::task::block_in_place(f)
+}
+
+#[cfg(not(feature = "rocket"))]
+fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+/// Number of the current thread id, distinguishing the temporary files of concurrent writers.
+fn thread_id() -> String {
+    format!("{:?}", thread::current().id()).chars().filter(char::is_ascii_digit).collect()
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PolarMeta {
+    pub id: Option<String>,
+    #[serde(rename = "_id")]
+    pub polar_id: u8,
+    pub label: String,
+    pub archived: bool,
+    pub hash: String,
+    /// size of the file, in bytes
+    pub size: u64,
+    pub modified: Option<u64>,
+}
+
+/// Result of an import run with `dry_run`, nothing being written.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportPreview {
+    pub polar: Polar,
+    pub outcome: ImportOutcome,
+    pub findings: Vec<Finding>,
+    /// whether the import would be rejected for its unknown fields, in strict mode
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub rejected: bool,
+}
+
+/// Active polar unused since `last_active`, first reported at `reported_at` and archived at
+/// `archive_at`, in seconds since epoch.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InactivePolar {
+    pub id: String,
+    pub last_active: u64,
+    pub reported_at: u64,
+    pub archive_at: u64,
+}
+
+/// Number of readable polars found by a reload.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Reload {
+    pub polars: usize,
+    pub archived: usize,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::test_polar;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    /// Empty directory of its own, removed first when left by a previous run.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nav-polars-service-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn service_in(dir: &Path) -> PolarService {
+        PolarService::new(dir.join("polars"), dir.join("archived"))
+            .sails_file(Some(dir.join("sails.yaml")))
+            .winches_file(Some(dir.join("winches.yaml")))
+    }
+
+    fn service(name: &str) -> PolarService {
+        service_in(&test_dir(name))
+    }
+
+    /// Runs `future` to completion on the test thread, the service never waits on anything.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut context = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    fn polar_error(error: &anyhow::Error) -> Option<&PolarError> {
+        error.downcast_ref::<PolarError>()
+    }
+
+    #[test]
+    fn available_ids_avoid_the_routes_and_the_used_ids() {
+        let service = service("available");
+        assert_eq!(service.available_id("dufour"), "dufour");
+        assert_eq!(service.available_id("count"), "count-2");
+        assert_eq!(service.available_id("reserve-id"), "reserve-id-2");
+
+        block_on(service.create(&test_polar("dufour", 2, 10, 4))).unwrap();
+        assert_eq!(service.available_id("dufour"), "dufour-2");
+    }
+
+    #[test]
+    fn an_update_cannot_take_the_id_of_another_polar() {
+        let service = service("update");
+        block_on(service.create(&test_polar("first", 2, 10, 4))).unwrap();
+        let mut second = test_polar("second", 2, 10, 4);
+        second.polar_id = 2;
+        block_on(service.create(&second)).unwrap();
+
+        second.id = Some("first".to_string());
+        let error = block_on(service.update("second".to_string(), &second)).unwrap_err();
+        assert!(matches!(polar_error(&error), Some(PolarError::AlreadyExists(id)) if id == "first"));
+        assert!(service.store.find_active("second").is_some());
+
+        second.id = Some("third".to_string());
+        block_on(service.update("second".to_string(), &second)).unwrap();
+        assert!(service.store.find_active("second").is_none());
+        assert!(service.store.find_active("third").is_some());
+    }
+
+    /// Polar with a speed above its `max_speed`.
+    fn too_fast(policy: Option<MaxSpeedPolicy>) -> Polar {
+        let mut polar = test_polar("fast", 2, 10, 4);
+        polar.sail[0].speed[2][4] = polar.max_speed + 1.0;
+        polar.max_speed_policy = policy;
+        polar
+    }
+
+    #[test]
+    fn applies_the_max_speed_policies() {
+        let service = service("max-speed");
+        assert!(service.check_import(&too_fast(None), OnConflict::Fail).is_ok());
+
+        let error = service.check_import(&too_fast(Some(MaxSpeedPolicy::Reject)), OnConflict::Fail).unwrap_err();
+        assert!(matches!(polar_error(&error), Some(PolarError::Invalid(errors)) if !errors.is_empty()));
+
+        let (clamped, _) = service.check_import(&too_fast(Some(MaxSpeedPolicy::Clamp)), OnConflict::Fail).unwrap();
+        assert!(clamped.sail.iter().flat_map(|s| s.speed.iter().flatten()).all(|&speed| speed <= clamped.max_speed));
+    }
+
+    #[test]
+    fn the_polar_policy_overrides_the_service_one() {
+        let service = service("max-speed-policy").max_speed_policy(MaxSpeedPolicy::Reject);
+        assert!(service.check_import(&too_fast(None), OnConflict::Fail).is_err());
+        assert!(service.check_import(&too_fast(Some(MaxSpeedPolicy::Ignore)), OnConflict::Fail).is_ok());
+    }
+
+    #[test]
+    fn reads_and_writes_compressed_files() {
+        let dir = test_dir("gzip");
+        let compressed = service_in(&dir).compress(true).compress_archived(true);
+        let polar = test_polar("gzip", 2, 10, 4);
+        block_on(compressed.create(&polar)).unwrap();
+        assert_eq!(compressed.store.find_active("gzip"), Some(dir.join("polars/gzip.yaml.gz")));
+        let read = block_on(compressed.get("gzip".to_string())).unwrap().unwrap();
+        assert_eq!(read.sail[1].speed, polar.sail[1].speed);
+
+        // the file of an update follows the compression setting
+        let plain = service_in(&dir);
+        block_on(plain.update("gzip".to_string(), &read)).unwrap();
+        assert_eq!(plain.store.find_active("gzip"), Some(dir.join("polars/gzip.yaml")));
+        assert!(!dir.join("polars/gzip.yaml.gz").exists());
+
+        block_on(compressed.archive("gzip".to_string())).unwrap();
+        assert_eq!(compressed.store.find_archived("gzip"), Some(dir.join("archived/gzip.yaml.gz")));
+        assert!(block_on(plain.get("gzip".to_string())).unwrap().is_some_and(|p| p.archived));
+    }
+
+    #[test]
+    fn resolves_import_conflicts() {
+        let service = service("import");
+        let polar = test_polar("imported", 2, 10, 4);
+        let result = block_on(service.import(&polar, OnConflict::Fail)).unwrap();
+        assert_eq!((result.id.as_str(), result.outcome), ("imported", ImportOutcome::Created));
+
+        let mut other = polar.clone();
+        other.label = "Other".to_string();
+        let error = block_on(service.import(&other, OnConflict::Fail)).unwrap_err();
+        assert!(matches!(polar_error(&error), Some(PolarError::AlreadyExists(id)) if id == "imported"));
+
+        let result = block_on(service.import(&other, OnConflict::Skip)).unwrap();
+        assert_eq!(result.outcome, ImportOutcome::Skipped);
+        assert_eq!(block_on(service.get("imported".to_string())).unwrap().unwrap().label, polar.label);
+
+        let result = block_on(service.import(&other, OnConflict::Overwrite)).unwrap();
+        assert_eq!(result.outcome, ImportOutcome::Overwritten);
+        assert_eq!(block_on(service.get("imported".to_string())).unwrap().unwrap().label, "Other");
+
+        other.polar_id = 2;
+        let result = block_on(service.import(&other, OnConflict::Rename)).unwrap();
+        assert_eq!((result.id.as_str(), result.outcome), ("imported-2", ImportOutcome::Renamed));
+        assert!(service.store.find_active("imported").is_some());
+    }
+
+    #[test]
+    fn parses_sort_keys() {
+        let keys = SortKey::parse_list(" -maxSpeed, +label,_id ,", true).unwrap();
+        assert_eq!(keys, vec![
+            SortKey { field: SortField::MaxSpeed, descending: true },
+            SortKey { field: SortField::Label, descending: false },
+            SortKey { field: SortField::PolarId, descending: true },
+        ]);
+        assert_eq!(SortKey::parse_list("", false), Some(Vec::new()));
+        assert_eq!(SortKey::parse_list("id,speed", false), None);
+    }
+
+    #[test]
+    fn sorts_by_the_keys_then_by_id() {
+        let mut polars: Vec<Polar> = [("c", 20.0), ("a", 30.0), ("b", 20.0), ("d", 30.0)].into_iter().map(|(id, max_speed)| {
+            let mut polar = test_polar(id, 2, 10, 4);
+            polar.max_speed = max_speed;
+            polar
+        }).collect();
+        sort(&mut polars, &SortKey::parse_list("-maxSpeed", false).unwrap());
+        let ids: Vec<_> = polars.iter().filter_map(|p| p.id.as_deref()).collect();
+        assert_eq!(ids, ["a", "d", "b", "c"]);
+    }
+
+    #[test]
+    fn parents_come_first_at_any_depth() {
+        let items = vec![("c", Some("b")), ("d", None), ("b", Some("a")), ("e", Some("missing")), ("a", None)];
+        let sorted = parents_first(items, |item| Some(item.0), |item| item.1);
+        let position = |id: &str| sorted.iter().position(|item| item.0 == id).unwrap();
+        assert_eq!(sorted.len(), 5);
+        assert!(position("a") < position("b") && position("b") < position("c"));
+    }
+
+    #[test]
+    fn parents_first_keeps_cycles() {
+        let items = vec![("a", Some("b")), ("b", Some("a")), ("c", None)];
+        let sorted = parents_first(items, |item| Some(item.0), |item| item.1);
+        assert_eq!(sorted.iter().map(|item| item.0).collect::<Vec<_>>(), ["c", "a", "b"]);
+    }
+
+    #[test]
+    fn reserved_ids_skip_the_used_ones() {
+        let service = service("reserve");
+        block_on(service.create(&test_polar("first", 2, 10, 4))).unwrap();
+        let first = block_on(service.reserve_id(60)).unwrap();
+        let second = block_on(service.reserve_id(60)).unwrap();
+        assert_eq!((first.polar_id, second.polar_id), (2, 3));
+    }
+
+    #[test]
+    fn a_reserved_id_needs_its_token() {
+        let service = service("token");
+        let reservation = block_on(service.reserve_id(60)).unwrap();
+        let mut polar = test_polar("reserved", 2, 10, 4);
+        polar.polar_id = reservation.polar_id;
+
+        let error = block_on(service.create(&polar)).unwrap_err();
+        assert!(matches!(polar_error(&error), Some(PolarError::PolarIdTaken(id)) if *id == reservation.polar_id));
+        block_on(service.create_reserved(&polar, Some(&reservation.token))).unwrap();
+        let created = block_on(service.get("reserved".to_string())).unwrap().unwrap();
+        assert_eq!(created.polar_id, reservation.polar_id);
+        assert!(service.reservations.check(reservation.polar_id, None).is_ok());
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::{anyhow, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+/// Public key from its hex encoding.
+pub fn verifying_key(hex: &str) -> Result<VerifyingKey> {
+    let bytes = hex::decode(hex.trim())?;
+    Ok(VerifyingKey::try_from(bytes.as_slice())?)
+}
+
+/// Secret key from the hex encoding of its 32 bytes seed.
+pub fn signing_key(hex: &str) -> Result<SigningKey> {
+    let bytes = hex::decode(hex.trim())?;
+    Ok(SigningKey::try_from(bytes.as_slice())?)
+}
+
+/// Hex encoded detached signature of `content`.
+pub fn sign(content: &[u8], key: &SigningKey) -> String {
+    hex::encode(key.sign(content).to_bytes())
+}
+
+/// Whether the hex encoded `signature` of `content` was made with one of the `keys`.
+pub fn verify(content: &[u8], signature: &str, keys: &[VerifyingKey]) -> Result<bool> {
+    let bytes = hex::decode(signature.trim())?;
+    let signature = Signature::try_from(bytes.as_slice()).map_err(|_| anyhow!("a signature is 64 bytes long"))?;
+    Ok(keys.iter().any(|key| key.verify(content, &signature).is_ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+
+    #[test]
+    fn verifies_its_own_signatures() {
+        let key = signing_key(SEED).unwrap();
+        let signature = sign(b"polar", &key);
+        assert!(verify(b"polar", &signature, &[key.verifying_key()]).unwrap());
+        assert!(!verify(b"polar 2", &signature, &[key.verifying_key()]).unwrap());
+    }
+
+    #[test]
+    fn verifies_with_any_trusted_key() {
+        let key = signing_key(SEED).unwrap();
+        let other = signing_key(&"11".repeat(32)).unwrap();
+        let signature = sign(b"polar", &key);
+        assert!(!verify(b"polar", &signature, &[other.verifying_key()]).unwrap());
+        assert!(verify(b"polar", &signature, &[other.verifying_key(), key.verifying_key()]).unwrap());
+        assert!(!verify(b"polar", &signature, &[]).unwrap());
+    }
+
+    #[test]
+    fn reads_keys_and_signatures_as_hex() {
+        let key = signing_key(SEED).unwrap();
+        let public = verifying_key(&hex::encode(key.verifying_key().to_bytes())).unwrap();
+        let signature = format!(" {}\n", sign(b"polar", &key));
+        assert!(verify(b"polar", &signature, &[public]).unwrap());
+        assert!(verify(b"polar", "00ff", &[public]).is_err());
+        assert!(signing_key("not hex").is_err());
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::compute::{self, Options};
+use crate::matrix::{self, Extrapolation, Interpolation};
+use crate::polar::Polar;
+use crate::units::{SpeedFormat, Units};
+use crate::usage::Counter;
+
+/// Width of the max speed histogram buckets, in knots.
+const SPEED_BUCKET: f64 = 5.0;
+
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogStats {
+    pub total: usize,
+    pub active: usize,
+    pub archived: usize,
+    pub by_class: BTreeMap<String, usize>,
+    pub by_tag: BTreeMap<String, usize>,
+    pub max_speed: Distribution,
+    /// number of polars per grid size, keyed by `<twa count>x<tws count>`
+    pub grid_sizes: BTreeMap<String, usize>,
+    /// number of polars per sail count
+    pub sail_counts: BTreeMap<usize, usize>,
+    pub oldest_update: Option<u64>,
+    pub last_update: Option<u64>,
+}
+
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Distribution {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    /// number of values per bucket, keyed by the bucket lower bound
+    pub histogram: BTreeMap<u32, usize>,
+}
+
+impl Distribution {
+    fn new(values: &[f64], bucket: f64) -> Self {
+        if values.is_empty() {
+            return Distribution::default();
+        }
+
+        let mut histogram = BTreeMap::new();
+        for v in values {
+            let lower = ((v / bucket).floor() * bucket) as u32;
+            *histogram.entry(lower).or_insert(0) += 1;
+        }
+
+        Distribution {
+            min: values.iter().copied().reduce(f64::min),
+            max: values.iter().copied().reduce(f64::max),
+            mean: Some(values.iter().sum::<f64>() / values.len() as f64),
+            histogram,
+        }
+    }
+}
+
+pub fn catalog(polars: &[Polar]) -> CatalogStats {
+    let mut stats = CatalogStats {
+        total: polars.len(),
+        ..Default::default()
+    };
+
+    for polar in polars {
+        if polar.archived {
+            stats.archived += 1;
+        } else {
+            stats.active += 1;
+        }
+        if let Some(class) = &polar.class {
+            *stats.by_class.entry(class.clone()).or_insert(0) += 1;
+        }
+        for tag in &polar.tags {
+            *stats.by_tag.entry(tag.clone()).or_insert(0) += 1;
+        }
+        *stats.grid_sizes.entry(format!("{}x{}", polar.twa.len(), polar.tws.len())).or_insert(0) += 1;
+        *stats.sail_counts.entry(polar.sail.len()).or_insert(0) += 1;
+    }
+
+    let max_speeds: Vec<f64> = polars.iter().map(|p| p.max_speed).collect();
+    stats.max_speed = Distribution::new(&max_speeds, SPEED_BUCKET);
+    stats.oldest_update = polars.iter().filter_map(|p| p.modified).min();
+    stats.last_update = polars.iter().filter_map(|p| p.modified).max();
+
+    stats
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PolarStats {
+    pub units: Units,
+    pub max_speed: Option<SpeedPoint>,
+    pub vmg: Vec<VmgStats>,
+    pub sails: Vec<SailRanges>,
+    /// set by the stats endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Counter>,
+}
+
+impl PolarStats {
+    pub fn format(&mut self, format: SpeedFormat) {
+        self.units = format.units;
+        if let Some(max_speed) = self.max_speed.as_mut() {
+            max_speed.speed = format.apply(max_speed.speed);
+        }
+        for vmg in self.vmg.iter_mut() {
+            vmg.upwind.format(format);
+            vmg.downwind.format(format);
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeedPoint {
+    pub speed: f64,
+    pub twa: f64,
+    pub tws: f64,
+    pub sail: u8,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VmgStats {
+    pub tws: f64,
+    pub upwind: Vmg,
+    pub downwind: Vmg,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct Vmg {
+    pub twa: f64,
+    pub speed: f64,
+    pub vmg: f64,
+    pub sail: u8,
+}
+
+impl Vmg {
+    fn format(&mut self, format: SpeedFormat) {
+        self.speed = format.apply(self.speed);
+        self.vmg = format.apply(self.vmg);
+    }
+}
+
+/// Twa ranges where a sail is the fastest one, per tws of the grid.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SailRanges {
+    pub sail: u8,
+    pub name: String,
+    pub ranges: Vec<TwaRange>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TwaRange {
+    pub tws: f64,
+    pub twa_from: f64,
+    pub twa_to: f64,
+}
+
+/// Twa resolution used when sweeping angles, in degrees.
+const TWA_STEP: f64 = 1.0;
+
+/// Angles from the first to the last twa of the grid, every `TWA_STEP` degree.
+pub fn sweep(polar: &Polar) -> Vec<f64> {
+    let (Some(first), Some(last)) = (polar.twa.first(), polar.twa.last()) else { return Vec::new() };
+    let (first, last) = (*first as f64, *last as f64);
+    (0..).map(|i| first + i as f64 * TWA_STEP).take_while(|twa| *twa <= last).collect()
+}
+
+/// Best upwind and downwind vmg at `tws`.
+pub fn vmg(polar: &Polar, tws: f64) -> Option<(Vmg, Vmg)> {
+    vmg_interpolated(polar, tws, Extrapolation::Clamp, Interpolation::Bilinear)
+}
+
+/// Best upwind and downwind vmg at `tws`, speeds being interpolated with `method` and
+/// extrapolated as `extrapolation` tells, `None` off the grid with `Error`.
+pub fn vmg_interpolated(polar: &Polar, tws: f64, extrapolation: Extrapolation, method: Interpolation) -> Option<(Vmg, Vmg)> {
+    if extrapolation == Extrapolation::Error && !matrix::contains(&polar.tws, tws) {
+        return None;
+    }
+    let mut upwind: Option<Vmg> = None;
+    let mut downwind: Option<Vmg> = None;
+
+    for twa in sweep(polar) {
+        let Some((sail, speed)) = polar.best_sail_interpolated(twa, tws, extrapolation, method) else { continue };
+        let speed = speed * polar.global_speed_ratio;
+        let vmg = speed * twa.to_radians().cos();
+        let point = Vmg { twa, speed, vmg: vmg.abs(), sail };
+        if vmg >= 0.0 && upwind.is_none_or(|u| point.vmg > u.vmg) {
+            upwind = Some(point);
+        }
+        if vmg <= 0.0 && downwind.is_none_or(|d| point.vmg > d.vmg) {
+            downwind = Some(point);
+        }
+    }
+
+    upwind.zip(downwind)
+}
+
+/// Statistics of `polar`, speeds between the grid points being interpolated with `method` and
+/// extrapolated as `extrapolation` tells.
+pub fn polar_stats(polar: &Polar, extrapolation: Extrapolation, method: Interpolation) -> PolarStats {
+    let mut max_speed: Option<SpeedPoint> = None;
+    for twa in &polar.twa {
+        for tws in &polar.tws {
+            let (twa, tws) = (*twa as f64, *tws as f64);
+            if let Some((sail, speed)) = polar.best_sail(twa, tws) {
+                let speed = speed * polar.global_speed_ratio;
+                if max_speed.is_none_or(|m| speed > m.speed) {
+                    max_speed = Some(SpeedPoint { speed, twa, tws, sail });
+                }
+            }
+        }
+    }
+
+    let vmg = polar.tws.iter()
+        .filter_map(|tws| vmg_interpolated(polar, *tws as f64, extrapolation, method).map(|(upwind, downwind)| VmgStats { tws: *tws as f64, upwind, downwind }))
+        .collect();
+
+    let mut sails: Vec<SailRanges> = polar.sail.iter()
+        .map(|s| SailRanges { sail: s.id, name: s.name.clone(), ranges: Vec::new() })
+        .collect();
+    let angles = sweep(polar);
+    for tws in &polar.tws {
+        let tws = *tws as f64;
+        let mut current: Option<(u8, f64, f64)> = None;
+        for twa in &angles {
+            let Some((sail, _)) = polar.best_sail_interpolated(*twa, tws, extrapolation, method) else { continue };
+            current = match current {
+                Some((s, from, _)) if s == sail => Some((s, from, *twa)),
+                Some(range) => {
+                    push_range(&mut sails, tws, range);
+                    Some((sail, *twa, *twa))
+                }
+                None => Some((sail, *twa, *twa)),
+            };
+        }
+        if let Some(range) = current {
+            push_range(&mut sails, tws, range);
+        }
+    }
+
+    PolarStats { units: Units::Kts, max_speed, vmg, sails, usage: None }
+}
+
+/// Table speeds of a sail per tws column and twa row, for sanity checks after edits.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SailStats {
+    pub units: Units,
+    pub sail: u8,
+    pub name: String,
+    pub tws: Vec<LineStats>,
+    pub twa: Vec<LineStats>,
+    /// fastest cell of the sail
+    pub peak: Option<SpeedPoint>,
+}
+
+/// Speeds of a column (`at` a tws) or a row (`at` a twa) of a speed matrix.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LineStats {
+    pub at: u8,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl LineStats {
+    fn new(at: u8, speeds: impl Iterator<Item = f64>) -> Option<Self> {
+        let (mut min, mut max, mut sum, mut count) = (f64::INFINITY, f64::NEG_INFINITY, 0.0, 0);
+        for speed in speeds {
+            min = min.min(speed);
+            max = max.max(speed);
+            sum += speed;
+            count += 1;
+        }
+        (count > 0).then(|| LineStats { at, min, max, mean: sum / count as f64 })
+    }
+}
+
+impl SailStats {
+    pub fn format(&mut self, format: SpeedFormat) {
+        self.units = format.units;
+        for line in self.tws.iter_mut().chain(self.twa.iter_mut()) {
+            line.min = format.apply(line.min);
+            line.max = format.apply(line.max);
+            line.mean = format.apply(line.mean);
+        }
+        if let Some(peak) = self.peak.as_mut() {
+            peak.speed = format.apply(peak.speed);
+        }
+    }
+}
+
+/// Statistics of the starboard table speeds of `sail` of `polar`, `None` when it has no such sail.
+pub fn sail_stats(polar: &Polar, sail: u8) -> Option<SailStats> {
+    let sail = polar.sail.iter().find(|s| s.id == sail)?;
+    let cell = |i: usize, j: usize| sail.speed.get(i).and_then(|row| row.get(j)).copied();
+
+    let tws = polar.tws.iter().enumerate()
+        .filter_map(|(j, tws)| LineStats::new(*tws, (0..polar.twa.len()).filter_map(|i| cell(i, j))))
+        .collect();
+    let twa = polar.twa.iter().enumerate()
+        .filter_map(|(i, twa)| LineStats::new(*twa, (0..polar.tws.len()).filter_map(|j| cell(i, j))))
+        .collect();
+
+    let mut peak: Option<SpeedPoint> = None;
+    for (i, twa) in polar.twa.iter().enumerate() {
+        for (j, tws) in polar.tws.iter().enumerate() {
+            let Some(speed) = cell(i, j) else { continue };
+            if peak.is_none_or(|p| speed > p.speed) {
+                peak = Some(SpeedPoint { speed, twa: *twa as f64, tws: *tws as f64, sail: sail.id });
+            }
+        }
+    }
+
+    Some(SailStats { units: Units::Kts, sail: sail.id, name: sail.name.clone(), tws, twa, peak })
+}
+
+/// Twa ranges where sails reach a target speed at a tws.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Reach {
+    pub units: Units,
+    pub speed: f64,
+    pub tws: f64,
+    /// sails reaching the speed, with the ranges where they do
+    pub sails: Vec<SailRanges>,
+}
+
+/// Twa ranges of each sail where its effective speed, with the options applied, is at least
+/// `speed` (in the units of `options`) at `tws`. `None` when the extrapolation of `options` is
+/// `Error` and `tws` is off the grid.
+pub fn reach(polar: &Polar, options: &Options, speed: f64, tws: f64) -> Option<Reach> {
+    let extrapolation = options.extrapolation.unwrap_or_default();
+    if extrapolation == Extrapolation::Error && !matrix::contains(&polar.tws, tws) {
+        return None;
+    }
+    let target = options.units.to_knots(speed);
+    let ratio = polar.speed_ratio(options.in_ice);
+    let angles = sweep(polar);
+
+    let sails = polar.sail.iter()
+        .map(|sail| {
+            let mut ranges = Vec::new();
+            let mut current: Option<(f64, f64)> = None;
+            for twa in &angles {
+                let speed = matrix::interpolate_with(&polar.twa, &polar.tws, &sail.speed, *twa, tws, extrapolation, options.method.unwrap_or_default())
+                    * ratio * compute::foil(polar, options.foil, *twa, tws);
+                let reached = speed >= target && sail.usable(*twa, tws);
+                current = match current {
+                    Some((from, _)) if reached => Some((from, *twa)),
+                    None if reached => Some((*twa, *twa)),
+                    Some((twa_from, twa_to)) => {
+                        ranges.push(TwaRange { tws, twa_from, twa_to });
+                        None
+                    }
+                    None => None,
+                };
+            }
+            if let Some((twa_from, twa_to)) = current {
+                ranges.push(TwaRange { tws, twa_from, twa_to });
+            }
+            SailRanges { sail: sail.id, name: sail.name.clone(), ranges }
+        })
+        .filter(|s| !s.ranges.is_empty())
+        .collect();
+
+    Some(Reach { units: options.units, speed, tws, sails })
+}
+
+fn push_range(sails: &mut [SailRanges], tws: f64, (sail, twa_from, twa_to): (u8, f64, f64)) {
+    if let Some(s) = sails.iter_mut().find(|s| s.sail == sail) {
+        s.ranges.push(TwaRange { tws, twa_from, twa_to });
+    }
+}
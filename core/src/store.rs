@@ -0,0 +1,903 @@
+use serde::{Deserialize, Serialize};
+use serde::de::{self, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde_json::Value;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use log::{error, warn};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use crate::migrate;
+use crate::polar::{canonical_yaml, content_hash, matrix_hash, Polar, PolarError};
+
+/// Files of the polars : the active and archived directories, gzip compression, the checksums
+/// and signatures next to the polar files, the parents of derived polars, the matrix store and
+/// the revisions. Polar files are handled as content, parsing them is left to `PolarService`.
+pub struct Store {
+    polars_dir: PathBuf,
+    archived_dir: PathBuf,
+    compress: bool,
+    compress_archived: bool,
+    /// secondary directory of the archived polars, read along `archived_dir`
+    cold_dir: Option<PathBuf>,
+    /// age in days of the archived polars moved to `cold_dir`
+    cold_after_days: Option<u64>,
+    rewrite_migrated: bool,
+    /// content addressed store of the sail matrices, matrices are kept in the polar files when `None`
+    matrices_dir: Option<PathBuf>,
+    /// previous files of the polars, a directory per polar
+    revisions_dir: PathBuf,
+}
+
+impl Store {
+
+    /// Creates `dir` when missing, panics when it can't be or isn't a directory.
+    pub fn create_dir(dir: &Path) {
+        if !dir.exists() {
+            if let Err(e) = fs::create_dir_all(dir) {
+                panic!("Error creating dir {:?} : {}", dir, e);
+            }
+        } else if !dir.is_dir() {
+            panic!("{:?} is not a directory", dir);
+        }
+    }
+
+    pub fn new<P: Into<PathBuf>, Q: Into<PathBuf>>(polars_dir: P, archived_dir: Q) -> Self {
+        let polars_dir: PathBuf = polars_dir.into();
+        let archived_dir: PathBuf = archived_dir.into();
+        Self::create_dir(&polars_dir);
+        Self::create_dir(&archived_dir);
+        Store {
+            revisions_dir: polars_dir.join(REVISIONS_DIR),
+            polars_dir,
+            archived_dir,
+            compress: false,
+            compress_archived: false,
+            cold_dir: None,
+            cold_after_days: None,
+            rewrite_migrated: false,
+            matrices_dir: None,
+        }
+    }
+
+    /// Writes polar files gzip compressed. Both plain and compressed files are always read.
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Gzip compresses the polar files when archiving them, keeping their modification time.
+    /// They are restored following `compress`.
+    pub fn compress_archived(mut self, compress_archived: bool) -> Self {
+        self.compress_archived = compress_archived;
+        self
+    }
+
+    /// Secondary directory of the archived polars, e.g. on a cheaper volume, see `tier`.
+    pub fn cold_dir<P: Into<PathBuf>>(mut self, cold_dir: Option<P>) -> Self {
+        if let Some(cold_dir) = cold_dir {
+            let cold_dir = cold_dir.into();
+            Self::create_dir(&cold_dir);
+            self.cold_dir = Some(cold_dir);
+        }
+        self
+    }
+
+    /// Age in days, from their last modification, of the archived polars moved to `cold_dir`.
+    pub fn cold_after_days(mut self, cold_after_days: Option<u64>) -> Self {
+        self.cold_after_days = cold_after_days;
+        self
+    }
+
+    /// Rewrites the polar files of an older schema version when they are read,
+    /// otherwise they are only migrated in memory.
+    pub fn rewrite_migrated(mut self, rewrite_migrated: bool) -> Self {
+        self.rewrite_migrated = rewrite_migrated;
+        self
+    }
+
+    /// Stores the sail matrices once in `matrices_dir`, named after the SHA-256 of their content,
+    /// polar files referencing them. Referenced matrices are always read.
+    pub fn matrices_dir<P: Into<PathBuf>>(mut self, matrices_dir: Option<P>) -> Self {
+        if let Some(matrices_dir) = matrices_dir {
+            let matrices_dir = matrices_dir.into();
+            Self::create_dir(&matrices_dir);
+            self.matrices_dir = Some(matrices_dir);
+        }
+        self
+    }
+
+    /// Directory of the previous files of the polars, `.revisions` in the polars directory
+    /// when `None`.
+    pub fn revisions_dir<P: Into<PathBuf>>(mut self, revisions_dir: Option<P>) -> Self {
+        if let Some(revisions_dir) = revisions_dir {
+            self.revisions_dir = revisions_dir.into();
+        }
+        self
+    }
+
+    /// Files of the active or `archived` polars with their metadata, only those modified at or
+    /// after `modified_since` when given.
+    pub fn files(&self, archived: bool, modified_since: Option<u64>) -> Result<Vec<(PathBuf, fs::Metadata)>> {
+        let dirs = if archived { self.archived_dirs() } else { vec![&self.polars_dir] };
+        let mut entries = Vec::new();
+        for dir in dirs {
+            entries.extend(fs::read_dir(dir)?.flatten());
+        }
+
+        let mut files = Vec::new();
+        for entry in entries {
+            if let Ok(metadata) = entry.metadata() {
+                let recent = modified_since.is_none_or(|since| Self::modified(&metadata).is_some_and(|m| m >= since));
+                if metadata.is_file() && recent {
+                    if let Some(name) = entry.path().file_name().and_then(OsStr::to_str) {
+                        if EXTENSIONS.iter().any(|ext| name.ends_with(&format!(".{}", ext))) {
+                            files.push((entry.path(), metadata));
+                        }
+                    }
+                }
+            } else {
+                warn!("Couldn't get metadata for {:?}", entry.path());
+            }
+        }
+        Ok(files)
+    }
+
+    /// Path of the file of `polar_id`, active or archived.
+    pub fn path_of(&self, polar_id: &str) -> Option<PathBuf> {
+        self.find_active(polar_id).or_else(|| self.find_archived(polar_id))
+    }
+
+    /// Existing file of the active polar `polar_id`, plain or compressed.
+    pub fn find_active(&self, polar_id: &str) -> Option<PathBuf> {
+        Self::find_in(&self.polars_dir, polar_id)
+    }
+
+    /// Existing archived file of `polar_id`, in `archived_dir` or `cold_dir`.
+    pub fn find_archived(&self, polar_id: &str) -> Option<PathBuf> {
+        self.archived_dirs().into_iter().find_map(|dir| Self::find_in(dir, polar_id))
+    }
+
+    /// Path where the file of the active polar `polar_id` is written.
+    pub fn new_path(&self, polar_id: &str) -> PathBuf {
+        let ext = if self.compress { EXTENSIONS[1] } else { EXTENSIONS[0] };
+        self.polars_dir.join(format!("{}.{}", polar_id, ext))
+    }
+
+    /// Directories of the archived polars, `cold_dir` last.
+    fn archived_dirs(&self) -> Vec<&PathBuf> {
+        [Some(&self.archived_dir), self.cold_dir.as_ref()].into_iter().flatten().collect()
+    }
+
+    /// Existing file of `polar_id` in `dir`, plain or compressed.
+    fn find_in(dir: &Path, polar_id: &str) -> Option<PathBuf> {
+        EXTENSIONS.iter()
+            .map(|ext| dir.join(format!("{}.{}", polar_id, ext)))
+            .find(|path| path.exists())
+    }
+
+    fn is_compressed(path: &Path) -> bool {
+        path.extension() == Some(OsStr::new("gz"))
+    }
+
+    pub fn modified(metadata: &fs::Metadata) -> Option<u64> {
+        metadata.modified().ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+    }
+
+    /// Content of the polar file at `path`, migrated and merged over its parent when it extends
+    /// one. With `summary`, the `SUMMARY_SKIPPED` keys are left out.
+    pub fn read_value(&self, path: &Path, summary: bool) -> Result<Value> {
+        self.read_value_at(path, 0, summary)
+    }
+
+    fn read_value_at(&self, path: &Path, depth: usize, summary: bool) -> Result<Value> {
+        let mut value = if summary {
+            Self::read_summary(path)?
+        } else {
+            let mut value = read_file(path)?;
+            self.load_matrices(&mut value)?;
+            value
+        };
+        // summaries lack the speed matrices and are never written back
+        if migrate::migrate(&mut value) && self.rewrite_migrated && !summary {
+            let mut migrated = value.clone();
+            if let Err(e) = self.store_matrices(&mut migrated).and_then(|_| Self::write_canonical(path, &migrated)) {
+                error!("Error rewriting migrated polar {:?} : {}", path, e);
+            }
+        }
+
+        self.resolve_at(value, depth, summary)
+    }
+
+    /// Polar file content `value` merged over its stored parent when it extends one.
+    pub fn resolve(&self, value: Value) -> Result<Value> {
+        self.resolve_at(value, 0, false)
+    }
+
+    fn resolve_at(&self, value: Value, depth: usize, summary: bool) -> Result<Value> {
+        match value.get("extends").and_then(|e| e.as_str()) {
+            Some(parent) => {
+                let mut base = self.parent_value_at(parent, depth, summary)?;
+                merge_values(&mut base, value);
+                Ok(base)
+            }
+            None => Ok(value),
+        }
+    }
+
+    /// Content of the polar file at `path` without the speed matrices, not merged over its
+    /// parent.
+    fn read_summary(path: &Path) -> Result<Value> {
+        let file = File::open(path)?;
+        if Self::is_compressed(path) {
+            Ok(serde_yaml::from_reader::<_, Summary>(BufReader::new(GzDecoder::new(file)))?.0)
+        } else {
+            Ok(serde_yaml::from_reader::<_, Summary>(BufReader::new(file))?.0)
+        }
+    }
+
+    /// Resolved content of the `parent` polar, without its own `extends`.
+    pub fn parent_value(&self, parent: &str) -> Result<Value> {
+        self.parent_value_at(parent, 0, false)
+    }
+
+    fn parent_value_at(&self, parent: &str, depth: usize, summary: bool) -> Result<Value> {
+        if depth >= MAX_EXTENDS_DEPTH {
+            return Err(PolarError::InvalidParent(format!("{} : too many levels of extends", parent)).into());
+        }
+        let path = self.path_of(parent).ok_or_else(|| PolarError::InvalidParent(format!("{} does not exist", parent)))?;
+        let mut base = self.read_value_at(&path, depth + 1, summary)?;
+        if let Some(map) = base.as_object_mut() {
+            map.remove("extends");
+        }
+        Ok(base)
+    }
+
+    /// Paths, sizes and modification times of the files `polar` was read from, its parents
+    /// included, a line per file. `None` when it wasn't read from the store or a file is gone.
+    pub fn files_stamp(&self, polar: &Polar) -> Option<String> {
+        let mut stamp = String::new();
+        let mut path = polar.source.clone();
+        let mut parent = polar.extends.clone();
+        for depth in 0..=MAX_EXTENDS_DEPTH {
+            let Some(current) = path else { return Some(stamp) };
+            stamp.push_str(&file_stamp(&current)?);
+            stamp.push('\n');
+
+            // the extends of the parents are only known from their files
+            if depth > 0 {
+                parent = Self::read_summary(&current).ok()?.get("extends").and_then(Value::as_str).map(String::from);
+            }
+            path = match parent.take() {
+                Some(parent) => Some(self.path_of(&parent)?),
+                None => None,
+            };
+        }
+        None
+    }
+
+    /// Writes the polar content `value` to `path`. A derived polar only keeps what differs from
+    /// its parent, and the sail matrices go to the matrix store when it is configured.
+    pub fn write_value(&self, path: &Path, value: Value) -> Result<()> {
+        let mut value = match value.get("extends").and_then(Value::as_str).map(String::from) {
+            Some(parent) => {
+                let base = self.parent_value(&parent)?;
+                let schema_version = value.get("schemaVersion").cloned();
+                let mut value = sparse_value(value, &base).unwrap_or_else(|| Value::Object(serde_json::Map::new()));
+                if let Some(map) = value.as_object_mut() {
+                    if let Some(schema_version) = schema_version {
+                        map.insert("schemaVersion".to_string(), schema_version);
+                    }
+                    map.insert("extends".to_string(), Value::from(parent));
+                }
+                value
+            }
+            None => value,
+        };
+        self.store_matrices(&mut value)?;
+        Self::write_canonical(path, &value)
+    }
+
+    /// Replaces the sail matrices of the polar file content `value` by references to the
+    /// matrix store, writing the matrices not stored yet, when the store is configured.
+    fn store_matrices(&self, value: &mut Value) -> Result<()> {
+        let Some(dir) = &self.matrices_dir else { return Ok(()) };
+        let Some(sails) = value.get_mut("sail").and_then(Value::as_array_mut) else { return Ok(()) };
+        for sail in sails.iter_mut().filter_map(Value::as_object_mut) {
+            for key in STORED_MATRICES {
+                let Some(matrix) = sail.remove(key) else { continue };
+                let yaml = canonical_yaml(&matrix)?;
+                let hash = matrix_hash(&matrix)?;
+                let path = dir.join(format!("{}.yaml", hash));
+                match fs::read(&path) {
+                    Ok(existing) if existing == yaml.as_bytes() => {}
+                    Ok(_) => return Err(anyhow!("matrix {} is stored with another content", hash)),
+                    Err(_) => fs::write(&path, yaml)?,
+                }
+                sail.insert(format!("{}Ref", key), Value::from(hash));
+            }
+        }
+        Ok(())
+    }
+
+    /// Inlines the sail matrices of the polar file content `value` referenced in the matrix store.
+    fn load_matrices(&self, value: &mut Value) -> Result<()> {
+        let Some(sails) = value.get_mut("sail").and_then(Value::as_array_mut) else { return Ok(()) };
+        for sail in sails.iter_mut().filter_map(Value::as_object_mut) {
+            for key in STORED_MATRICES {
+                let Some(hash) = sail.remove(&format!("{}Ref", key)) else { continue };
+                let hash = hash.as_str().unwrap_or_default().to_string();
+                let Some(dir) = &self.matrices_dir else {
+                    return Err(anyhow!("matrix {} is referenced but no matrix store is configured", hash));
+                };
+                sail.insert(key.to_string(), read_file(&dir.join(format!("{}.yaml", hash)))?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the polar file content `value` to `path` as canonical YAML, gzip compressed for
+    /// `.gz` files, and its checksum next to it. The file is left untouched when it already has
+    /// that content.
+    fn write_canonical(path: &Path, value: &Value) -> Result<()> {
+        let yaml = canonical_yaml(value)?;
+        let bytes = if Self::is_compressed(path) {
+            gzip(yaml.as_bytes())?
+        } else {
+            yaml.into_bytes()
+        };
+        Self::write_file(path, &bytes)
+    }
+
+    /// Checksum of the polar file at `path` from its checksum file, computed from the content
+    /// when it has none or when the polar file was modified after it, e.g. edited by hand.
+    pub fn checksum(path: &Path) -> Result<String> {
+        let sidecar = sidecar_path(path, CHECKSUM_SUFFIX);
+        let modified = fs::metadata(path)?.modified()?;
+        if fs::metadata(&sidecar).and_then(|m| m.modified()).is_ok_and(|checked| checked >= modified) {
+            if let Ok(checksum) = fs::read_to_string(&sidecar) {
+                return Ok(checksum.trim().to_string());
+            }
+        }
+        Ok(content_hash(&fs::read(path)?))
+    }
+
+    /// Writes `bytes` to the polar file at `path` and its checksum next to it, unless the file
+    /// already has that content.
+    fn write_file(path: &Path, bytes: &[u8]) -> Result<()> {
+        let checksum = sidecar_path(path, CHECKSUM_SUFFIX);
+        if checksum.exists() && fs::read(path).is_ok_and(|existing| existing == bytes) {
+            return Ok(());
+        }
+        fs::write(path, bytes)?;
+        fs::write(checksum, format!("{}\n", content_hash(bytes)))?;
+
+        Ok(())
+    }
+
+    /// Hex encoded detached signature of the polar file at `path`.
+    pub fn signature(path: &Path) -> Result<String> {
+        Ok(fs::read_to_string(sidecar_path(path, SIGNATURE_SUFFIX))?)
+    }
+
+    /// Attaches the hex encoded detached `signature` to the polar file at `path`.
+    pub fn write_signature(path: &Path, signature: &str) -> Result<()> {
+        fs::write(sidecar_path(path, SIGNATURE_SUFFIX), format!("{}\n", signature.trim()))?;
+        Ok(())
+    }
+
+    /// Removes the polar file at `path` with its checksum and signature.
+    pub fn remove(path: &Path) -> Result<()> {
+        fs::remove_file(path)?;
+        Self::remove_sidecars(path);
+        Ok(())
+    }
+
+    /// Moves the active polar file at `path` to the archived directory.
+    pub fn archive(&self, path: &Path) -> Result<()> {
+        let compressed = self.compress_archived || Self::is_compressed(path);
+        Self::transfer(path, &self.archived_dir, compressed)
+    }
+
+    /// Moves the archived polar file at `path` back to the polars directory.
+    pub fn restore(&self, path: &Path) -> Result<()> {
+        let compressed = if self.compress_archived { self.compress } else { Self::is_compressed(path) };
+        Self::transfer(path, &self.polars_dir, compressed)
+    }
+
+    /// Moves the polar file at `from` to `dir`, gzip compressing or decompressing it when
+    /// `compressed` differs, keeping its modification time and signature.
+    fn transfer(from: &Path, dir: &Path, compressed: bool) -> Result<()> {
+        if Self::is_compressed(from) == compressed {
+            return Self::rename(from, &dir.join(from.file_name().unwrap_or_default()));
+        }
+
+        let polar_id = from.file_prefix().unwrap_or_default().to_string_lossy();
+        let to = dir.join(format!("{}.{}", polar_id, if compressed { EXTENSIONS[1] } else { EXTENSIONS[0] }));
+        let modified = fs::metadata(from)?.modified()?;
+        let bytes = fs::read(from)?;
+        let bytes = if compressed {
+            gzip(&bytes)?
+        } else {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed)?;
+            decompressed
+        };
+        Self::write_file(&to, &bytes)?;
+        let file = File::options().write(true).open(&to)?;
+        file.set_modified(modified)?;
+        // on disk before the original is removed
+        file.sync_all()?;
+
+        // the signature covers the uncompressed content, it stays valid
+        let signature = sidecar_path(from, SIGNATURE_SUFFIX);
+        if signature.exists() {
+            Self::move_file(&signature, &sidecar_path(&to, SIGNATURE_SUFFIX))?;
+        }
+        fs::remove_file(from)?;
+        Self::remove_sidecars(from);
+        Ok(())
+    }
+
+    /// Moves the archived polars last modified more than `cold_after_days` ago to `cold_dir`,
+    /// they stay readable from there.
+    pub fn tier(&self) -> Result<Tiering> {
+        let mut tiering = Tiering::default();
+        let (Some(cold_dir), Some(days)) = (&self.cold_dir, self.cold_after_days) else { return Ok(tiering) };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let limit = now.saturating_sub(days.saturating_mul(86_400));
+
+        for entry in fs::read_dir(&self.archived_dir)?.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(OsStr::to_str) else { continue };
+            if !EXTENSIONS.iter().any(|ext| name.ends_with(&format!(".{}", ext))) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else { continue };
+            if metadata.is_file() && Self::modified(&metadata).is_some_and(|modified| modified < limit) {
+                Self::rename(&path, &cold_dir.join(name))?;
+                tiering.moved.push(path.file_prefix().unwrap_or_default().to_string_lossy().to_string());
+            }
+        }
+        Ok(tiering)
+    }
+
+    /// Moves the polar file at `from` to `to` with its sidecars, possibly to another file system.
+    fn rename(from: &Path, to: &Path) -> Result<()> {
+        if let Err(e) = Self::move_file(from, to) {
+            error!("Error moving file {:?} to {:?} : {}", from, to, e);
+            return Err(e);
+        }
+        for suffix in SIDECAR_SUFFIXES {
+            let sidecar = sidecar_path(from, suffix);
+            if sidecar.exists() {
+                if let Err(e) = Self::move_file(&sidecar, &sidecar_path(to, suffix)) {
+                    error!("Error moving file {:?} : {}", sidecar, e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the file at `from` to `to`. Renaming fails across file systems (e.g. a cold
+    /// directory on another disk), the file is then copied, synced to disk with its
+    /// modification time and only then removed.
+    fn move_file(from: &Path, to: &Path) -> Result<()> {
+        if fs::rename(from, to).is_ok() {
+            return Ok(());
+        }
+        let modified = fs::metadata(from)?.modified()?;
+        fs::copy(from, to)?;
+        let file = File::options().write(true).open(to)?;
+        file.set_modified(modified)?;
+        file.sync_all()?;
+        fs::remove_file(from)?;
+        Ok(())
+    }
+
+    /// Removes the checksum and signature of the polar file at `path`, the polar file being gone.
+    fn remove_sidecars(path: &Path) {
+        for suffix in SIDECAR_SUFFIXES {
+            let sidecar = sidecar_path(path, suffix);
+            if let Err(e) = fs::remove_file(&sidecar) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    error!("Error removing file {:?} : {}", sidecar, e);
+                }
+            }
+        }
+    }
+
+    /// Keeps the `previous` content of the file of the active polar `polar_id` at `path` as
+    /// its next revision, returned. Only the last `MAX_REVISIONS` revisions are kept.
+    pub fn save_revision(&self, polar_id: &str, path: &Path, previous: &[u8]) -> Result<u32> {
+        let dir = self.revisions_dir.join(polar_id);
+        fs::create_dir_all(&dir)?;
+        let mut revisions = Self::revision_files(&dir)?;
+        let revision = revisions.last().map_or(1, |(revision, _)| revision + 1);
+        let ext = if Self::is_compressed(path) { EXTENSIONS[1] } else { EXTENSIONS[0] };
+        fs::write(dir.join(format!("{}.{}", revision, ext)), previous)?;
+
+        revisions.push((revision, PathBuf::new()));
+        let excess = revisions.len().saturating_sub(MAX_REVISIONS);
+        for (_, path) in revisions.drain(..excess) {
+            if let Err(e) = fs::remove_file(&path) {
+                error!("Error removing revision {:?} : {}", path, e);
+            }
+        }
+        Ok(revision)
+    }
+
+    /// Revisions kept of the polar `polar_id`, oldest first.
+    pub fn revisions(&self, polar_id: &str) -> Result<Vec<Revision>> {
+        let dir = self.revisions_dir.join(polar_id);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        Ok(Self::revision_files(&dir)?.into_iter()
+            .map(|(revision, path)| Revision { revision, replaced: fs::metadata(&path).ok().as_ref().and_then(Self::modified) })
+            .collect())
+    }
+
+    /// File of the `revision` of the polar `polar_id`.
+    pub fn find_revision(&self, polar_id: &str, revision: u32) -> Option<PathBuf> {
+        Self::find_in(&self.revisions_dir.join(polar_id), &revision.to_string())
+    }
+
+    /// Revision files in `dir` by increasing revision number.
+    fn revision_files(dir: &Path) -> Result<Vec<(u32, PathBuf)>> {
+        let mut revisions: Vec<(u32, PathBuf)> = fs::read_dir(dir)?.flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                let revision = path.file_prefix()?.to_str()?.parse().ok()?;
+                Some((revision, path))
+            })
+            .collect();
+        revisions.sort_by_key(|(revision, _)| *revision);
+        Ok(revisions)
+    }
+
+    /// Compares the polar files of both directories with their checksums, and the matrices of
+    /// the matrix store with their names, to detect corrupted files or files modified out of band.
+    pub fn verify(&self) -> Result<VerifyReport> {
+        let mut report = VerifyReport::default();
+        for dir in [&self.polars_dir].into_iter().chain(self.archived_dirs()) {
+            Self::verify_dir(dir, &mut report)?;
+        }
+        if let Some(dir) = &self.matrices_dir {
+            for entry in fs::read_dir(dir)?.flatten() {
+                let path = entry.path();
+                let Some(expected) = path.file_name().and_then(OsStr::to_str).and_then(|name| name.strip_suffix(".yaml")) else { continue };
+                report.checked += 1;
+                let actual = content_hash(&fs::read(&path)?);
+                if actual != expected {
+                    report.issues.push(IntegrityIssue::new(&path, IntegrityIssueKind::Mismatch, Some(expected.to_string()), Some(actual)));
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    fn verify_dir(dir: &Path, report: &mut VerifyReport) -> Result<()> {
+        for entry in fs::read_dir(dir)?.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(OsStr::to_str) else { continue };
+            if let Some(polar_file) = name.strip_suffix(CHECKSUM_SUFFIX) {
+                if !dir.join(polar_file).exists() {
+                    report.issues.push(IntegrityIssue::new(&path, IntegrityIssueKind::MissingFile, None, None));
+                }
+                continue;
+            }
+            if !path.is_file() || !EXTENSIONS.iter().any(|ext| name.ends_with(&format!(".{}", ext))) {
+                continue;
+            }
+
+            report.checked += 1;
+            let actual = content_hash(&fs::read(&path)?);
+            match fs::read_to_string(sidecar_path(&path, CHECKSUM_SUFFIX)) {
+                Ok(expected) if expected.trim() == actual => {}
+                Ok(expected) => report.issues.push(IntegrityIssue::new(&path, IntegrityIssueKind::Mismatch, Some(expected.trim().to_string()), Some(actual))),
+                Err(_) => report.issues.push(IntegrityIssue::new(&path, IntegrityIssueKind::MissingChecksum, None, Some(actual))),
+            }
+        }
+        Ok(())
+    }
+
+    /// File counts and sizes of the polar directories and of the `catalogs` files.
+    pub fn storage(&self, catalogs: &[&Path]) -> Result<StorageReport> {
+        let catalogs = catalogs.iter()
+            .filter_map(|path| fs::metadata(path).ok().map(|m| FileSize { name: path.to_string_lossy().to_string(), bytes: m.len() }))
+            .collect();
+        Ok(StorageReport {
+            polars: Self::directory_usage(&self.polars_dir)?,
+            archived: Self::directory_usage(&self.archived_dir)?,
+            cold: self.cold_dir.as_deref().map(Self::directory_usage).transpose()?,
+            catalogs,
+        })
+    }
+
+    fn directory_usage(dir: &Path) -> Result<DirectoryUsage> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)?.flatten() {
+            let Ok(metadata) = entry.metadata() else { continue };
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(OsStr::to_str) else { continue };
+            if metadata.is_file() && EXTENSIONS.iter().any(|ext| name.ends_with(&format!(".{}", ext))) {
+                files.push(FileSize { name: name.to_string(), bytes: metadata.len() });
+            }
+        }
+
+        let bytes = files.iter().map(|f| f.bytes).sum();
+        let count = files.len();
+        files.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+        files.truncate(LARGEST_FILES);
+        Ok(DirectoryUsage { dir: dir.to_string_lossy().to_string(), files: count, bytes, largest: files })
+    }
+}
+
+/// Directory of the revisions in the polars directory, unless configured.
+const REVISIONS_DIR: &str = ".revisions";
+
+/// Revisions kept per polar, the oldest ones are removed.
+const MAX_REVISIONS: usize = 20;
+
+/// Gzip compressed `bytes`.
+fn gzip(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    Ok(encoder.finish()?)
+}
+
+/// Path of the file with `suffix` next to the polar file at `path`.
+fn sidecar_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Content of the YAML (or JSON) polar file at `path`, gzip compressed for `.gz` files,
+/// as it is stored : neither migrated nor merged over its parent.
+pub fn read_file(path: &Path) -> Result<Value> {
+    let file = File::open(path)?;
+    if path.extension() == Some(OsStr::new("gz")) {
+        Ok(serde_yaml::from_reader(BufReader::new(GzDecoder::new(file)))?)
+    } else {
+        Ok(serde_yaml::from_reader(BufReader::new(file))?)
+    }
+}
+
+/// Extensions of polar files, plain then gzip compressed.
+const EXTENSIONS: [&str; 2] = ["yaml", "yaml.gz"];
+
+/// Levels of `extends` followed before giving up, protects against cycles.
+pub(crate) const MAX_EXTENDS_DEPTH: usize = 8;
+
+/// Number of largest files listed per directory in storage reports.
+const LARGEST_FILES: usize = 10;
+
+/// Keys of a polar file not parsed for summaries, they hold the bulk of its content.
+pub(crate) const SUMMARY_SKIPPED: [&str; 3] = ["twa", "tws", "sail"];
+
+/// Suffix of the file holding the checksum of a polar file, next to it.
+const CHECKSUM_SUFFIX: &str = ".sum";
+
+/// Suffix of the file holding the detached signature of a polar file, next to it.
+const SIGNATURE_SUFFIX: &str = ".sig";
+
+/// Files following their polar file when it is moved or removed.
+const SIDECAR_SUFFIXES: [&str; 2] = [CHECKSUM_SUFFIX, SIGNATURE_SUFFIX];
+
+/// Keys of the sail matrices kept in the matrix store, referenced by `<key>Ref` in polar files.
+const STORED_MATRICES: [&str; 2] = ["speed", "portSpeed"];
+
+/// Polar file content without the `SUMMARY_SKIPPED` keys, which are scanned over but never built.
+struct Summary(Value);
+
+impl<'de> Deserialize<'de> for Summary {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct SummaryVisitor;
+
+        impl<'de> Visitor<'de> for SummaryVisitor {
+            type Value = Summary;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a polar")
+            }
+
+            fn visit_unit<E: de::Error>(self) -> std::result::Result<Summary, E> {
+                Ok(Summary(Value::Null))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<Summary, A::Error> {
+                let mut fields = serde_json::Map::new();
+                while let Some(key) = map.next_key::<String>()? {
+                    if SUMMARY_SKIPPED.contains(&key.as_str()) {
+                        map.next_value::<IgnoredAny>()?;
+                    } else {
+                        fields.insert(key, map.next_value()?);
+                    }
+                }
+                Ok(Summary(Value::Object(fields)))
+            }
+        }
+
+        deserializer.deserialize_any(SummaryVisitor)
+    }
+}
+
+/// Merges `overlay` into `base`, recursively for maps, replacing anything else.
+fn merge_values(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_values(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Part of `value` that differs from `base`, `None` when they are equal.
+fn sparse_value(value: Value, base: &Value) -> Option<Value> {
+    match (value, base) {
+        (Value::Object(value), Value::Object(base)) => {
+            let mut sparse = serde_json::Map::new();
+            for (key, v) in value {
+                let v = match base.get(&key) {
+                    Some(b) => sparse_value(v, b),
+                    None => Some(v),
+                };
+                if let Some(v) = v {
+                    sparse.insert(key, v);
+                }
+            }
+            (!sparse.is_empty()).then_some(Value::Object(sparse))
+        }
+        (value, base) => (&value != base).then_some(value),
+    }
+}
+
+/// Path, size and modification time in nanoseconds of the file at `path`, colon separated.
+pub(crate) fn file_stamp(path: &Path) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    Some(format!("{}:{}:{}", path.display(), metadata.len(), modified))
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum IntegrityIssueKind {
+    /// content differs from its checksum
+    Mismatch,
+    /// file written before checksums were kept or added out of band
+    MissingChecksum,
+    /// checksum left without its polar file
+    MissingFile,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityIssue {
+    pub file: String,
+    pub kind: IntegrityIssueKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual: Option<String>,
+}
+
+impl IntegrityIssue {
+    fn new(path: &Path, kind: IntegrityIssueKind, expected: Option<String>, actual: Option<String>) -> Self {
+        IntegrityIssue { file: path.to_string_lossy().to_string(), kind, expected, actual }
+    }
+}
+
+/// Number of files checked by a verification and the files failing it.
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyReport {
+    pub checked: usize,
+    pub issues: Vec<IntegrityIssue>,
+}
+
+/// Ids of the archived polars moved to the cold directory.
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Tiering {
+    pub moved: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FileSize {
+    pub name: String,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryUsage {
+    pub dir: String,
+    /// number of polar files
+    pub files: usize,
+    pub bytes: u64,
+    /// largest polar files, at most `LARGEST_FILES`
+    pub largest: Vec<FileSize>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageReport {
+    pub polars: DirectoryUsage,
+    pub archived: DirectoryUsage,
+    /// secondary directory of the archived polars, when configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cold: Option<DirectoryUsage>,
+    /// sail and winch catalogs, when they exist
+    pub catalogs: Vec<FileSize>,
+}
+
+/// Previous file of a polar, replaced at `replaced`, in seconds since epoch.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Revision {
+    pub revision: u32,
+    pub replaced: Option<u64>,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::migrate::SCHEMA_VERSION;
+
+    /// Store on empty directories of its own, removed first when left by a previous run.
+    fn store(name: &str) -> Store {
+        let dir = std::env::temp_dir().join(format!("nav-polars-store-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Store::new(dir.join("polars"), dir.join("archived"))
+    }
+
+    #[test]
+    fn merges_maps_and_replaces_the_rest() {
+        let mut base = json!({ "label": "Parent", "foil": { "speedRatio": 1.04, "twaMin": 70 }, "tws": [0, 10] });
+        merge_values(&mut base, json!({ "foil": { "speedRatio": 1.1 }, "tws": [5], "id": "child" }));
+        assert_eq!(base, json!({ "label": "Parent", "foil": { "speedRatio": 1.1, "twaMin": 70 }, "tws": [5], "id": "child" }));
+    }
+
+    #[test]
+    fn keeps_what_differs_from_the_base() {
+        let base = json!({ "label": "Parent", "foil": { "speedRatio": 1.04, "twaMin": 70 }, "tws": [0, 10] });
+        let value = json!({ "label": "Parent", "foil": { "speedRatio": 1.1, "twaMin": 70 }, "tws": [0, 10], "id": "child" });
+        assert_eq!(sparse_value(value, &base), Some(json!({ "foil": { "speedRatio": 1.1 }, "id": "child" })));
+        assert_eq!(sparse_value(base.clone(), &base), None);
+    }
+
+    #[test]
+    fn writes_derived_polars_sparse_and_reads_them_merged() {
+        let store = store("extends");
+        let parent = json!({ "schemaVersion": SCHEMA_VERSION, "id": "parent", "label": "Parent", "foil": { "speedRatio": 1.04, "twaMin": 70 } });
+        store.write_value(&store.new_path("parent"), parent).unwrap();
+
+        let child = json!({ "schemaVersion": SCHEMA_VERSION, "id": "child", "extends": "parent", "label": "Parent", "foil": { "speedRatio": 1.1, "twaMin": 70 } });
+        let path = store.new_path("child");
+        store.write_value(&path, child.clone()).unwrap();
+        assert_eq!(read_file(&path).unwrap(), json!({ "schemaVersion": SCHEMA_VERSION, "id": "child", "extends": "parent", "foil": { "speedRatio": 1.1 } }));
+        assert_eq!(store.read_value(&path, false).unwrap(), child);
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::matrix::{self, Extrapolation, Flat};
+use crate::polar::{Polar, SailBounds};
+
+/// Bilinear coefficients of a grid cell : `a + b·u + c·v + d·u·v`, `u` and `v` being the
+/// fractions of the point along twa and tws within the cell.
+#[derive(Debug, Clone, Copy, Default)]
+struct Cell {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+}
+
+#[derive(Debug)]
+struct SailTable {
+    id: u8,
+    starboard: Vec<Cell>,
+    port: Option<Vec<Cell>>,
+    bounds: Option<SailBounds>,
+}
+
+impl SailTable {
+    fn usable(&self, twa: f64, tws: f64) -> bool {
+        self.bounds.is_none_or(|bounds| bounds.contains(twa, tws))
+    }
+}
+
+/// Evaluation structure of a polar with the interpolation coefficients of every cell
+/// precomputed, built once per polar content.
+#[derive(Debug)]
+pub struct Table {
+    twa: Vec<u8>,
+    tws: Vec<u8>,
+    sails: Vec<SailTable>,
+}
+
+impl Table {
+    pub fn new(polar: &Polar) -> Self {
+        let (rows, columns) = (polar.twa.len(), polar.tws.len());
+        let cells = |speed: &Vec<Vec<f64>>| -> Vec<Cell> {
+            let flat = Flat::new(speed, rows, columns);
+            let value = |i: usize, j: usize| flat.get(i, j);
+            let mut cells = Vec::with_capacity(rows.max(2) * columns.max(2));
+            for i in 0..rows.saturating_sub(1).max(1) {
+                let i1 = (i + 1).min(rows.saturating_sub(1));
+                for j in 0..columns.saturating_sub(1).max(1) {
+                    let j1 = (j + 1).min(columns.saturating_sub(1));
+                    let (v00, v01, v10, v11) = (value(i, j), value(i, j1), value(i1, j), value(i1, j1));
+                    cells.push(Cell { a: v00, b: v10 - v00, c: v01 - v00, d: v11 - v10 - v01 + v00 });
+                }
+            }
+            cells
+        };
+
+        Table {
+            twa: polar.twa.clone(),
+            tws: polar.tws.clone(),
+            sails: polar.sail.iter()
+                .map(|s| SailTable { id: s.id, starboard: cells(&s.speed), port: s.port_speed.as_ref().map(cells), bounds: s.bounds })
+                .collect(),
+        }
+    }
+
+    /// Key of the content a table is built from : grids, speed matrices and sail bounds.
+    pub fn key(polar: &Polar) -> u64 {
+        let mut hash = 0xcbf29ce484222325u64;
+        let mut feed = |bytes: &[u8]| {
+            for b in bytes {
+                hash = (hash ^ *b as u64).wrapping_mul(0x100000001b3);
+            }
+        };
+        feed(&polar.twa);
+        feed(&[0xff]);
+        feed(&polar.tws);
+        for sail in &polar.sail {
+            feed(&[0xff, sail.id]);
+            for matrix in std::iter::once(&sail.speed).chain(sail.port_speed.as_ref()) {
+                for v in matrix.iter().flatten() {
+                    feed(&v.to_bits().to_le_bytes());
+                }
+            }
+            if let Some(bounds) = sail.bounds {
+                for bound in [bounds.twa_min, bounds.twa_max, bounds.tws_min, bounds.tws_max] {
+                    feed(&bound.map_or(f64::NAN, |b| b).to_bits().to_le_bytes());
+                }
+            }
+        }
+        hash
+    }
+
+    /// Key of the table of a polar read from the files of `stamp` : `stamp`, the grids and the
+    /// sails kept by the evaluation options.
+    pub fn file_key(stamp: &str, polar: &Polar) -> u64 {
+        let mut hash = 0x84222325cbf29ce4u64;
+        let mut feed = |bytes: &[u8]| {
+            for b in bytes {
+                hash = (hash ^ *b as u64).wrapping_mul(0x100000001b3);
+            }
+        };
+        feed(stamp.as_bytes());
+        feed(&[0xff]);
+        feed(&polar.twa);
+        feed(&[0xff]);
+        feed(&polar.tws);
+        feed(&[0xff]);
+        feed(&polar.sail.iter().map(|s| s.id).collect::<Vec<u8>>());
+        hash
+    }
+
+    /// Same result as `matrix::interpolate` on the sail matrix for the tack of `twa`.
+    fn speed(&self, sail: &SailTable, twa: f64, tws: f64, extrapolation: Extrapolation) -> f64 {
+        let linear = extrapolation == Extrapolation::Linear;
+        let cells = match &sail.port {
+            Some(port) if twa < 0.0 => port,
+            _ => &sail.starboard,
+        };
+        let (i, u) = matrix::bracket(&self.twa, twa.abs(), linear);
+        let (j, v) = matrix::bracket(&self.tws, tws, linear);
+        let columns = self.tws.len().saturating_sub(1).max(1);
+        let Some(cell) = cells.get(i * columns + j) else { return 0.0 };
+
+        let res = cell.a + cell.b * u + cell.c * v + cell.d * u * v;
+        if linear { res.max(0.0) } else { res }
+    }
+
+    /// Fastest usable sail and its table speed at each `(twa, tws)` of `points`, in one pass per sail.
+    pub fn best_sails(&self, points: &[(f64, f64)], extrapolation: Extrapolation) -> Vec<(u8, f64)> {
+        let mut best = vec![(0, f64::NEG_INFINITY); points.len()];
+        for sail in &self.sails {
+            for (best, (twa, tws)) in best.iter_mut().zip(points).filter(|(_, (twa, tws))| sail.usable(*twa, *tws)) {
+                let speed = self.speed(sail, *twa, *tws, extrapolation);
+                if speed >= best.1 {
+                    *best = (sail.id, speed);
+                }
+            }
+        }
+        best.into_iter().map(|(sail, speed)| if speed.is_finite() { (sail, speed) } else { (0, 0.0) }).collect()
+    }
+
+    /// Fastest usable sail and its table speed at `(twa, tws)`.
+    pub fn best_sail(&self, twa: f64, tws: f64, extrapolation: Extrapolation) -> Option<(u8, f64)> {
+        self.sails.iter()
+            .filter(|s| s.usable(twa, tws))
+            .map(|s| (s.id, self.speed(s, twa, tws, extrapolation)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+/// Tables of the evaluated polars by key, see `Table::key` and `Table::file_key`. The cache is
+/// emptied when it holds `MAX_TABLES` tables.
+#[derive(Default)]
+pub struct TableCache {
+    tables: Mutex<HashMap<u64, Arc<Table>>>,
+}
+
+impl TableCache {
+    /// Table of `key`, built from `polar` on first use.
+    pub fn get(&self, key: u64, polar: &Polar) -> Arc<Table> {
+        let mut tables = self.tables.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(table) = tables.get(&key) {
+            return table.clone();
+        }
+        if tables.len() >= MAX_TABLES {
+            tables.clear();
+        }
+        let table = Arc::new(Table::new(polar));
+        tables.insert(key, table.clone());
+        table
+    }
+
+    pub fn clear(&self) {
+        self.tables.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+/// Evaluation tables kept in cache, it is emptied when full.
+const MAX_TABLES: usize = 256;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::test_polar;
+
+    /// Scaffold of three sails, the first one slower on port tack, the second one only used up
+    /// to 10 knots.
+    fn polar() -> Polar {
+        let mut polar = test_polar("table", 3, 10, 4);
+        polar.sail[0].port_speed = Some(polar.sail[0].speed.iter().map(|row| row.iter().map(|v| v * 0.9).collect()).collect());
+        polar.sail[1].bounds = Some(SailBounds { tws_max: Some(10.0), ..SailBounds::default() });
+        polar
+    }
+
+    /// Best sail of `polar` at `(twa, tws)` interpolating the speed matrices directly.
+    fn expected(polar: &Polar, twa: f64, tws: f64, extrapolation: Extrapolation) -> (u8, f64) {
+        polar.sail.iter()
+            .filter(|s| s.usable(twa, tws))
+            .map(|s| (s.id, matrix::interpolate(&polar.twa, &polar.tws, s.matrix(twa), twa.abs(), tws, extrapolation)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn best_sail_matches_the_speed_matrices() {
+        let polar = polar();
+        let table = Table::new(&polar);
+        let points = [(45.0, 7.0), (-45.0, 7.0), (95.0, 12.5), (-152.0, 3.0), (180.0, 40.0), (0.0, 0.0), (120.0, 46.0)];
+        for extrapolation in [Extrapolation::Clamp, Extrapolation::Linear] {
+            for (twa, tws) in points {
+                let (sail, speed) = table.best_sail(twa, tws, extrapolation).unwrap_or_default();
+                let (expected_sail, expected_speed) = expected(&polar, twa, tws, extrapolation);
+                assert!((speed - expected_speed).abs() < 1e-9, "speed at ({}, {})", twa, tws);
+                assert!(speed == 0.0 || sail == expected_sail, "sail at ({}, {})", twa, tws);
+            }
+        }
+    }
+
+    #[test]
+    fn best_sails_match_best_sail() {
+        let polar = polar();
+        let table = Table::new(&polar);
+        let points = [(40.0, 8.0), (-40.0, 8.0), (100.0, 9.0), (100.0, 14.0), (170.0, 30.0)];
+        let best = table.best_sails(&points, Extrapolation::Clamp);
+        for ((twa, tws), best) in points.iter().zip(best) {
+            let (_, speed) = table.best_sail(*twa, *tws, Extrapolation::Clamp).unwrap_or_default();
+            assert!((best.1 - speed).abs() < 1e-9, "speed at ({}, {})", twa, tws);
+        }
+    }
+
+    #[test]
+    fn key_changes_with_the_speeds() {
+        let polar = polar();
+        let mut changed = polar.clone();
+        changed.sail[2].speed[5][5] += 0.1;
+        assert_eq!(Table::key(&polar), Table::key(&polar.clone()));
+        assert_ne!(Table::key(&polar), Table::key(&changed));
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::migrate::SCHEMA_VERSION;
+use crate::polar::{Foil, Hull, Penalty, PenaltyBoundaries, PenaltyCase, Polar, Sail, Winch};
+
+/// Sail names of the game, in the order they are given to scaffolded sails.
+const SAIL_NAMES: [&str; 7] = ["Jib", "Spi", "Staysail", "LightJib", "Code0", "HeavyGnk", "LightGnk"];
+/// Upwind angle under which boat speed is null.
+const NO_GO_TWA: f64 = 30.0;
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum Template {
+    Monohull,
+    Multihull,
+}
+
+impl Template {
+    fn base_speed(&self) -> f64 {
+        match self {
+            Template::Monohull => 8.0,
+            Template::Multihull => 14.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateParams {
+    pub template: Template,
+    pub id: String,
+    #[serde(rename = "_id", default)]
+    pub polar_id: u8,
+    pub label: Option<String>,
+    /// number of sails, up to 7
+    pub sails: Option<u8>,
+    pub twa_step: Option<u8>,
+    pub tws_step: Option<u8>,
+    pub tws_max: Option<u8>,
+    /// best speed reached in medium wind, in knots
+    pub base_speed: Option<f64>,
+}
+
+impl TemplateParams {
+    /// Polar skeleton with a plausible speed shape, meant to be tuned afterwards.
+    pub fn scaffold(&self) -> Polar {
+        let twa_step = self.twa_step.unwrap_or(5).max(1);
+        let tws_step = self.tws_step.unwrap_or(2).max(1);
+        let tws_max = self.tws_max.unwrap_or(40);
+        let sails = self.sails.unwrap_or(3).clamp(1, SAIL_NAMES.len() as u8);
+        let base_speed = self.base_speed.unwrap_or_else(|| self.template.base_speed());
+
+        let mut twa: Vec<u8> = (0..=180u8).step_by(twa_step as usize).chain(std::iter::once(180)).collect();
+        twa.dedup();
+        let mut tws: Vec<u8> = (0..=tws_max).step_by(tws_step as usize).chain(std::iter::once(tws_max)).collect();
+        tws.dedup();
+
+        let sail = (0..sails)
+            .map(|i| Sail {
+                id: i + 1,
+                name: SAIL_NAMES[i as usize].to_string(),
+                catalog: None,
+                option: None,
+                speed: twa.iter()
+                    .map(|a| tws.iter().map(|w| speed(base_speed, i, sails, *a as f64, *w as f64)).collect())
+                    .collect(),
+                port_speed: None,
+                bounds: None,
+            })
+            .collect();
+
+        Polar {
+            schema_version: SCHEMA_VERSION,
+            id: Some(self.id.clone()),
+            extends: None,
+            polar_id: self.polar_id,
+            archived: false,
+            modified: None,
+            verified: None,
+            file: None,
+            source: None,
+            label: self.label.clone().unwrap_or_else(|| self.id.clone()),
+            labels: BTreeMap::new(),
+            class: None,
+            tags: Vec::new(),
+            default: None,
+            global_speed_ratio: 1.0,
+            ice_speed_ratio: 1.0,
+            auto_sail_change_tolerance: 1.0,
+            bad_sail_tolerance: 1.0,
+            max_speed: base_speed * 2.5,
+            max_speed_policy: None,
+            foil: Foil { speed_ratio: 1.0, twa_min: 0.0, twa_max: 0.0, twa_merge: 0.0, tws_min: 0.0, tws_max: 0.0, tws_merge: 0.0 },
+            hull: Hull { speed_ratio: 1.0 },
+            winch: Some(Winch {
+                tack: penalty_case(),
+                gybe: penalty_case(),
+                sail_change: penalty_case(),
+                lws: None,
+                hws: None,
+            }),
+            winch_profile: None,
+            tws,
+            twa,
+            sail,
+        }
+    }
+}
+
+fn penalty_case() -> PenaltyCase {
+    let boundaries = || PenaltyBoundaries { lw: Penalty { ratio: 0.5, timer: 60 }, hw: Penalty { ratio: 0.5, timer: 60 } };
+    PenaltyCase {
+        std_timer_sec: 60,
+        std_ratio: 0.5,
+        pro_timer_sec: 45,
+        pro_ratio: 0.5,
+        std: Some(boundaries()),
+        pro: Some(boundaries()),
+    }
+}
+
+/// Speed of sail `index` out of `count` : the twa range past the no-go zone is split in one
+/// sector per sail, each sail losing 10% per sector away from its own.
+fn speed(base_speed: f64, index: u8, count: u8, twa: f64, tws: f64) -> f64 {
+    if twa < NO_GO_TWA {
+        return 0.0;
+    }
+    // fastest on a beam reach, a bit slower running
+    let twa_factor = if twa <= 110.0 {
+        ((twa - NO_GO_TWA) / (110.0 - NO_GO_TWA) * std::f64::consts::FRAC_PI_2).sin().max(0.4)
+    } else {
+        1.0 - 0.2 * (twa - 110.0) / 70.0
+    };
+    let tws_factor = 1.0 - (-tws / 10.0).exp();
+
+    let sector = (180.0 - NO_GO_TWA) / count as f64;
+    let own = ((twa - NO_GO_TWA) / sector).floor().min(count as f64 - 1.0);
+    let sail_factor = (1.0 - 0.1 * (own - index as f64).abs()).max(0.5);
+
+    base_speed * 1.2 * twa_factor * tws_factor * sail_factor
+}
+
+/// Monohull scaffold of `sails` sails on a `twa_step` by `tws_step` grid up to 40 knots, shared by the tests.
+#[cfg(test)]
+pub(crate) fn test_polar(id: &str, sails: u8, twa_step: u8, tws_step: u8) -> Polar {
+    TemplateParams {
+        template: Template::Monohull,
+        id: id.to_string(),
+        polar_id: 1,
+        label: None,
+        sails: Some(sails),
+        twa_step: Some(twa_step),
+        tws_step: Some(tws_step),
+        tws_max: Some(40),
+        base_speed: None,
+    }
+    .scaffold()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn axes_end_on_the_last_angle_and_wind() {
+        let polar = test_polar("scaffold", 2, 7, 3);
+        assert_eq!(polar.twa.first(), Some(&0));
+        assert_eq!(polar.twa.last(), Some(&180));
+        assert_eq!(polar.tws.last(), Some(&40));
+        assert!(polar.twa.windows(2).all(|w| w[0] < w[1]));
+        assert!(polar.tws.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn sails_fit_the_grid_and_stop_in_the_no_go_zone() {
+        let polar = test_polar("scaffold", 7, 10, 4);
+        assert_eq!(polar.sail.len(), SAIL_NAMES.len());
+        for sail in &polar.sail {
+            assert_eq!(sail.speed.len(), polar.twa.len());
+            assert!(sail.speed.iter().all(|row| row.len() == polar.tws.len()));
+            for (twa, row) in polar.twa.iter().zip(&sail.speed) {
+                assert_eq!(*twa < 30, row.iter().all(|s| *s == 0.0), "{} at {}", sail.name, twa);
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::polar::Polar;
+use crate::service;
+
+/// Cells affected by a transform. Every bound is optional and inclusive.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Selection {
+    pub sail: Option<u8>,
+    pub twa_min: Option<u8>,
+    pub twa_max: Option<u8>,
+    pub tws_min: Option<u8>,
+    pub tws_max: Option<u8>,
+}
+
+impl Selection {
+    fn contains(&self, sail: u8, twa: u8, tws: u8) -> bool {
+        self.sail.is_none_or(|s| s == sail)
+            && self.twa_min.is_none_or(|m| twa >= m)
+            && self.twa_max.is_none_or(|m| twa <= m)
+            && self.tws_min.is_none_or(|m| tws >= m)
+            && self.tws_max.is_none_or(|m| tws <= m)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum Transform {
+    /// Multiply speeds by `factor`.
+    Scale {
+        factor: f64,
+        #[serde(flatten)]
+        selection: Selection,
+    },
+    /// Add `delta` knots to speeds.
+    Offset {
+        delta: f64,
+        #[serde(flatten)]
+        selection: Selection,
+    },
+}
+
+impl Transform {
+
+    /// Applies the transform in place. Resulting speeds are clamped to `[0, max_speed]`.
+    pub fn apply(&self, polar: &mut Polar) {
+        let (selection, f): (&Selection, Box<dyn Fn(f64) -> f64>) = match self {
+            Transform::Scale { factor, selection } => (selection, Box::new(move |v| v * factor)),
+            Transform::Offset { delta, selection } => (selection, Box::new(move |v| v + delta)),
+        };
+
+        let max_speed = polar.max_speed;
+        for sail in polar.sail.iter_mut() {
+            let id = sail.id;
+            for matrix in std::iter::once(&mut sail.speed).chain(sail.port_speed.as_mut()) {
+                for (row, twa) in matrix.iter_mut().zip(&polar.twa) {
+                    for (v, tws) in row.iter_mut().zip(&polar.tws) {
+                        if selection.contains(id, *twa, *tws) {
+                            *v = clamp(f(*v), max_speed);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn clamp(v: f64, max_speed: f64) -> f64 {
+    if max_speed > 0.0 {
+        v.clamp(0.0, max_speed)
+    } else {
+        v.max(0.0)
+    }
+}
+
+/// Change of a single field of the polars, addressed by its JSON pointer in the polar file,
+/// e.g. `/winch/lws`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum FieldChange {
+    /// Set the field to `value`, it is added when its parent exists.
+    Set { path: String, value: Value },
+    /// Multiply the numeric field by `factor`.
+    Scale { path: String, factor: f64 },
+}
+
+/// Value of a field before and after a change, `null` when it did not exist.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldUpdate {
+    pub path: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// Field changes applied to every polar of `filter`, see `PolarService::bulk_update`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkUpdate {
+    #[serde(default)]
+    pub filter: service::Selection,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Changes made, or that would be made, to a polar by a bulk update, or why it failed.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkResult {
+    pub id: String,
+    pub updates: Vec<FieldUpdate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl FieldChange {
+
+    /// Applies the change to the polar file content `value`.
+    pub fn apply(&self, value: &mut Value) -> Result<FieldUpdate> {
+        match self {
+            FieldChange::Set { path, value: new } => {
+                let before = match value.pointer_mut(path) {
+                    Some(field) => std::mem::replace(field, new.clone()),
+                    None => {
+                        let (parent, key) = path.rsplit_once('/').ok_or_else(|| anyhow!("{} is not a JSON pointer", path))?;
+                        let key = key.replace("~1", "/").replace("~0", "~");
+                        let map = value.pointer_mut(parent).and_then(Value::as_object_mut).ok_or_else(|| anyhow!("{} does not exist", parent))?;
+                        map.insert(key, new.clone());
+                        Value::Null
+                    }
+                };
+                Ok(FieldUpdate { path: path.clone(), before, after: new.clone() })
+            }
+            FieldChange::Scale { path, factor } => {
+                let field = value.pointer_mut(path).ok_or_else(|| anyhow!("{} does not exist", path))?;
+                let before = field.as_f64().ok_or_else(|| anyhow!("{} is not a number", path))?;
+                *field = Value::from(before * factor);
+                Ok(FieldUpdate { path: path.clone(), before: Value::from(before), after: field.clone() })
+            }
+        }
+    }
+}
+
+/// `polar` with the `changes` applied in order, and the updates of its fields.
+pub fn change_fields(polar: &Polar, changes: &[FieldChange]) -> Result<(Polar, Vec<FieldUpdate>)> {
+    let mut value = serde_json::to_value(polar)?;
+    let updates = changes.iter().map(|change| change.apply(&mut value)).collect::<Result<Vec<_>>>()?;
+    Ok((serde_json::from_value(value)?, updates))
+}
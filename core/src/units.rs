@@ -0,0 +1,56 @@
+#[cfg(feature = "rocket")]
+use rocket::form::{FromForm, FromFormField};
+use serde::Serialize;
+
+/// Speed units. Polars are stored in knots.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "rocket", derive(FromFormField))]
+#[serde(rename_all = "camelCase")]
+pub enum Units {
+    #[default]
+    Kts,
+    Ms,
+    Kmh,
+}
+
+impl Units {
+    /// Converts a speed in knots to these units.
+    pub fn convert(&self, knots: f64) -> f64 {
+        match self {
+            Units::Kts => knots,
+            Units::Ms => knots * 1852.0 / 3600.0,
+            Units::Kmh => knots * 1.852,
+        }
+    }
+
+    /// Converts a speed in these units to knots.
+    pub fn to_knots(&self, value: f64) -> f64 {
+        value / self.convert(1.0)
+    }
+}
+
+/// Rounds `value` to `precision` decimals.
+pub fn round(value: f64, precision: u8) -> f64 {
+    let factor = 10f64.powi(precision as i32);
+    (value * factor).round() / factor
+}
+
+/// Units and precision of the speeds in a response.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "rocket", derive(FromForm))]
+pub struct SpeedFormat {
+    #[cfg_attr(feature = "rocket", field(default = Units::Kts))]
+    pub units: Units,
+    pub precision: Option<u8>,
+}
+
+impl SpeedFormat {
+    /// Converts a speed in knots to this format.
+    pub fn apply(&self, knots: f64) -> f64 {
+        let value = self.units.convert(knots);
+        match self.precision {
+            Some(precision) => round(value, precision),
+            None => value,
+        }
+    }
+}
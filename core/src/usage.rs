@@ -0,0 +1,305 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use log::error;
+use serde::{Deserialize, Serialize};
+
+/// Length of the usage history buckets, in seconds.
+const BUCKET: u64 = 3600;
+
+/// Days of usage history kept.
+pub const RETENTION_DAYS: u64 = 90;
+
+/// Buckets older than this are dropped, in seconds.
+const RETENTION: u64 = RETENTION_DAYS * 86400;
+
+/// Number of polars in the top of a usage report.
+const TOP_POLARS: usize = 20;
+
+/// How often a polar was used.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Counter {
+    /// polar fetches, i.e. `GET /polars/<id>`
+    pub fetches: u64,
+    /// speed queries
+    pub evaluations: u64,
+}
+
+/// Requests of an endpoint, errors being the 4xx and 5xx responses.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Volume {
+    pub requests: u64,
+    pub errors: u64,
+}
+
+impl Volume {
+    fn add(&mut self, other: &Volume) {
+        self.requests += other.requests;
+        self.errors += other.errors;
+    }
+
+    fn error_rate(&self) -> f64 {
+        if self.requests == 0 { 0.0 } else { self.errors as f64 / self.requests as f64 }
+    }
+}
+
+/// Usage during the `BUCKET` seconds from `start`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct Bucket {
+    start: u64,
+    #[serde(default)]
+    polars: BTreeMap<String, Counter>,
+    /// by route name
+    #[serde(default)]
+    endpoints: BTreeMap<String, Volume>,
+    /// requests of deprecated routes, by route name
+    #[serde(default)]
+    deprecated: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointUsage {
+    #[serde(flatten)]
+    pub volume: Volume,
+    pub error_rate: f64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PolarUsage {
+    pub id: String,
+    #[serde(flatten)]
+    pub counter: Counter,
+}
+
+/// Usage over the window `[from, to)`, in seconds since epoch.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageReport {
+    pub from: u64,
+    pub to: u64,
+    pub requests: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+    pub endpoints: BTreeMap<String, EndpointUsage>,
+    /// most used polars, by fetches and evaluations
+    pub top_polars: Vec<PolarUsage>,
+    /// requests of deprecated routes, by route name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub deprecated: BTreeMap<String, u64>,
+}
+
+/// Name, help and value of a counter metric.
+type Metric = (&'static str, &'static str, fn(&Counter) -> u64);
+
+/// Usage counters by polar id since the service started, and an hourly history
+/// of polar and endpoint usage, saved to `file` when given.
+#[derive(Debug, Default)]
+pub struct Usage {
+    counters: Mutex<HashMap<String, Counter>>,
+    /// requests of deprecated routes since the service started, by route name
+    deprecated: Mutex<BTreeMap<String, u64>>,
+    history: Mutex<Vec<Bucket>>,
+    file: Option<PathBuf>,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Length of a period such as `24h` or `7d`, in seconds. `None` when malformed, empty or too
+/// long to be counted in seconds.
+pub fn parse_period(period: &str) -> Option<u64> {
+    let unit = match period.chars().last()? {
+        'h' => 3600,
+        'd' => 86400,
+        _ => return None,
+    };
+    let count: u64 = period[..period.len() - 1].parse().ok()?;
+    count.checked_mul(unit).filter(|_| count > 0)
+}
+
+impl Usage {
+    /// Usage with the history saved in `file`, starting from its content when it exists.
+    pub fn load<P: Into<PathBuf>>(file: P) -> Self {
+        let file = file.into();
+        let history = match Self::read_history(&file) {
+            Ok(history) => history,
+            Err(e) => {
+                error!("Error reading usage history {:?} : {}", file, e);
+                Vec::new()
+            }
+        };
+        Usage { counters: Mutex::new(HashMap::new()), deprecated: Mutex::new(BTreeMap::new()), history: Mutex::new(history), file: Some(file) }
+    }
+
+    fn read_history(file: &Path) -> Result<Vec<Bucket>> {
+        if !file.exists() {
+            return Ok(Vec::new());
+        }
+        let history: Option<Vec<Bucket>> = serde_yaml::from_reader(BufReader::new(File::open(file)?))?;
+        Ok(history.unwrap_or_default())
+    }
+
+    fn save_history(&self, history: &[Bucket]) {
+        if let Some(file) = &self.file {
+            let res = File::create(file).map_err(anyhow::Error::from)
+                .and_then(|f| serde_yaml::to_writer(f, history).map_err(anyhow::Error::from));
+            if let Err(e) = res {
+                error!("Error saving usage history {:?} : {}", file, e);
+            }
+        }
+    }
+
+    /// Updates the bucket of the current hour. The history is saved each time an hour starts,
+    /// so at most the current hour is lost on restart.
+    fn record(&self, f: impl FnOnce(&mut Bucket)) {
+        let start = now() / BUCKET * BUCKET;
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        if history.last().is_none_or(|b| b.start != start) {
+            history.retain(|b| b.start + RETENTION > start);
+            self.save_history(&history);
+            history.push(Bucket { start, ..Bucket::default() });
+        }
+        if let Some(bucket) = history.last_mut() {
+            f(bucket);
+        }
+    }
+
+    fn update(&self, polar_id: &str, f: impl Fn(&mut Counter)) {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        f(counters.entry(polar_id.to_string()).or_default());
+        drop(counters);
+        self.record(|bucket| f(bucket.polars.entry(polar_id.to_string()).or_default()));
+    }
+
+    pub fn fetched(&self, polar_id: &str) {
+        self.update(polar_id, |c| c.fetches += 1);
+    }
+
+    pub fn evaluated(&self, polar_id: &str) {
+        self.update(polar_id, |c| c.evaluations += 1);
+    }
+
+    /// Records a request of `endpoint`, a route name.
+    pub fn requested(&self, endpoint: &str, error: bool) {
+        self.record(|bucket| {
+            let volume = bucket.endpoints.entry(endpoint.to_string()).or_default();
+            volume.requests += 1;
+            volume.errors += error as u64;
+        });
+    }
+
+    /// Records a request of the deprecated route `route`, a route name.
+    pub fn deprecated_requested(&self, route: &str) {
+        *self.deprecated.lock().unwrap_or_else(|e| e.into_inner()).entry(route.to_string()).or_default() += 1;
+        self.record(|bucket| *bucket.deprecated.entry(route.to_string()).or_default() += 1);
+    }
+
+    pub fn of(&self, polar_id: &str) -> Counter {
+        let counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        counters.get(polar_id).copied().unwrap_or_default()
+    }
+
+    pub fn counters(&self) -> BTreeMap<String, Counter> {
+        let counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        counters.iter().map(|(id, c)| (id.clone(), *c)).collect()
+    }
+
+    /// Start of the oldest hour of the history, `None` when it is empty.
+    pub fn history_start(&self) -> Option<u64> {
+        let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        history.first().map(|b| b.start)
+    }
+
+    /// End of the last hour each polar was fetched or evaluated in, according to the history.
+    pub fn last_used(&self) -> HashMap<String, u64> {
+        let mut last_used = HashMap::new();
+        let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        for bucket in history.iter() {
+            for (id, counter) in &bucket.polars {
+                if counter.fetches + counter.evaluations > 0 {
+                    last_used.insert(id.clone(), bucket.start + BUCKET);
+                }
+            }
+        }
+        last_used
+    }
+
+    /// Usage over the last `period` seconds, by whole hours.
+    pub fn report(&self, period: u64) -> UsageReport {
+        let to = now();
+        let from = (to.saturating_sub(period)) / BUCKET * BUCKET;
+
+        let mut total = Volume::default();
+        let mut endpoints: BTreeMap<String, Volume> = BTreeMap::new();
+        let mut polars: BTreeMap<String, Counter> = BTreeMap::new();
+        let mut deprecated: BTreeMap<String, u64> = BTreeMap::new();
+        let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        for bucket in history.iter().filter(|b| b.start >= from) {
+            for (endpoint, volume) in &bucket.endpoints {
+                endpoints.entry(endpoint.clone()).or_default().add(volume);
+                total.add(volume);
+            }
+            for (id, counter) in &bucket.polars {
+                let c = polars.entry(id.clone()).or_default();
+                c.fetches += counter.fetches;
+                c.evaluations += counter.evaluations;
+            }
+            for (route, requests) in &bucket.deprecated {
+                *deprecated.entry(route.clone()).or_default() += requests;
+            }
+        }
+        drop(history);
+
+        let mut top_polars: Vec<PolarUsage> = polars.into_iter().map(|(id, counter)| PolarUsage { id, counter }).collect();
+        top_polars.sort_by_key(|p| std::cmp::Reverse(p.counter.fetches + p.counter.evaluations));
+        top_polars.truncate(TOP_POLARS);
+
+        UsageReport {
+            from,
+            to,
+            requests: total.requests,
+            errors: total.errors,
+            error_rate: total.error_rate(),
+            endpoints: endpoints.into_iter()
+                .map(|(endpoint, volume)| (endpoint, EndpointUsage { volume, error_rate: volume.error_rate() }))
+                .collect(),
+            top_polars,
+            deprecated,
+        }
+    }
+
+    /// Counters in the Prometheus text exposition format.
+    pub fn prometheus(&self) -> String {
+        let counters = self.counters();
+        let mut out = String::new();
+        let metrics: [Metric; 2] = [
+            ("polar_fetches_total", "Polar fetches by polar id.", |c| c.fetches),
+            ("polar_evaluations_total", "Speed queries by polar id.", |c| c.evaluations),
+        ];
+        for (name, help, value) in metrics {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (id, counter) in &counters {
+                let _ = writeln!(out, "{}{{polar=\"{}\"}} {}", name, id.replace('\\', "\\\\").replace('"', "\\\""), value(counter));
+            }
+        }
+        let _ = writeln!(out, "# HELP deprecated_requests_total Requests of deprecated routes by route name.");
+        let _ = writeln!(out, "# TYPE deprecated_requests_total counter");
+        for (route, requests) in self.deprecated.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            let _ = writeln!(out, "deprecated_requests_total{{route=\"{}\"}} {}", route, requests);
+        }
+        out
+    }
+}
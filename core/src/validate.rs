@@ -0,0 +1,165 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::polar::Polar;
+
+/// Invalid field of a polar, located by the JSON pointer of the field in the polar, e.g.
+/// `/sail/2/speed/5` for the sixth row of the speeds of the third sail.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationError {
+    pub pointer: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<Value>,
+    /// how `actual` compares to `expected` when valid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bound: Option<Bound>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual: Option<Value>,
+}
+
+/// Kind of the bound of a field, `expected` being its value.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Bound {
+    Equal,
+    AtMost,
+}
+
+impl ValidationError {
+    fn new(pointer: String, message: String, expected: Option<Value>, actual: Option<Value>) -> Self {
+        ValidationError { pointer, message, expected, bound: None, actual }
+    }
+
+    /// Error of the field at `pointer` whose value `actual` is out of the `bound` of `expected`.
+    fn bounded(pointer: String, message: String, expected: Value, bound: Bound, actual: Value) -> Self {
+        ValidationError { pointer, message, expected: Some(expected), bound: Some(bound), actual: Some(actual) }
+    }
+
+    /// Error of the unknown field at `path`, as reported by `polar::unknown_fields`, `actual`
+    /// being the name of the field.
+    pub fn unknown_field(path: &str) -> Self {
+        let name = path.rsplit('.').next().unwrap_or(path);
+        Self::new(pointer(path.split('.')), format!("{} is not a known field", path), None, Some(Value::from(name)))
+    }
+
+    /// Error of a field failing to deserialize at the path made of `segments`.
+    pub fn malformed<'a>(segments: impl IntoIterator<Item = &'a str>, message: String) -> Self {
+        Self::new(pointer(segments), message, None, None)
+    }
+}
+
+/// JSON pointer of the field at the path made of `segments`, escaping `~` and `/` in them.
+pub fn pointer<'a>(segments: impl IntoIterator<Item = &'a str>) -> String {
+    segments.into_iter()
+        .filter(|s| !s.is_empty())
+        .map(|s| format!("/{}", s.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+/// Structural errors of `polar` : speed matrices of its sails not matching its twa and tws
+/// axes.
+pub fn validate(polar: &Polar) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+
+    for (s, sail) in polar.sail.iter().enumerate() {
+        let matrices = [("speed", Some(&sail.speed)), ("portSpeed", sail.port_speed.as_ref())];
+        for (field, matrix) in matrices.into_iter().filter_map(|(field, matrix)| matrix.map(|m| (field, m))) {
+            let at = format!("/sail/{}/{}", s, field);
+            if matrix.len() != polar.twa.len() {
+                errors.push(ValidationError::bounded(at.clone(), "one row is expected per twa".to_string(),
+                    Value::from(polar.twa.len() as u64), Bound::Equal, Value::from(matrix.len() as u64)));
+            }
+            for (i, row) in matrix.iter().enumerate() {
+                if row.len() != polar.tws.len() {
+                    errors.push(ValidationError::bounded(format!("{}/{}", at, i), "one speed is expected per tws".to_string(),
+                        Value::from(polar.tws.len() as u64), Bound::Equal, Value::from(row.len() as u64)));
+                }
+            }
+        }
+    }
+
+    errors
+}
+
+/// Speeds of `polar` above its max speed, on both tacks, none when it has no max speed.
+pub fn above_max_speed(polar: &Polar) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    if polar.max_speed <= 0.0 {
+        return errors;
+    }
+
+    for (s, sail) in polar.sail.iter().enumerate() {
+        let matrices = [("speed", Some(&sail.speed)), ("portSpeed", sail.port_speed.as_ref())];
+        for (field, matrix) in matrices.into_iter().filter_map(|(field, matrix)| matrix.map(|m| (field, m))) {
+            for (i, row) in matrix.iter().enumerate() {
+                for (j, v) in row.iter().enumerate().filter(|(_, v)| **v > polar.max_speed) {
+                    errors.push(ValidationError::bounded(format!("/sail/{}/{}/{}/{}", s, field, i, j), "speed is above max speed".to_string(),
+                        Value::from(polar.max_speed), Bound::AtMost, Value::from(*v)));
+                }
+            }
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::test_polar;
+
+    fn polar() -> Polar {
+        test_polar("validate", 2, 10, 5)
+    }
+
+    #[test]
+    fn scaffold_is_valid() {
+        let polar = polar();
+        assert!(validate(&polar).is_empty());
+        assert!(above_max_speed(&polar).is_empty());
+    }
+
+    #[test]
+    fn points_to_the_matrices_off_the_grid() {
+        let mut polar = polar();
+        polar.sail[0].speed.pop();
+        let mut port_speed = polar.sail[1].speed.clone();
+        port_speed[3].push(1.0);
+        polar.sail[1].port_speed = Some(port_speed);
+
+        let errors = validate(&polar);
+        assert_eq!(errors.iter().map(|e| e.pointer.as_str()).collect::<Vec<_>>(), ["/sail/0/speed", "/sail/1/portSpeed/3"]);
+        assert_eq!(errors[0].expected, Some(Value::from(polar.twa.len() as u64)));
+        assert_eq!(errors[0].actual, Some(Value::from(polar.twa.len() as u64 - 1)));
+        assert_eq!(errors[1].bound, Some(Bound::Equal));
+        assert_eq!(errors[1].actual, Some(Value::from(polar.tws.len() as u64 + 1)));
+    }
+
+    #[test]
+    fn points_to_the_speeds_above_max_speed_on_both_tacks() {
+        let mut polar = polar();
+        polar.max_speed = 50.0;
+        polar.sail[0].speed[2][4] = 51.0;
+        let mut port_speed = polar.sail[0].speed.clone();
+        port_speed[2][4] = 10.0;
+        port_speed[5][1] = 52.0;
+        polar.sail[0].port_speed = Some(port_speed);
+
+        let errors = above_max_speed(&polar);
+        assert_eq!(errors.iter().map(|e| e.pointer.as_str()).collect::<Vec<_>>(), ["/sail/0/speed/2/4", "/sail/0/portSpeed/5/1"]);
+        assert!(errors.iter().all(|e| e.bound == Some(Bound::AtMost) && e.expected == Some(Value::from(50.0))));
+        assert_eq!(errors[1].actual, Some(Value::from(52.0)));
+
+        polar.max_speed = 0.0;
+        assert!(above_max_speed(&polar).is_empty());
+    }
+
+    #[test]
+    fn escapes_pointers() {
+        assert_eq!(pointer(["labels", "en/GB", "a~b"]), "/labels/en~1GB/a~0b");
+        assert_eq!(pointer(["", "foil"]), "/foil");
+        assert_eq!(ValidationError::unknown_field("foil.speedRatoi").pointer, "/foil/speedRatoi");
+    }
+}
@@ -0,0 +1,207 @@
+use std::io::Read;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rocket::{get, post, Request, Response, Route, routes, State};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+
+use nav_polars_core::service::{ImportResult, InactivePolar, PolarService, Reload};
+use nav_polars_core::store::{StorageReport, Tiering, VerifyReport};
+use nav_polars_core::preset::Preset;
+use nav_polars_core::audit::AuditEntry;
+use nav_polars_core::usage::UsageReport;
+
+/// Longest download of a seed archive, in seconds.
+const SEED_TIMEOUT: u64 = 60;
+
+/// Largest seed archive downloaded, in bytes.
+const MAX_SEED_DOWNLOAD: u64 = 64 * 1024 * 1024;
+
+pub(crate) fn routes() -> Vec<Route> {
+    routes![usage, storage, reload, verify, tier, inactive, audit, seed]
+}
+
+/// Top requested polars, request volumes and error rates per endpoint over the last `period`,
+/// e.g. `24h` or `7d` (the default).
+#[get("/usage?<period>")]
+async fn usage(polar_service: &State<Arc<PolarService>>, period: Option<String>) -> Result<Json<UsageReport>, Status> {
+
+    let period = nav_polars_core::usage::parse_period(period.as_deref().unwrap_or("7d")).ok_or(Status::BadRequest)?;
+
+    Ok(Json(polar_service.usage().report(period)))
+}
+
+/// Entries of the audit log, oldest first, only those of `polar` when given.
+#[get("/audit?<polar>")]
+async fn audit(polar_service: &State<Arc<PolarService>>, polar: Option<String>) -> Result<Json<Vec<AuditEntry>>, Status> {
+
+    match polar_service.audit().entries(polar.as_deref()) {
+        Ok(entries) => Ok(Json(entries)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+/// File counts, sizes and largest polars of the polar directories.
+#[get("/storage")]
+async fn storage(polar_service: &State<Arc<PolarService>>) -> Result<Json<StorageReport>, Status> {
+
+    match polar_service.storage().await {
+        Ok(report) => Ok(Json(report)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+/// Rescans the polar directories and drops the cached evaluation tables, as SIGHUP does.
+#[post("/reload")]
+async fn reload(polar_service: &State<Arc<PolarService>>) -> Result<Json<Reload>, Status> {
+
+    match polar_service.reload().await {
+        Ok(reload) => Ok(Json(reload)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+/// Checks every polar file against its checksum, reporting corrupted or modified files.
+#[post("/verify")]
+async fn verify(polar_service: &State<Arc<PolarService>>) -> Result<Json<VerifyReport>, Status> {
+
+    match polar_service.store().verify() {
+        Ok(report) => Ok(Json(report)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+/// Active polars unused for the configured period, and when the daily run archives them.
+#[get("/inactive")]
+async fn inactive(polar_service: &State<Arc<PolarService>>) -> Result<Json<Vec<InactivePolar>>, Status> {
+
+    match polar_service.inactive().await {
+        Ok(inactive) => Ok(Json(inactive)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+/// Moves the old archived polars to the cold directory now instead of waiting for the daily run.
+#[post("/tier")]
+async fn tier(polar_service: &State<Arc<PolarService>>) -> Result<Json<Tiering>, Status> {
+
+    match polar_service.store().tier() {
+        Ok(tiering) => Ok(Json(tiering)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+/// Creates the polar of `preset`, of every bundled preset when not given, in an instance without
+/// active polars.
+#[post("/seed?<preset>")]
+async fn seed(polar_service: &State<Arc<PolarService>>, preset: Option<Preset>) -> Result<Json<Vec<ImportResult>>, Status> {
+
+    let polars = polar_service.list(None).await.map_err(|_| Status::InternalServerError)?;
+    if !polars.is_empty() {
+        return Err(Status::Conflict);
+    }
+
+    let presets = preset.map_or(Preset::ALL.to_vec(), |preset| vec![preset]);
+    match polar_service.seed(&presets).await {
+        Ok(results) => Ok(Json(results)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+/// Seeds `polar_service` when it has no active polar : from the archive at `seed_url` when
+/// given, from the bundled presets otherwise.
+pub(crate) async fn bootstrap(polar_service: &PolarService, seed_url: Option<String>) {
+    match polar_service.list(None).await {
+        Ok(polars) if !polars.is_empty() => return,
+        Ok(_) => {}
+        Err(e) => {
+            println!("Error listing polars before bootstrap : {}", e);
+            return;
+        }
+    }
+
+    let results = match seed_url {
+        Some(url) => {
+            let download = rocket::tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<u8>> {
+                let mut archive = Vec::new();
+                ureq::get(&url).timeout(Duration::from_secs(SEED_TIMEOUT)).call()?.into_reader().take(MAX_SEED_DOWNLOAD).read_to_end(&mut archive)?;
+                if archive.len() as u64 >= MAX_SEED_DOWNLOAD {
+                    return Err(anyhow::anyhow!("seed archive {} is larger than {} bytes", url, MAX_SEED_DOWNLOAD));
+                }
+                Ok(archive)
+            });
+            match download.await {
+                Ok(Ok(archive)) => polar_service.seed_archive(&archive).await,
+                Ok(Err(e)) => Err(e),
+                Err(e) => Err(e.into()),
+            }
+        }
+        None => polar_service.seed(&Preset::ALL).await,
+    };
+    match results {
+        Ok(results) => println!("Bootstrapped {} polars", results.len()),
+        Err(e) => println!("Error bootstrapping polars : {}", e),
+    }
+}
+
+/// Every day, reports the inactive polars of `polar_service` and archives those past their
+/// notice, then moves its old archived polars to its cold directory.
+pub(crate) async fn run_daily(polar_service: Arc<PolarService>) {
+    let mut interval = rocket::tokio::time::interval(rocket::tokio::time::Duration::from_secs(86_400));
+    loop {
+        interval.tick().await;
+        match polar_service.inactive().await {
+            Ok(inactive) => inactive.iter().for_each(|p| println!("Polar {} is inactive, it is archived at {}", p.id, p.archive_at)),
+            Err(e) => println!("Error looking for inactive polars : {}", e),
+        }
+        match polar_service.archive_inactive().await {
+            Ok(archived) if !archived.is_empty() => println!("Archived inactive polars {}", archived.join(", ")),
+            Ok(_) => {}
+            Err(e) => println!("Error archiving inactive polars : {}", e),
+        }
+        match polar_service.store().tier() {
+            Ok(tiering) if !tiering.moved.is_empty() => println!("Moved {} archived polars to cold storage", tiering.moved.len()),
+            Ok(_) => {}
+            Err(e) => println!("Error moving archived polars to cold storage : {}", e),
+        }
+    }
+}
+
+/// Reloads `polar_service` on every SIGHUP.
+#[cfg(unix)]
+pub(crate) async fn reload_on_hangup(polar_service: Arc<PolarService>) {
+    use rocket::tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            println!("Couldn't listen to SIGHUP : {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        match polar_service.reload().await {
+            Ok(reload) => println!("Reloaded {} polars and {} archived polars", reload.polars, reload.archived),
+            Err(e) => println!("Error reloading polars : {}", e),
+        }
+    }
+}
+
+/// Records the routed requests in the usage history of the polar service.
+pub(crate) struct UsageRecorder;
+
+#[rocket::async_trait]
+impl Fairing for UsageRecorder {
+    fn info(&self) -> Info {
+        Info { name: "Usage recorder", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let name = request.route().and_then(|route| route.name.as_deref());
+        if let (Some(name), Some(polar_service)) = (name, request.rocket().state::<Arc<PolarService>>()) {
+            polar_service.usage().requested(name, response.status().code >= 400);
+        }
+    }
+}
@@ -0,0 +1,15 @@
+use std::sync::Arc;
+
+use rocket::{get, Route, routes, State};
+
+use nav_polars_core::service::PolarService;
+
+pub(crate) fn routes() -> Vec<Route> {
+    routes![metrics]
+}
+
+/// Usage counters in the Prometheus text format.
+#[get("/metrics")]
+async fn metrics(polar_service: &State<Arc<PolarService>>) -> String {
+    polar_service.usage().prometheus()
+}
@@ -1,9 +1,25 @@
 use rocket::{Build, Rocket};
 
+pub(crate) mod admin;
+pub(crate) mod metrics;
+pub(crate) mod ui;
 pub(crate) mod v1;
+/// Polar resources with JSON error bodies, summary and full representations and sub-resources,
+/// evaluation endpoints being served by v1 only.
+pub(crate) mod v2;
+pub(crate) mod version;
+pub(crate) mod wire;
 
-pub(crate) fn init() -> Rocket<Build> {
+pub(crate) fn init(deprecation: v1::Deprecation) -> Rocket<Build> {
 
     rocket::build()
-        .mount("/polars/api/v1", v1::routes())
+        .mount(v1::BASE, v1::routes())
+        .mount(v2::BASE, v2::routes())
+        .register(v2::BASE, v2::catchers())
+        .mount("/", metrics::routes())
+        .mount("/admin", admin::routes())
+        .mount("/polars/ui", ui::routes())
+        .attach(admin::UsageRecorder)
+        .attach(deprecation)
+        .attach(version::Negotiation)
 }
@@ -0,0 +1,15 @@
+use rocket::{get, Route, routes};
+use rocket::response::content::Html;
+
+/// Single page admin UI, compiled into the binary.
+const INDEX: &str = include_str!("../../ui/index.html");
+
+pub(crate) fn routes() -> Vec<Route> {
+    routes![index]
+}
+
+/// Browsing, editing, archiving and comparing polars through the v1 API.
+#[get("/")]
+async fn index() -> Html<&'static str> {
+    Html(INDEX)
+}
@@ -1,96 +1,564 @@
-use rocket::{delete, get, post, put, Route, routes, State};
-use rocket::form::{FromForm, FromFormField};
-use rocket::http::Status;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rocket::{delete, get, post, put, Data, Request, Response, Route, routes, State};
+use rocket::data::ToByteUnit;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::form::FromFormField;
+use rocket::http::{ContentType, Header, Status};
+use rocket::response::Responder;
 use rocket::serde::json::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::polar::{Polar, PolarError, PolarService};
+use nav_polars_core::compare::{Comparison, RankBy, Ranked};
+use nav_polars_core::compute::{self, AngleConvention, AutoSail, Current, Options};
+use nav_polars_core::diff::PolarDiff;
+use nav_polars_core::eta::{Eta, Leg, Scenario, ScenarioResult};
+use nav_polars_core::export::{ExportFormat, Steps, Surface};
+use nav_polars_core::import::ImportFormat;
+use nav_polars_core::lint::{Finding, LintReport};
+use nav_polars_core::matrix::{self, Axis, Extrapolation, Interpolation, Matrix, Smoothing};
+use nav_polars_core::penalty::{self, Maneuver, ManeuverTable, Penalties, Timeline, WinchKind};
+use nav_polars_core::migrate;
+use nav_polars_core::polar::{BoatOption, DefaultScope, Polar, PolarError, SailDefinition, SailRemap, SpeedDiff, WinchProfile};
+use nav_polars_core::reservation::Reservation;
+use nav_polars_core::service::{ImportOutcome, ImportPreview, ImportResult, ManifestEntry, OnConflict, PolarMeta, PolarService, Selection, SortKey};
+use nav_polars_core::store::Revision;
+use nav_polars_core::repair::{AppliedFix, Fix};
+use nav_polars_core::sensitivity::{Perturbation, Sensitivity};
+use nav_polars_core::stats::{CatalogStats, PolarStats, Reach, SailStats};
+use nav_polars_core::template::TemplateParams;
+use nav_polars_core::transform::{BulkResult, BulkUpdate, Transform};
+use nav_polars_core::units::{SpeedFormat, Units};
+use nav_polars_core::validate::ValidationError;
 
-pub(crate) fn routes() -> Vec<Route> {
-    routes![list, get, find_by_polar_id, post, put, delete, archive, restore]
-}
+use crate::api::wire::Wire;
 
-#[derive(FromForm)]
-struct Sort {
-    sort_by: String,
-    #[field(default = Order::Asc)]
-    order: Order,
+pub(crate) fn routes() -> Vec<Route> {
+    routes![list, count, manifest, rank, rank_scenario, export_all, get, meta, fingerprint, find_by_polar_id, find_by_label, get_default, set_default, post, import, variants, reserve_id, from_template, put, put_signature, delete, bulk_update, remap, archive, restore, smooth, transform, merge, append_sails, regrid, blend, diff, compare, catalog_stats, stats, sail_stats, reach, lint, repair, revisions, get_revision, speed, ground_speed, mapped_speed, grid, export, surface, auto_sail, penalties, maneuver, maneuvers, eta, evaluate_scenario, sensitivity, list_sails, get_sail, post_sail, put_sail, delete_sail, list_winches, get_winch, post_winch, put_winch, delete_winch]
 }
 
-#[derive(FromFormField)]
+#[derive(FromFormField, Clone, Copy, PartialEq, Eq)]
 enum Order {
     Asc,
     Desc
 }
 
-#[get("/polars?<archived>&<sort..>", rank = 25)]
-async fn list(polar_service: &State<PolarService>, archived: Option<bool>, sort: Option<Sort>) -> Result<Json<Vec<Polar>>, Status> {
+/// List response wrapped with its pagination metadata, for clients that can't rely on headers.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Envelope<T> {
+    data: T,
+    page: Page,
+    /// number of polars of the selection, all pages included
+    total: usize,
+    /// in seconds since epoch
+    generated_at: u64,
+    /// hash of the polars of the page, changing whenever one of them changes
+    etag: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Page {
+    /// starting at 1
+    number: usize,
+    size: usize,
+    count: usize,
+}
+
+impl Page {
+    /// Page `number` (the first one by default) of `size` items (all of them by default) out of
+    /// `total`, both starting at 1.
+    fn of(number: Option<usize>, size: Option<usize>, total: usize) -> Page {
+        let size = size.unwrap_or(total.max(1));
+        Page { number: number.unwrap_or(1), size, count: total.div_ceil(size) }
+    }
+
+    /// Items of `items` on the page, none past the last page.
+    fn items<T>(&self, items: Vec<T>) -> Vec<T> {
+        items.into_iter().skip((self.number - 1).saturating_mul(self.size)).take(self.size).collect()
+    }
+}
 
-    match polar_service.list(archived).await {
+#[derive(Responder)]
+enum Listed {
+    Plain(Wire<Vec<Polar>>),
+    Enveloped(Wire<Envelope<Vec<Polar>>>),
+}
+
+/// With `lang`, labels are given in that language when translated.
+/// With `summary`, the speed matrices are neither read nor returned.
+/// With `modified_since`, in seconds since epoch, only the polars modified since then are read.
+/// `sort_by` is a comma separated list of keys among `id`, `_id`, `label`, `class`, `maxSpeed`
+/// and `modified`, e.g. `-maxSpeed,id`, a `-` prefix sorting by a key in descending order and
+/// `order` giving the order of the keys without prefix. Ties are always sorted by id.
+/// With `per_page`, only the polars of `page` (the first one by default) are returned.
+/// With `envelope`, the polars are wrapped in `{data, page, total, generatedAt, etag}`.
+#[allow(clippy::too_many_arguments)]
+#[get("/polars?<precision>&<lang>&<summary>&<sort_by>&<order>&<envelope>&<page>&<per_page>&<selection..>", rank = 25)]
+async fn list(polar_service: &State<Arc<PolarService>>, precision: Option<u8>, lang: Option<String>, summary: Option<bool>, sort_by: Option<String>, order: Option<Order>, envelope: Option<bool>, page: Option<usize>, per_page: Option<usize>, selection: Selection) -> Result<Listed, Status> {
+
+    if page == Some(0) || per_page == Some(0) {
+        return Err(Status::BadRequest);
+    }
+    let keys = SortKey::parse_list(sort_by.as_deref().unwrap_or_default(), order == Some(Order::Desc)).ok_or(Status::BadRequest)?;
+
+    match polar_service.select(&selection, summary.unwrap_or(false)).await {
         Ok(polars) => {
             let mut polars: Vec<Polar> = polars.into_iter().map(|r| r.into()).collect();
-            if let Some(sort) = sort {
-                polars.sort_by(|a, b| {
-                    let (a, b) = match sort.order {
-                        Order::Asc => (a, b),
-                        Order::Desc => (b, a)
-                    };
-
-                    match sort.sort_by.as_str() {
-                        "id" => a.id.cmp(&b.id),
-                        "_id" => a.polar_id.cmp(&b.polar_id),
-                        _ => a.id.cmp(&b.id),
-                    }
-                })
-            }
-
-            Ok(Json(polars))
+            // sorted and paginated by the labels as returned
+            if let Some(lang) = lang {
+                polars.iter_mut().for_each(|p| p.localize(&lang));
+            }
+            nav_polars_core::service::sort(&mut polars, &keys);
+
+            let total = polars.len();
+            let page = Page::of(page, per_page, total);
+            let mut polars = page.items(polars);
+            if let Some(precision) = precision {
+                polars.iter_mut().for_each(|p| p.round(precision));
+            }
+
+            if !envelope.unwrap_or(false) {
+                return Ok(Listed::Plain(Wire(polars)));
+            }
+            let hashes: String = polars.iter().map(|p| p.hash()).collect();
+            Ok(Listed::Enveloped(Wire(Envelope {
+                page,
+                total,
+                generated_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+                etag: nav_polars_core::polar::content_hash(hashes.as_bytes()),
+                data: polars,
+            })))
         },
         Err(_) => Err(Status::InternalServerError)
     }
 }
 
-#[get("/polars/<polar_id>")]
-async fn get(polar_service: &State<PolarService>, polar_id: String) -> Result<Json<Polar>, Status> {
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Count {
+    count: usize,
+}
+
+#[get("/polars/count?<selection..>")]
+async fn count(polar_service: &State<Arc<PolarService>>, selection: Selection) -> Result<Json<Count>, Status> {
+
+    match polar_service.select(&selection, true).await {
+        Ok(polars) => Ok(Json(Count { count: polars.len() })),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+/// Id, content hash and last modification of each polar of the selection, for clients to find
+/// which of their cached polars are stale.
+#[get("/polars/manifest?<selection..>")]
+async fn manifest(polar_service: &State<Arc<PolarService>>, selection: Selection) -> Result<Json<Vec<ManifestEntry>>, Status> {
+
+    match polar_service.manifest(&selection).await {
+        Ok(manifest) => Ok(Json(manifest)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+/// Active polars ranked by their effective speed at `(twa, tws)`, or their best vmg at `tws` on
+/// the side of `twa` with `by=vmg`. Polars without any sail once fitted with the boat options are
+/// left out.
+#[get("/polars/rank?<twa>&<tws>&<by>&<options..>")]
+async fn rank(polar_service: &State<Arc<PolarService>>, twa: f64, tws: f64, by: Option<RankBy>, mut options: Options) -> Result<Json<Vec<Ranked>>, Status> {
+
+    options.extrapolation.get_or_insert_with(|| polar_service.default_extrapolation());
+    let polars = polar_service.list(None).await.map_err(|_| Status::InternalServerError)?;
+    let twa = AngleConvention::normalize(twa);
+
+    let mut ranked = Vec::with_capacity(polars.len());
+    for mut polar in polars {
+        options.fit(&mut polar).ok_or(Status::BadRequest)?;
+        let table = polar_service.table(&polar);
+        let best = compute::effective_speeds(&polar, &table, &options, &[(twa, tws)]).ok_or(Status::BadRequest)?;
+        if let Some(best) = best.first() {
+            ranked.extend(Ranked::new(&polar, twa, tws, *best));
+        }
+    }
+    nav_polars_core::compare::rank(&mut ranked, by.unwrap_or_default());
+    ranked.iter_mut().for_each(|r| r.format(options.format()));
+
+    Ok(Json(ranked))
+}
+
+/// Active polars ranked by their total time over the legs or wind histogram of `scenario`.
+#[post("/polars/rank", data = "<scenario>")]
+async fn rank_scenario(polar_service: &State<Arc<PolarService>>, scenario: Json<Scenario>) -> Result<Json<Vec<ScenarioResult>>, Status> {
+
+    if scenario.legs.is_empty() && scenario.histogram.is_empty() {
+        return Err(Status::BadRequest);
+    }
+    let polars = polar_service.list(None).await.map_err(|_| Status::InternalServerError)?;
+
+    Ok(Json(nav_polars_core::eta::evaluate(&polars, &scenario)))
+}
+
+/// Effective polars of the selection as YAML files in a gzip compressed tar.
+#[get("/polars/export?<selection..>")]
+async fn export_all(polar_service: &State<Arc<PolarService>>, selection: Selection) -> Result<(ContentType, Vec<u8>), Status> {
+
+    let polars = polar_service.select(&selection, false).await.map_err(|_| Status::InternalServerError)?;
+    match nav_polars_core::export::archive(&polars) {
+        Ok(archive) => Ok((ContentType::GZIP, archive)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+/// Response with the cache validators of a polar, Rocket answers HEAD requests with them too.
+#[derive(Responder)]
+struct Validated<T> {
+    inner: T,
+    etag: Header<'static>,
+    last_modified: Header<'static>,
+}
+
+impl Validated<Wire<Polar>> {
+    fn new(polar: Polar) -> Self {
+        Validated {
+            etag: Header::new("ETag", format!("\"{}\"", polar.hash())),
+            last_modified: Header::new("Last-Modified", http_date(polar.modified.unwrap_or_default())),
+            inner: Wire(polar),
+        }
+    }
+}
+
+/// Formats seconds since epoch as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn http_date(secs: u64) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+
+    // civil date from days since epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize], day, MONTHS[(month - 1) as usize], year, time / 3600, time % 3600 / 60, time % 60)
+}
+
+/// With `options`, the polar of a boat fitted with these comma separated options.
+/// With `lang`, the label is given in that language when translated.
+#[get("/polars/<polar_id>?<precision>&<options>&<lang>")]
+async fn get(polar_service: &State<Arc<PolarService>>, polar_id: String, precision: Option<u8>, options: Option<String>, lang: Option<String>) -> Result<Validated<Wire<Polar>>, Status> {
 
-    match polar_service.get(polar_id).await {
+    match polar_service.get(polar_id.clone()).await {
         Ok(None) => Err(Status::NotFound),
-        Ok(Some(polar)) => Ok(Json(polar.into())),
+        Ok(Some(mut polar)) => {
+            polar_service.usage().fetched(&polar_id);
+            compute::fit(&mut polar, options.as_deref()).ok_or(Status::BadRequest)?;
+            if let Some(precision) = precision {
+                polar.round(precision);
+            }
+            if let Some(lang) = lang {
+                polar.localize(&lang);
+            }
+            Ok(Validated::new(polar))
+        },
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Fingerprint {
+    id: String,
+    fingerprint: String,
+}
+
+/// Short hash of the effective polar ignoring its cosmetic fields, to label what was computed with it.
+#[get("/polars/<polar_id>/fingerprint")]
+async fn fingerprint(polar_service: &State<Arc<PolarService>>, polar_id: String) -> Result<Json<Fingerprint>, Status> {
+
+    let polar = load(polar_service, &polar_id).await?;
+    Ok(Json(Fingerprint { fingerprint: polar.fingerprint(), id: polar_id }))
+}
+
+#[get("/polars/<polar_id>/meta")]
+async fn meta(polar_service: &State<Arc<PolarService>>, polar_id: String) -> Result<Json<PolarMeta>, Status> {
+
+    match polar_service.meta(polar_id).await {
+        Ok(None) => Err(Status::NotFound),
+        Ok(Some(meta)) => Ok(Json(meta)),
         Err(_) => Err(Status::InternalServerError)
     }
 }
 
-#[get("/polars?<polar_id>")]
-async fn find_by_polar_id(polar_service: &State<PolarService>, polar_id: u8) -> Result<Json<Polar>, Status> {
+#[get("/polars?<polar_id>&<precision>")]
+async fn find_by_polar_id(polar_service: &State<Arc<PolarService>>, polar_id: u8, precision: Option<u8>) -> Result<Json<Polar>, Status> {
 
     match polar_service.find_by_polar_id(polar_id).await {
         Ok(None) => Err(Status::NotFound),
-        Ok(Some(polar)) => Ok(Json(polar.into())),
+        Ok(Some(mut polar)) => {
+            if let Some(id) = &polar.id {
+                polar_service.usage().fetched(id);
+            }
+            if let Some(precision) = precision {
+                polar.round(precision);
+            }
+            Ok(Json(polar))
+        },
         Err(_) => Err(Status::InternalServerError)
     }
 }
 
-#[post("/polars", data = "<polar>")]
-async fn post(polar_service: &State<PolarService>, polar: Json<Polar>) -> Status {
+/// Default polar of `class` when there is one, the global default otherwise.
+#[get("/polars/default?<class>")]
+async fn get_default(polar_service: &State<Arc<PolarService>>, class: Option<String>) -> Result<Json<Polar>, Status> {
 
-    let mut polar = polar.into_inner();
-    if polar.id.is_none() {
-        polar.id = polar.label.split("/").last().map(|x| x.to_string());
+    match polar_service.get_default(class.as_deref()).await {
+        Ok(None) => Err(Status::NotFound),
+        Ok(Some(polar)) => {
+            if let Some(id) = &polar.id {
+                polar_service.usage().fetched(id);
+            }
+            Ok(Json(polar))
+        },
+        Err(_) => Err(Status::InternalServerError)
     }
+}
 
-    match polar_service.create(&polar).await {
-        Ok(_) => Status::Created,
+/// `scope` defaults to the class of the polar when it has one, global otherwise.
+/// Fails with 422 when a polar to save doesn't pass the checks of its update.
+#[post("/polars/<polar_id>/default?<scope>")]
+async fn set_default(polar_service: &State<Arc<PolarService>>, polar_id: String, scope: Option<DefaultScope>) -> Status {
+
+    let scope = match scope {
+        Some(scope) => scope,
+        None => match polar_service.get(polar_id.clone()).await {
+            Ok(Some(polar)) if polar.class.is_some() => DefaultScope::Class,
+            Ok(_) => DefaultScope::Global,
+            Err(_) => return Status::InternalServerError,
+        },
+    };
+
+    match polar_service.set_default(polar_id, scope).await {
+        Ok(_) => Status::NoContent,
         Err(error) => {
             match error.downcast_ref::<PolarError>() {
-                Some(PolarError::AlreadyExists(_)) => Status::Conflict,
-                Some(PolarError::IdIsMandatory()) => Status::BadRequest,
+                Some(PolarError::NotFound(_)) => Status::NotFound,
+                Some(PolarError::Invalid(_)) => Status::UnprocessableEntity,
                 _ => Status::InternalServerError,
             }
         }
     }
 }
 
+#[get("/polars?<label>&<precision>", rank = 20)]
+async fn find_by_label(polar_service: &State<Arc<PolarService>>, label: String, precision: Option<u8>) -> Result<Json<Polar>, Status> {
+
+    match polar_service.find_by_label(&label).await {
+        Ok(None) => Err(Status::NotFound),
+        Ok(Some(mut polar)) => {
+            if let Some(id) = &polar.id {
+                polar_service.usage().fetched(id);
+            }
+            if let Some(precision) = precision {
+                polar.round(precision);
+            }
+            Ok(Json(polar))
+        },
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+/// Response of an import, the preview of what would be done for dry runs.
+#[derive(Responder)]
+enum Imported {
+    Done((Status, Json<ImportResult>)),
+    Preview(Json<Box<ImportPreview>>),
+}
+
+/// Failure of a polar write, with the offending fields when the polar is invalid.
+#[derive(Responder)]
+enum Rejected {
+    Invalid((Status, Json<Vec<ValidationError>>)),
+    Status(Status),
+}
+
+impl From<Status> for Rejected {
+    fn from(status: Status) -> Self {
+        Rejected::Status(status)
+    }
+}
+
+fn write_error(error: anyhow::Error) -> Rejected {
+    match error.downcast_ref::<PolarError>() {
+        Some(PolarError::Invalid(errors)) => Rejected::Invalid((Status::BadRequest, Json(errors.clone()))),
+        Some(PolarError::NotFound(_)) => Status::NotFound.into(),
+        Some(PolarError::AlreadyExists(_)) | Some(PolarError::PolarIdTaken(_)) => Status::Conflict.into(),
+        Some(PolarError::IdIsMandatory()) => Status::BadRequest.into(),
+        Some(PolarError::InvalidParent(_)) => Status::BadRequest.into(),
+        Some(PolarError::NoFreeSailId(_)) => Status::BadRequest.into(),
+        _ => Status::InternalServerError.into(),
+    }
+}
+
+/// Creates a polar, resolving a conflict with an existing polar of the same id with `on_conflict`
+/// (`fail` by default), and only parsing, validating and linting it with `dry_run`.
+/// An invalid polar is rejected with the JSON pointers of its offending fields, and a reserved
+/// `_id` can only be used with the token of its `reservation`.
+#[post("/polars?<dry_run>&<on_conflict>&<reservation>", data = "<polar>")]
+async fn post(polar_service: &State<Arc<PolarService>>, dry_run: Option<bool>, on_conflict: Option<OnConflict>, reservation: Option<String>, polar: Json<Value>) -> Result<Imported, Rejected> {
+
+    let mut value = polar.into_inner();
+    migrate::migrate(&mut value);
+    let unknown = nav_polars_core::polar::unknown_fields(&value);
+    let dry_run = dry_run.unwrap_or(false);
+    // a dry run reports the unknown fields rejected in strict mode as findings
+    let polar = if dry_run { polar_service.parse_lenient(value) } else { polar_service.parse(value) };
+    let mut polar = polar.map_err(write_error)?;
+    if polar.id.is_none() {
+        let slug = polar.label.rsplit('/').next().map(nav_polars_core::polar::slug).unwrap_or_default();
+        if !slug.is_empty() {
+            polar.id = Some(polar_service.available_id(&slug));
+        }
+    }
+
+    let on_conflict = on_conflict.unwrap_or_default();
+    if dry_run {
+        return polar_service.preview(&polar, unknown, on_conflict).map(|preview| Imported::Preview(Json(Box::new(preview)))).map_err(write_error);
+    }
+    match polar_service.import_reserved(&polar, on_conflict, reservation.as_deref()).await {
+        Ok(result) => {
+            let status = match result.outcome {
+                ImportOutcome::Created | ImportOutcome::Renamed => Status::Created,
+                ImportOutcome::Skipped | ImportOutcome::Overwritten => Status::Ok,
+            };
+            Ok(Imported::Done((status, Json(result))))
+        }
+        Err(error) => Err(write_error(error)),
+    }
+}
+
+/// Largest polar table accepted by `import`, in kibibytes.
+const IMPORT_LIMIT: u64 = 512;
+
+/// Creates polar `id` from the polar table of another app in the body, see `import::import`,
+/// with the `_id` of the existing polar `id`, the one of `reservation` or a free one. A table
+/// failing to parse is rejected with the line at fault.
+#[post("/polars/import?<format>&<id>&<on_conflict>&<reservation>", data = "<table>")]
+async fn import(polar_service: &State<Arc<PolarService>>, format: ImportFormat, id: String, on_conflict: Option<OnConflict>, reservation: Option<String>, table: Data<'_>) -> Result<(Status, Json<ImportResult>), Rejected> {
+
+    let table = table.open(IMPORT_LIMIT.kibibytes()).into_string().await.map_err(|_| Status::BadRequest)?;
+    if !table.is_complete() {
+        return Err(Status::PayloadTooLarge.into());
+    }
+    let polar = nav_polars_core::import::import(&table, format, &id)
+        .map_err(|e| Rejected::Invalid((Status::BadRequest, Json(vec![ValidationError::malformed([], e.to_string())]))))?;
+    match polar_service.import_new(&polar, on_conflict.unwrap_or_default(), reservation.as_deref()).await {
+        Ok(result) => {
+            let status = match result.outcome {
+                ImportOutcome::Created | ImportOutcome::Renamed => Status::Created,
+                ImportOutcome::Skipped | ImportOutcome::Overwritten => Status::Ok,
+            };
+            Ok((status, Json(result)))
+        }
+        Err(error) => Err(write_error(error)),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VariantsRequest {
+    /// option combinations, one variant each, e.g. `[["foil"], []]`
+    variants: Vec<Vec<BoatOption>>,
+}
+
+/// Creates the effective polars of the polar fitted with each option combination, with ids
+/// derived from the options, e.g. `imoca-foil-heavy`.
+#[post("/polars/<polar_id>/variants?<on_conflict>", data = "<request>")]
+async fn variants(polar_service: &State<Arc<PolarService>>, polar_id: String, on_conflict: Option<OnConflict>, request: Json<VariantsRequest>) -> Result<(Status, Json<Vec<ImportResult>>), Rejected> {
+
+    if request.variants.is_empty() {
+        return Err(Status::BadRequest.into());
+    }
+    let polar = load(polar_service, &polar_id).await?;
+
+    match polar_service.create_variants(&polar, &request.variants, on_conflict.unwrap_or_default()).await {
+        Ok(results) => Ok((Status::Created, Json(results))),
+        Err(error) => Err(write_error(error)),
+    }
+}
+
+/// Lifetime of an `_id` reservation when not given, in seconds.
+const RESERVATION_TTL: u64 = 86_400;
+
+/// Reserves the next free `_id` for `ttl` seconds, a day by default and a week at most, or until
+/// a polar is created with it. Creating a polar with that `_id` needs the returned token as
+/// `reservation` meanwhile.
+#[post("/polars/reserve-id?<ttl>")]
+async fn reserve_id(polar_service: &State<Arc<PolarService>>, ttl: Option<u64>) -> Result<Json<Reservation>, Status> {
+
+    match polar_service.reserve_id(ttl.unwrap_or(RESERVATION_TTL)).await {
+        Ok(reservation) => Ok(Json(reservation)),
+        Err(error) => {
+            match error.downcast_ref::<PolarError>() {
+                Some(PolarError::NoFreeId()) => Err(Status::Conflict),
+                _ => Err(Status::InternalServerError),
+            }
+        }
+    }
+}
+
+#[post("/polars/from-template?<dry_run>&<reservation>", data = "<params>")]
+async fn from_template(polar_service: &State<Arc<PolarService>>, dry_run: Option<bool>, reservation: Option<String>, params: Json<TemplateParams>) -> Result<(Status, Json<Polar>), Rejected> {
+
+    let polar = params.scaffold();
+    if dry_run.unwrap_or(false) {
+        return polar_service.check_import(&polar, OnConflict::Fail).map(|(polar, _)| (Status::Ok, Json(polar))).map_err(write_error);
+    }
+
+    match polar_service.create_reserved(&polar, reservation.as_deref()).await {
+        Ok(_) => Ok((Status::Created, Json(polar))),
+        Err(error) => Err(write_error(error)),
+    }
+}
+
+/// Applies field changes, e.g. setting `/winch/lws` or scaling `/globalSpeedRatio`, to every polar
+/// of the filter, only reporting them with `dry_run`.
+#[post("/polars/bulk-update?<dry_run>", data = "<update>")]
+async fn bulk_update(polar_service: &State<Arc<PolarService>>, dry_run: Option<bool>, update: Json<BulkUpdate>) -> Result<Json<Vec<BulkResult>>, Status> {
+
+    match polar_service.bulk_update(&update, dry_run.unwrap_or(false)).await {
+        Ok(results) => Ok(Json(results)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+/// Changes the `_id` of a polar, it must be neither used nor reserved.
+#[post("/polars/<polar_id>/remap?<_id>")]
+async fn remap(polar_service: &State<Arc<PolarService>>, polar_id: String, _id: u8) -> Result<Json<Polar>, Status> {
+
+    match polar_service.remap(polar_id, _id).await {
+        Ok(polar) => Ok(Json(polar)),
+        Err(error) => {
+            match error.downcast_ref::<PolarError>() {
+                Some(PolarError::NotFound(_)) => Err(Status::NotFound),
+                Some(PolarError::PolarIdTaken(_)) => Err(Status::Conflict),
+                _ => Err(Status::InternalServerError),
+            }
+        }
+    }
+}
+
 #[post("/polars/<polar_id>/archive")]
-async fn archive(polar_service: &State<PolarService>, polar_id: String) -> Status {
+async fn archive(polar_service: &State<Arc<PolarService>>, polar_id: String) -> Status {
     match polar_service.archive(polar_id).await {
         Ok(_) => Status::Ok,
         Err(error) => {
@@ -103,7 +571,7 @@ async fn archive(polar_service: &State<PolarService>, polar_id: String) -> Statu
 }
 
 #[post("/polars/<polar_id>/restore")]
-async fn restore(polar_service: &State<PolarService>, polar_id: String) -> Status {
+async fn restore(polar_service: &State<Arc<PolarService>>, polar_id: String) -> Status {
     match polar_service.restore(polar_id).await {
         Ok(_) => Status::Created,
         Err(error) => {
@@ -117,13 +585,30 @@ async fn restore(polar_service: &State<PolarService>, polar_id: String) -> Statu
 }
 
 #[put("/polars/<polar_id>", data = "<polar>")]
-async fn put(polar_service: &State<PolarService>, polar_id: String, polar: Json<Polar>) -> Status {
+async fn put(polar_service: &State<Arc<PolarService>>, polar_id: String, polar: Json<Value>) -> Result<Status, Rejected> {
+
+    let polar = polar_service.parse(polar.into_inner()).map_err(write_error)?;
+    match polar_service.update(polar_id, &polar).await {
+        Ok(_) => Ok(Status::NoContent),
+        Err(error) => {
+            match error.downcast_ref::<PolarError>() {
+                Some(PolarError::NotFound(_)) => Err(Status::NotFound.into()),
+                _ => Err(write_error(error)),
+            }
+        }
+    }
+}
 
-    match polar_service.update(polar_id, &polar.into_inner().into()).await {
+/// Attaches the hex encoded ed25519 detached signature of the polar file, made with a trusted key.
+#[put("/polars/<polar_id>/signature", data = "<signature>")]
+async fn put_signature(polar_service: &State<Arc<PolarService>>, polar_id: String, signature: String) -> Status {
+
+    match polar_service.attach_signature(polar_id, &signature).await {
         Ok(_) => Status::NoContent,
         Err(error) => {
             match error.downcast_ref::<PolarError>() {
                 Some(PolarError::NotFound(_)) => Status::NotFound,
+                Some(PolarError::InvalidSignature(_)) => Status::BadRequest,
                 _ => Status::InternalServerError,
             }
         }
@@ -131,7 +616,7 @@ async fn put(polar_service: &State<PolarService>, polar_id: String, polar: Json<
 }
 
 #[delete("/polars/<polar_id>")]
-async fn delete(polar_service: &State<PolarService>, polar_id: String) -> Status {
+async fn delete(polar_service: &State<Arc<PolarService>>, polar_id: String) -> Status {
 
     match polar_service.delete(polar_id).await {
         Ok(_) => Status::NoContent,
@@ -143,3 +628,909 @@ async fn delete(polar_service: &State<PolarService>, polar_id: String) -> Status
         }
     }
 }
+
+async fn load(polar_service: &PolarService, polar_id: &str) -> Result<Polar, Status> {
+    match polar_service.get(polar_id.to_string()).await {
+        Ok(None) => Err(Status::NotFound),
+        Ok(Some(polar)) => Ok(polar),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SmoothRequest {
+    #[serde(flatten)]
+    smoothing: Smoothing,
+    #[serde(default)]
+    axis: Axis,
+    /// sails to smooth, all of them when empty
+    #[serde(default)]
+    sails: Vec<u8>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SmoothPreview {
+    saved: bool,
+    #[serde(flatten)]
+    diff: SpeedDiff,
+}
+
+#[post("/polars/<polar_id>/smooth?<save>", data = "<request>")]
+async fn smooth(polar_service: &State<Arc<PolarService>>, polar_id: String, save: Option<bool>, request: Json<SmoothRequest>) -> Result<Json<SmoothPreview>, Rejected> {
+
+    if let Smoothing::MovingAverage { window: 0 } = request.smoothing {
+        return Err(Status::BadRequest.into());
+    }
+
+    let polar = load(polar_service, &polar_id).await?;
+
+    let mut smoothed = polar.clone();
+    for sail in smoothed.sail.iter_mut() {
+        if request.sails.is_empty() || request.sails.contains(&sail.id) {
+            sail.speed = matrix::smooth(&sail.speed, &request.smoothing, request.axis);
+            if let Some(port_speed) = &sail.port_speed {
+                sail.port_speed = Some(matrix::smooth(port_speed, &request.smoothing, request.axis));
+            }
+        }
+    }
+
+    let diff = polar.speed_diff(&smoothed);
+
+    let saved = save.unwrap_or(false);
+    if saved {
+        polar_service.update(polar_id, &smoothed).await.map_err(write_error)?;
+    }
+
+    Ok(Json(SmoothPreview { saved, diff }))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Transformed {
+    /// revision keeping the previous file, `None` when the speeds are unchanged
+    revision: Option<u32>,
+    #[serde(flatten)]
+    diff: SpeedDiff,
+}
+
+/// Applies the operations and saves the result, the previous file being kept as a revision.
+#[post("/polars/<polar_id>/transform", data = "<operations>")]
+async fn transform(polar_service: &State<Arc<PolarService>>, polar_id: String, operations: Json<Vec<Transform>>) -> Result<Json<Transformed>, Rejected> {
+
+    let polar = load(polar_service, &polar_id).await?;
+
+    let mut transformed = polar.clone();
+    for operation in operations.iter() {
+        operation.apply(&mut transformed);
+    }
+
+    match polar_service.revise(polar_id, &transformed).await {
+        Ok(revision) => Ok(Json(Transformed { revision, diff: polar.speed_diff(&transformed) })),
+        Err(error) => Err(write_error(error)),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MergeRequest {
+    base: String,
+    other: String,
+    /// sails of `other` to add, all of them when empty
+    #[serde(default)]
+    sails: Vec<u8>,
+    id: Option<String>,
+    label: Option<String>,
+}
+
+/// Polar made of the base polar and sails of the other one, created with a free `_id` with `save`.
+#[post("/polars/merge?<save>", data = "<request>")]
+async fn merge(polar_service: &State<Arc<PolarService>>, save: Option<bool>, request: Json<MergeRequest>) -> Result<(Status, Json<Polar>), Rejected> {
+
+    let request = request.into_inner();
+    let base = load(polar_service, &request.base).await?;
+    let other = load(polar_service, &request.other).await?;
+
+    let mut merged = base.merge(&other, &request.sails).map_err(write_error)?;
+    merged.id = Some(request.id.unwrap_or_else(|| format!("{}-{}", request.base, request.other)));
+    if let Some(label) = request.label {
+        merged.label = label;
+    }
+
+    if let Some(true) = save {
+        match polar_service.create_new(&merged).await {
+            Ok(merged) => Ok((Status::Created, Json(merged))),
+            Err(error) => Err(write_error(error)),
+        }
+    } else {
+        Ok((Status::Ok, Json(merged)))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AppendSailsRequest {
+    /// polar the sails are taken from
+    from: String,
+    /// sails of `from` to append, all of them when empty
+    #[serde(default)]
+    sails: Vec<u8>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AppendedSails {
+    polar: Polar,
+    remapped: Vec<SailRemap>,
+}
+
+/// Appends the sails of another polar to the polar, e.g. a pack sails polar to the standard
+/// sails one, renumbering them and renaming those whose name is taken. Saved with `save`.
+#[post("/polars/<polar_id>/append-sails?<save>", data = "<request>")]
+async fn append_sails(polar_service: &State<Arc<PolarService>>, polar_id: String, save: Option<bool>, request: Json<AppendSailsRequest>) -> Result<Json<AppendedSails>, Rejected> {
+
+    let polar = load(polar_service, &polar_id).await?;
+    let from = load(polar_service, &request.from).await?;
+
+    let (polar, remapped) = polar.append_sails(&from, &request.sails).map_err(write_error)?;
+    if let Some(true) = save {
+        polar_service.update(polar_id, &polar).await.map_err(write_error)?;
+    }
+    Ok(Json(AppendedSails { polar, remapped }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RegridRequest {
+    ids: Vec<String>,
+    /// target axes, the union of the grids of the polars when absent
+    twa: Option<Vec<u8>>,
+    tws: Option<Vec<u8>>,
+}
+
+/// Polars interpolated onto identical axes, e.g. to compare them cell by cell.
+#[post("/polars/regrid?<precision>", data = "<request>")]
+async fn regrid(polar_service: &State<Arc<PolarService>>, precision: Option<u8>, request: Json<RegridRequest>) -> Result<Json<Vec<Polar>>, Status> {
+
+    let request = request.into_inner();
+    let increasing = |axis: &Vec<u8>| !axis.is_empty() && axis.windows(2).all(|w| w[0] < w[1]);
+    if request.ids.is_empty() || !request.twa.iter().chain(&request.tws).all(increasing) {
+        return Err(Status::BadRequest);
+    }
+
+    let mut polars = Vec::with_capacity(request.ids.len());
+    for id in &request.ids {
+        polars.push(load(polar_service, id).await?);
+    }
+    let polars: Vec<&Polar> = polars.iter().collect();
+    let (twa, tws) = nav_polars_core::polar::common_grid(&polars);
+    let twa = request.twa.unwrap_or(twa);
+    let tws = request.tws.unwrap_or(tws);
+
+    let mut regridded = nav_polars_core::polar::regrid_all(&polars, &twa, &tws);
+    if let Some(precision) = precision {
+        regridded.iter_mut().for_each(|polar| polar.round(precision));
+    }
+    Ok(Json(regridded))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlendRequest {
+    sources: Vec<BlendSource>,
+    id: Option<String>,
+    label: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlendSource {
+    id: String,
+    weight: f64,
+}
+
+/// Weighted average of two or more polars, e.g. a generic class polar from several certificates,
+/// created with a free `_id` with `save`.
+#[post("/polars/blend?<save>", data = "<request>")]
+async fn blend(polar_service: &State<Arc<PolarService>>, save: Option<bool>, request: Json<BlendRequest>) -> Result<(Status, Json<Polar>), Rejected> {
+
+    let request = request.into_inner();
+    let mut sources = Vec::with_capacity(request.sources.len());
+    for source in &request.sources {
+        sources.push((load(polar_service, &source.id).await?, source.weight));
+    }
+
+    let sources: Vec<(&Polar, f64)> = sources.iter().map(|(polar, weight)| (polar, *weight)).collect();
+    let mut blended = nav_polars_core::polar::blend(&sources).ok_or(Status::BadRequest)?;
+    blended.id = Some(request.id.unwrap_or_else(|| request.sources.iter().map(|s| s.id.as_str()).collect::<Vec<_>>().join("-")));
+    if let Some(label) = request.label {
+        blended.label = label;
+    }
+
+    if let Some(true) = save {
+        match polar_service.create_new(&blended).await {
+            Ok(blended) => Ok((Status::Created, Json(blended))),
+            Err(error) => Err(write_error(error)),
+        }
+    } else {
+        Ok((Status::Ok, Json(blended)))
+    }
+}
+
+#[get("/polars/<polar_id>/diff/<other_id>")]
+async fn diff(polar_service: &State<Arc<PolarService>>, polar_id: String, other_id: String) -> Result<Json<PolarDiff>, Status> {
+
+    let polar = load(polar_service, &polar_id).await?;
+    let other = load(polar_service, &other_id).await?;
+
+    match nav_polars_core::diff::diff(&polar, &other) {
+        Ok(diff) => Ok(Json(diff)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+/// With `step`, of at least 0.1 degree, the boats are compared every `step` degrees instead of
+/// on the angles of their grids.
+#[get("/polars/compare?<ids>&<tws>&<step>&<format..>")]
+async fn compare(polar_service: &State<Arc<PolarService>>, ids: String, tws: f64, step: Option<f64>, format: SpeedFormat) -> Result<Json<Comparison>, Status> {
+
+    let ids: Vec<&str> = ids.split(',').map(|id| id.trim()).filter(|id| !id.is_empty()).collect();
+    if ids.len() < 2 {
+        return Err(Status::BadRequest);
+    }
+
+    let mut polars = Vec::with_capacity(ids.len());
+    for id in ids {
+        polars.push(load(polar_service, id).await?);
+    }
+
+    let mut comparison = nav_polars_core::compare::compare(&polars, tws, step).ok_or(Status::BadRequest)?;
+    comparison.format(format);
+
+    Ok(Json(comparison))
+}
+
+/// Revisions kept of the polar by transform and repair, oldest first.
+#[get("/polars/<polar_id>/revisions")]
+async fn revisions(polar_service: &State<Arc<PolarService>>, polar_id: String) -> Result<Json<Vec<Revision>>, Status> {
+
+    load(polar_service, &polar_id).await?;
+    polar_service.revisions(&polar_id).await.map(Json).map_err(|_| Status::InternalServerError)
+}
+
+#[get("/polars/<polar_id>/revisions/<revision>")]
+async fn get_revision(polar_service: &State<Arc<PolarService>>, polar_id: String, revision: u32) -> Result<Json<Polar>, Status> {
+
+    match polar_service.get_revision(polar_id, revision).await {
+        Ok(Some(polar)) => Ok(Json(polar)),
+        Ok(None) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[get("/polars/stats")]
+async fn catalog_stats(polar_service: &State<Arc<PolarService>>) -> Result<Json<CatalogStats>, Status> {
+
+    let mut polars = polar_service.list(None).await.map_err(|_| Status::InternalServerError)?;
+    polars.extend(polar_service.list(Some(true)).await.map_err(|_| Status::InternalServerError)?);
+
+    Ok(Json(nav_polars_core::stats::catalog(&polars)))
+}
+
+#[get("/polars/<polar_id>/stats?<options..>")]
+async fn stats(polar_service: &State<Arc<PolarService>>, polar_id: String, mut options: Options) -> Result<Json<PolarStats>, Status> {
+
+    options.extrapolation.get_or_insert_with(|| polar_service.default_extrapolation());
+    let mut polar = load(polar_service, &polar_id).await?;
+    options.fit(&mut polar).ok_or(Status::BadRequest)?;
+
+    let mut stats = nav_polars_core::stats::polar_stats(&polar, options.extrapolation.unwrap_or_default(), options.method.unwrap_or_default());
+    stats.format(options.format());
+    stats.usage = Some(polar_service.usage().of(&polar_id));
+
+    Ok(Json(stats))
+}
+
+/// Min, max and mean table speeds of a sail per tws column and twa row, and its fastest cell.
+#[get("/polars/<polar_id>/sails/<sail_id>/stats?<format..>")]
+async fn sail_stats(polar_service: &State<Arc<PolarService>>, polar_id: String, sail_id: u8, format: SpeedFormat) -> Result<Json<SailStats>, Status> {
+
+    let polar = load(polar_service, &polar_id).await?;
+
+    let mut stats = nav_polars_core::stats::sail_stats(&polar, sail_id).ok_or(Status::NotFound)?;
+    stats.format(format);
+    Ok(Json(stats))
+}
+
+/// Twa ranges per sail where the polar reaches at least `speed` at `tws`, e.g. to know when the
+/// boat foils above 30 knots.
+#[get("/polars/<polar_id>/reach?<speed>&<tws>&<options..>")]
+async fn reach(polar_service: &State<Arc<PolarService>>, polar_id: String, speed: f64, tws: f64, mut options: Options) -> Result<Json<Reach>, Status> {
+
+    options.extrapolation.get_or_insert_with(|| polar_service.default_extrapolation());
+    let mut polar = load(polar_service, &polar_id).await?;
+    polar_service.usage().evaluated(&polar_id);
+    options.fit(&mut polar).ok_or(Status::BadRequest)?;
+
+    nav_polars_core::stats::reach(&polar, &options, speed, tws).map(Json).ok_or(Status::BadRequest)
+}
+
+#[get("/polars/<polar_id>/lint")]
+async fn lint(polar_service: &State<Arc<PolarService>>, polar_id: String) -> Result<Json<LintReport>, Status> {
+
+    let polar = load(polar_service, &polar_id).await?;
+
+    let mut report = nav_polars_core::lint::lint(&polar);
+    let unknown = polar_service.unknown_fields(&polar_id).await.map_err(|_| Status::InternalServerError)?;
+    report.findings.extend(unknown.unwrap_or_default().into_iter().map(Finding::unknown_field));
+
+    Ok(Json(report))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RepairRequest {
+    /// fixes to apply, all of them when empty
+    #[serde(default)]
+    fixes: Vec<Fix>,
+}
+
+/// Applies the fixes and saves the result, the previous file being kept as a revision.
+#[post("/polars/<polar_id>/repair", data = "<request>")]
+async fn repair(polar_service: &State<Arc<PolarService>>, polar_id: String, request: Json<RepairRequest>) -> Result<Json<Vec<AppliedFix>>, Rejected> {
+
+    let mut polar = load(polar_service, &polar_id).await?;
+
+    let applied = nav_polars_core::repair::repair(&mut polar, &request.fixes);
+    if applied.is_empty() {
+        return Ok(Json(applied));
+    }
+
+    match polar_service.revise(polar_id, &polar).await {
+        Ok(_) => Ok(Json(applied)),
+        Err(error) => Err(write_error(error)),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Speed {
+    units: Units,
+    twa: f64,
+    tws: f64,
+    sail: Option<u8>,
+    speed: f64,
+    foil_ratio: f64,
+    /// set sail outside of the bad sail tolerance
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    bad_sail: bool,
+}
+
+/// With `sail`, the speed is evaluated with that sail set instead of the fastest one.
+#[get("/polars/<polar_id>/speed?<twa>&<tws>&<sail>&<options..>")]
+async fn speed(polar_service: &State<Arc<PolarService>>, polar_id: String, twa: f64, tws: f64, sail: Option<u8>, options: Options) -> Result<Wire<Speed>, Status> {
+
+    let mut polar = load(polar_service, &polar_id).await?;
+    polar_service.usage().evaluated(&polar_id);
+    options.fit(&mut polar).ok_or(Status::BadRequest)?;
+
+    let angles = options.angles.unwrap_or_else(|| polar_service.default_angles());
+    let twa = AngleConvention::normalize(twa);
+    let extrapolation = options.extrapolation.unwrap_or_else(|| polar_service.default_extrapolation());
+    if extrapolation == Extrapolation::Error && !polar.contains(twa, tws) {
+        return Err(Status::BadRequest);
+    }
+
+    let method = options.method.unwrap_or_default();
+    let (best, bad_sail) = match sail {
+        Some(sail) => {
+            let (speed, bad_sail) = compute::set_sail(&polar, sail, twa, tws, extrapolation, method).ok_or(Status::BadRequest)?;
+            (Some((sail, speed)), bad_sail)
+        }
+        None if method == Interpolation::Bilinear => (polar_service.table(&polar).best_sail(twa, tws, extrapolation), false),
+        None => (polar.best_sail_interpolated(twa, tws, extrapolation, method), false),
+    };
+    let foil_ratio = compute::foil(&polar, options.foil, twa, tws);
+    Ok(Wire(Speed {
+        units: options.units,
+        twa: angles.present(twa),
+        tws,
+        sail: best.map(|(sail, _)| sail),
+        speed: options.format().apply(best.map_or(0.0, |(_, speed)| speed * polar.speed_ratio(options.in_ice) * foil_ratio)),
+        foil_ratio,
+        bad_sail,
+    }))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GroundSpeed {
+    units: Units,
+    twa: f64,
+    tws: f64,
+    heading: f64,
+    sail: Option<u8>,
+    /// speed through water
+    speed: f64,
+    current: Current,
+    /// speed over ground
+    sog: f64,
+    /// course over ground
+    cog: f64,
+}
+
+/// Speed over ground on `heading` with a current given as `current.set` and `current.drift`.
+#[get("/polars/<polar_id>/speed/ground?<twa>&<tws>&<heading>&<current>&<options..>")]
+async fn ground_speed(polar_service: &State<Arc<PolarService>>, polar_id: String, twa: f64, tws: f64, heading: f64, current: Current, options: Options) -> Result<Json<GroundSpeed>, Status> {
+
+    let mut polar = load(polar_service, &polar_id).await?;
+    polar_service.usage().evaluated(&polar_id);
+    options.fit(&mut polar).ok_or(Status::BadRequest)?;
+
+    let angles = options.angles.unwrap_or_else(|| polar_service.default_angles());
+    let twa = AngleConvention::normalize(twa);
+    let extrapolation = options.extrapolation.unwrap_or_else(|| polar_service.default_extrapolation());
+    if extrapolation == Extrapolation::Error && !polar.contains(twa, tws) {
+        return Err(Status::BadRequest);
+    }
+
+    let best = match options.method.unwrap_or_default() {
+        Interpolation::Bilinear => polar_service.table(&polar).best_sail(twa, tws, extrapolation),
+        method => polar.best_sail_interpolated(twa, tws, extrapolation, method),
+    };
+    let speed = best.map_or(0.0, |(_, speed)| speed * polar.speed_ratio(options.in_ice) * compute::foil(&polar, options.foil, twa, tws));
+    let (sog, cog) = compute::over_ground(heading, speed, current);
+
+    let format = options.format();
+    Ok(Json(GroundSpeed {
+        units: format.units,
+        twa: angles.present(twa),
+        tws,
+        heading,
+        sail: best.map(|(sail, _)| sail),
+        speed: format.apply(speed),
+        current: Current { drift: format.apply(current.drift), ..current },
+        sog: format.apply(sog),
+        cog,
+    }))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MappedSpeed {
+    units: Units,
+    twa: f64,
+    tws: f64,
+    sail: u8,
+    speed: f64,
+}
+
+/// Effective speed interpolated between the cells of the binary export of the polar, memory
+/// mapped. Not found when the service keeps no binary exports. The speed is evaluated on the
+/// polar itself when the options change its effective polar, and on port tack when it has port
+/// tack speeds, which the exports don't hold.
+#[get("/polars/<polar_id>/speed/mapped?<twa>&<tws>&<options..>")]
+async fn mapped_speed(polar_service: &State<Arc<PolarService>>, polar_id: String, twa: f64, tws: f64, mut options: Options) -> Result<Json<MappedSpeed>, Status> {
+
+    options.extrapolation.get_or_insert_with(|| polar_service.default_extrapolation());
+    let mapped = match polar_service.mapped(polar_id.clone()).await {
+        Ok(Some(mapped)) => mapped,
+        Ok(None) => return Err(Status::NotFound),
+        Err(_) => return Err(Status::InternalServerError),
+    };
+    polar_service.usage().evaluated(&polar_id);
+
+    let angles = options.angles.unwrap_or_else(|| polar_service.default_angles());
+    let twa = AngleConvention::normalize(twa);
+    let (sail, speed) = if options.is_default_polar() && !(twa < 0.0 && mapped.has_port_speeds()) {
+        mapped.speed(twa, tws, options.extrapolation.unwrap_or_default()).ok_or(Status::BadRequest)?
+    } else {
+        let mut polar = load(polar_service, &polar_id).await?;
+        options.fit(&mut polar).ok_or(Status::BadRequest)?;
+        let table = polar_service.table(&polar);
+        let speeds = compute::effective_speeds(&polar, &table, &options, &[(twa, tws)]).ok_or(Status::BadRequest)?;
+        speeds.first().copied().unwrap_or_default()
+    };
+    let format = options.format();
+    Ok(Json(MappedSpeed { units: format.units, twa: angles.present(twa), tws, sail, speed: format.apply(speed) }))
+}
+
+/// Boat speed and fastest sail on every cell of the polar grid.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Grid {
+    units: Units,
+    twa: Vec<i16>,
+    tws: Vec<u8>,
+    speed: Matrix,
+    sail: Vec<Vec<u8>>,
+}
+
+/// With `signed`, port tack rows (negative twa, above 180 with circular angles) are added before
+/// the starboard ones.
+#[get("/polars/<polar_id>/grid?<signed>&<options..>")]
+async fn grid(polar_service: &State<Arc<PolarService>>, polar_id: String, signed: Option<bool>, mut options: Options) -> Result<Json<Grid>, Status> {
+
+    options.extrapolation.get_or_insert_with(|| polar_service.default_extrapolation());
+    let mut polar = load(polar_service, &polar_id).await?;
+    options.fit(&mut polar).ok_or(Status::BadRequest)?;
+    let format = options.format();
+
+    let mut angles: Vec<i16> = polar.twa.iter().map(|twa| *twa as i16).collect();
+    if let Some(true) = signed {
+        let port = angles.iter().rev().filter(|twa| **twa > 0).map(|twa| -twa);
+        angles = port.chain(angles.iter().copied()).collect();
+    }
+
+    let points: Vec<(f64, f64)> = angles.iter()
+        .flat_map(|twa| polar.tws.iter().map(move |tws| (*twa as f64, *tws as f64)))
+        .collect();
+    let table = polar_service.table(&polar);
+    let cells = compute::effective_speeds(&polar, &table, &options, &points).ok_or(Status::BadRequest)?;
+
+    let mut speed = Vec::with_capacity(angles.len());
+    let mut sail = Vec::with_capacity(angles.len());
+    for row in cells.chunks(polar.tws.len().max(1)) {
+        let (speeds, sails) = row.iter().map(|(sail, speed)| (format.apply(*speed), *sail)).unzip();
+        speed.push(speeds);
+        sail.push(sails);
+    }
+
+    let convention = options.angles.unwrap_or_else(|| polar_service.default_angles());
+    let twa = angles.iter().map(|twa| convention.present(*twa as f64) as i16).collect();
+    Ok(Json(Grid { units: format.units, twa, tws: polar.tws, speed, sail }))
+}
+
+/// Effective polar, i.e. with the evaluation options applied, in `format`.
+/// `twa_step` and `tws_step` set the resampling of the lite format.
+#[get("/polars/<polar_id>/export?<format>&<twa_step>&<tws_step>&<options..>")]
+async fn export(polar_service: &State<Arc<PolarService>>, polar_id: String, format: ExportFormat, twa_step: Option<u8>, tws_step: Option<u8>, mut options: Options) -> Result<(ContentType, Vec<u8>), Status> {
+
+    options.extrapolation.get_or_insert_with(|| polar_service.default_extrapolation());
+    // the formats have their own angle conventions
+    if options.angles.is_some() {
+        return Err(Status::BadRequest);
+    }
+    let mut polar = load(polar_service, &polar_id).await?;
+    options.fit(&mut polar).ok_or(Status::BadRequest)?;
+
+    let defaults = Steps::default();
+    let steps = Steps { twa: twa_step.unwrap_or(defaults.twa), tws: tws_step.unwrap_or(defaults.tws) };
+    let table = polar_service.table(&polar);
+    let content_type = match format {
+        ExportFormat::Bin => ContentType::Binary,
+        ExportFormat::Lite | ExportFormat::CrossoverJson => ContentType::JSON,
+        ExportFormat::Adrena | ExportFormat::TimeZero => ContentType::Plain,
+        ExportFormat::Targets | ExportFormat::BandG | ExportFormat::Crossover => ContentType::CSV,
+    };
+    let bytes = nav_polars_core::export::export(&polar, &table, &options, format, steps).ok_or(Status::BadRequest)?;
+    Ok((content_type, bytes))
+}
+
+/// Speed surface of `sail`, of the fastest sail when not given, every `twa_step` degrees and
+/// `tws_step` knots (1 by default) for 3D plots.
+#[get("/polars/<polar_id>/surface?<sail>&<twa_step>&<tws_step>&<options..>")]
+async fn surface(polar_service: &State<Arc<PolarService>>, polar_id: String, sail: Option<u8>, twa_step: Option<u8>, tws_step: Option<u8>, mut options: Options) -> Result<Json<Surface>, Status> {
+
+    options.extrapolation.get_or_insert_with(|| polar_service.default_extrapolation());
+    let mut polar = load(polar_service, &polar_id).await?;
+    options.fit(&mut polar).ok_or(Status::BadRequest)?;
+
+    let steps = Steps { twa: twa_step.unwrap_or(1), tws: tws_step.unwrap_or(1) };
+    let table = polar_service.table(&polar);
+    let surface = nav_polars_core::export::surface(&polar, &table, &options, sail, steps).ok_or(Status::BadRequest)?;
+    Ok(Json(surface))
+}
+
+/// Sweeps from `twa_from` to `twa_to` (the grid bounds by default) every `step` degrees, at
+/// least 0.1 degree and at most 3601 angles.
+/// `tolerance` overrides the polar `autoSailChangeTolerance`. The twa are given in either
+/// convention and returned in the `angles` one. Speeds are in the ice with `in_ice`.
+#[allow(clippy::too_many_arguments)]
+#[get("/polars/<polar_id>/auto-sail?<tws>&<twa_from>&<twa_to>&<step>&<tolerance>&<angles>&<in_ice>")]
+async fn auto_sail(polar_service: &State<Arc<PolarService>>, polar_id: String, tws: f64, twa_from: Option<f64>, twa_to: Option<f64>, step: Option<f64>, tolerance: Option<f64>, angles: Option<AngleConvention>, in_ice: Option<bool>) -> Result<Json<AutoSail>, Status> {
+
+    let polar = load(polar_service, &polar_id).await?;
+    polar_service.usage().evaluated(&polar_id);
+
+    let from = twa_from.map(AngleConvention::normalize).or_else(|| polar.twa.first().map(|twa| *twa as f64)).unwrap_or_default();
+    let to = twa_to.map(AngleConvention::normalize).or_else(|| polar.twa.last().map(|twa| *twa as f64)).unwrap_or_default();
+    let sweep = compute::sweep(from, to, step.unwrap_or(1.0).abs()).ok_or(Status::BadRequest)?;
+
+    let mut auto_sail = compute::auto_sail(&polar, tws, &sweep, tolerance.unwrap_or(polar.auto_sail_change_tolerance), in_ice.unwrap_or(false));
+    let angles = angles.unwrap_or_else(|| polar_service.default_angles());
+    auto_sail.steps.iter_mut().for_each(|step| step.twa = angles.present(step.twa));
+    auto_sail.changes.iter_mut().for_each(|change| change.twa = angles.present(change.twa));
+    Ok(Json(auto_sail))
+}
+
+/// With `tws`, timers and ratios are interpolated between the low and high wind boundaries.
+#[get("/polars/<polar_id>/penalties?<winch>&<tws>")]
+async fn penalties(polar_service: &State<Arc<PolarService>>, polar_id: String, winch: Option<WinchKind>, tws: Option<f64>) -> Result<Json<Penalties>, Status> {
+
+    let polar = load(polar_service, &polar_id).await?;
+
+    let polar_winch = polar.winch.as_ref().ok_or(Status::UnprocessableEntity)?;
+    Ok(Json(penalty::penalties(polar_winch, winch.unwrap_or_default(), tws)))
+}
+
+/// `kind` is deduced from the angles when not given. The twa are given in either convention
+/// and returned in the `angles` one.
+#[allow(clippy::too_many_arguments)]
+#[get("/polars/<polar_id>/maneuver?<kind>&<tws>&<twa_from>&<twa_to>&<winch>&<angles>")]
+async fn maneuver(polar_service: &State<Arc<PolarService>>, polar_id: String, kind: Option<Maneuver>, tws: f64, twa_from: f64, twa_to: f64, winch: Option<WinchKind>, angles: Option<AngleConvention>) -> Result<Json<Timeline>, Status> {
+
+    let polar = load(polar_service, &polar_id).await?;
+
+    let (twa_from, twa_to) = (AngleConvention::normalize(twa_from), AngleConvention::normalize(twa_to));
+    let kind = kind.unwrap_or_else(|| Maneuver::between(twa_from, twa_to));
+    let mut timeline = penalty::timeline(&polar, kind, winch.unwrap_or_default(), tws, twa_from, twa_to).ok_or(Status::UnprocessableEntity)?;
+    let angles = angles.unwrap_or_else(|| polar_service.default_angles());
+    timeline.twa_from = angles.present(timeline.twa_from);
+    timeline.twa_to = angles.present(timeline.twa_to);
+    Ok(Json(timeline))
+}
+
+#[get("/polars/<polar_id>/maneuvers?<winch>")]
+async fn maneuvers(polar_service: &State<Arc<PolarService>>, polar_id: String, winch: Option<WinchKind>) -> Result<Json<ManeuverTable>, Status> {
+
+    let polar = load(polar_service, &polar_id).await?;
+
+    Ok(Json(penalty::maneuver_table(&polar, winch.unwrap_or_default())))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EtaRequest {
+    legs: Vec<Leg>,
+    /// winch pack used to account for the maneuvers between legs, none when absent
+    winch: Option<WinchKind>,
+}
+
+/// Time to sail the legs, whose twa are given in either convention and returned in the `angles` one.
+#[post("/polars/<polar_id>/eta?<angles>", data = "<request>")]
+async fn eta(polar_service: &State<Arc<PolarService>>, polar_id: String, angles: Option<AngleConvention>, request: Json<EtaRequest>) -> Result<Json<Eta>, Status> {
+
+    let polar = load(polar_service, &polar_id).await?;
+    polar_service.usage().evaluated(&polar_id);
+
+    let mut eta = nav_polars_core::eta::eta(&polar, &request.legs, request.winch);
+    let angles = angles.unwrap_or_else(|| polar_service.default_angles());
+    eta.legs.iter_mut().for_each(|leg| leg.twa = angles.present(leg.twa));
+    Ok(Json(eta))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScenarioRequest {
+    /// polars to evaluate
+    ids: Vec<String>,
+    #[serde(flatten)]
+    scenario: Scenario,
+}
+
+/// Total times of several polars over a route of legs or a wind histogram, fastest first : which
+/// boat or tuning suits a race profile best.
+#[post("/polars/evaluate-scenario", data = "<request>")]
+async fn evaluate_scenario(polar_service: &State<Arc<PolarService>>, request: Json<ScenarioRequest>) -> Result<Json<Vec<ScenarioResult>>, Status> {
+
+    let request = request.into_inner();
+    if request.ids.is_empty() || (request.scenario.legs.is_empty() && request.scenario.histogram.is_empty()) {
+        return Err(Status::BadRequest);
+    }
+
+    let mut polars = Vec::with_capacity(request.ids.len());
+    for id in &request.ids {
+        polars.push(load(polar_service, id).await?);
+        polar_service.usage().evaluated(id);
+    }
+
+    Ok(Json(nav_polars_core::eta::evaluate(&polars, &request.scenario)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SensitivityRequest {
+    perturbations: Vec<Perturbation>,
+    /// legs whose time is compared, none when absent
+    #[serde(default)]
+    legs: Vec<Leg>,
+}
+
+/// Changes of the best vmg per tws and of the time to sail the legs when each of the
+/// perturbations is applied to the polar, to know which fields matter for routing.
+#[post("/polars/<polar_id>/sensitivity", data = "<request>")]
+async fn sensitivity(polar_service: &State<Arc<PolarService>>, polar_id: String, request: Json<SensitivityRequest>) -> Result<Json<Vec<Sensitivity>>, Status> {
+
+    let polar = load(polar_service, &polar_id).await?;
+    polar_service.usage().evaluated(&polar_id);
+
+    Ok(Json(nav_polars_core::sensitivity::sensitivity(&polar, &request.perturbations, &request.legs)))
+}
+
+#[get("/sails")]
+async fn list_sails(polar_service: &State<Arc<PolarService>>) -> Result<Json<Vec<SailDefinition>>, Status> {
+
+    match polar_service.sails().list() {
+        Ok(sails) => Ok(Json(sails)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+#[get("/sails/<sail_id>")]
+async fn get_sail(polar_service: &State<Arc<PolarService>>, sail_id: String) -> Result<Json<SailDefinition>, Status> {
+
+    match polar_service.sails().get(&sail_id) {
+        Ok(None) => Err(Status::NotFound),
+        Ok(Some(sail)) => Ok(Json(sail)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+#[post("/sails", data = "<sail>")]
+async fn post_sail(polar_service: &State<Arc<PolarService>>, sail: Json<SailDefinition>) -> Status {
+
+    match polar_service.sails().create(&sail.into_inner()) {
+        Ok(_) => Status::Created,
+        Err(error) => {
+            match error.downcast_ref::<PolarError>() {
+                Some(PolarError::AlreadyExists(_)) => Status::Conflict,
+                _ => Status::InternalServerError,
+            }
+        }
+    }
+}
+
+#[put("/sails/<sail_id>", data = "<sail>")]
+async fn put_sail(polar_service: &State<Arc<PolarService>>, sail_id: String, sail: Json<SailDefinition>) -> Status {
+
+    match polar_service.sails().update(sail_id, &sail.into_inner()) {
+        Ok(_) => Status::NoContent,
+        Err(error) => {
+            match error.downcast_ref::<PolarError>() {
+                Some(PolarError::NotFound(_)) => Status::NotFound,
+                _ => Status::InternalServerError,
+            }
+        }
+    }
+}
+
+#[delete("/sails/<sail_id>")]
+async fn delete_sail(polar_service: &State<Arc<PolarService>>, sail_id: String) -> Status {
+
+    match polar_service.delete_sail(sail_id).await {
+        Ok(_) => Status::NoContent,
+        Err(error) => {
+            match error.downcast_ref::<PolarError>() {
+                Some(PolarError::NotFound(_)) => Status::NotFound,
+                Some(PolarError::InUse(_)) => Status::Conflict,
+                _ => Status::InternalServerError,
+            }
+        }
+    }
+}
+
+#[get("/winches")]
+async fn list_winches(polar_service: &State<Arc<PolarService>>) -> Result<Json<Vec<WinchProfile>>, Status> {
+
+    match polar_service.winches().list() {
+        Ok(winches) => Ok(Json(winches)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+#[get("/winches/<name>")]
+async fn get_winch(polar_service: &State<Arc<PolarService>>, name: String) -> Result<Json<WinchProfile>, Status> {
+
+    match polar_service.winches().get(&name) {
+        Ok(None) => Err(Status::NotFound),
+        Ok(Some(winch)) => Ok(Json(winch)),
+        Err(_) => Err(Status::InternalServerError)
+    }
+}
+
+#[post("/winches", data = "<winch>")]
+async fn post_winch(polar_service: &State<Arc<PolarService>>, winch: Json<WinchProfile>) -> Status {
+
+    match polar_service.winches().create(&winch.into_inner()) {
+        Ok(_) => Status::Created,
+        Err(error) => {
+            match error.downcast_ref::<PolarError>() {
+                Some(PolarError::AlreadyExists(_)) => Status::Conflict,
+                _ => Status::InternalServerError,
+            }
+        }
+    }
+}
+
+#[put("/winches/<name>", data = "<winch>")]
+async fn put_winch(polar_service: &State<Arc<PolarService>>, name: String, winch: Json<WinchProfile>) -> Status {
+
+    match polar_service.winches().update(name, &winch.into_inner()) {
+        Ok(_) => Status::NoContent,
+        Err(error) => {
+            match error.downcast_ref::<PolarError>() {
+                Some(PolarError::NotFound(_)) => Status::NotFound,
+                _ => Status::InternalServerError,
+            }
+        }
+    }
+}
+
+#[delete("/winches/<name>")]
+async fn delete_winch(polar_service: &State<Arc<PolarService>>, name: String) -> Status {
+
+    match polar_service.delete_winch(name).await {
+        Ok(_) => Status::NoContent,
+        Err(error) => {
+            match error.downcast_ref::<PolarError>() {
+                Some(PolarError::NotFound(_)) => Status::NotFound,
+                Some(PolarError::InUse(_)) => Status::Conflict,
+                _ => Status::InternalServerError,
+            }
+        }
+    }
+}
+
+/// Base of the v1 routes.
+pub(crate) const BASE: &str = "/polars/api/v1";
+
+/// Flags the v1 responses as deprecated with the `Deprecation` and `Sunset` headers when their
+/// dates, in seconds since epoch, are configured, and counts the requests of each v1 route.
+pub(crate) struct Deprecation {
+    pub(crate) deprecated_at: Option<u64>,
+    pub(crate) sunset_at: Option<u64>,
+}
+
+#[rocket::async_trait]
+impl Fairing for Deprecation {
+    fn info(&self) -> Info {
+        Info { name: "v1 deprecation", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if !request.uri().path().as_str().starts_with(BASE) {
+            return;
+        }
+        if let Some(deprecated_at) = self.deprecated_at {
+            response.set_header(Header::new("Deprecation", format!("@{}", deprecated_at)));
+        }
+        if let Some(sunset_at) = self.sunset_at {
+            response.set_header(Header::new("Sunset", http_date(sunset_at)));
+        }
+        let name = request.route().and_then(|route| route.name.as_deref());
+        if let (Some(name), Some(polar_service)) = (name, request.rocket().state::<Arc<PolarService>>()) {
+            polar_service.usage().deprecated_requested(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_the_items() {
+        let page = Page::of(Some(2), Some(3), 7);
+        assert_eq!((page.number, page.size, page.count), (2, 3, 3));
+        assert_eq!(page.items((1..=7).collect()), vec![4, 5, 6]);
+        assert_eq!(Page::of(Some(3), Some(3), 7).items((1..=7).collect()), vec![7]);
+        assert!(Page::of(Some(4), Some(3), 7).items((1..=7).collect::<Vec<_>>()).is_empty());
+    }
+
+    #[test]
+    fn a_single_page_by_default() {
+        let page = Page::of(None, None, 7);
+        assert_eq!((page.number, page.size, page.count), (1, 7, 1));
+        assert_eq!(page.items((1..=7).collect()).len(), 7);
+
+        let empty = Page::of(None, None, 0);
+        assert_eq!((empty.size, empty.count), (1, 0));
+    }
+
+    #[test]
+    fn does_not_overflow_past_the_last_page() {
+        assert!(Page::of(Some(usize::MAX), Some(usize::MAX), 7).items((1..=7).collect::<Vec<_>>()).is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+use std::sync::Arc;
+
+use rocket::{catch, catchers, delete, get, post, put, Catcher, Request, Route, routes, State};
+use rocket::form::FromFormField;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use serde::Serialize;
+use serde_json::Value;
+
+use nav_polars_core::polar::{DefaultScope, FileMeta, Polar, PolarError, Sail, Winch};
+use nav_polars_core::service::{ImportOutcome, ImportResult, OnConflict, PolarService, Selection, SortKey};
+use nav_polars_core::validate::ValidationError;
+
+use crate::api::wire::Wire;
+
+/// Base of the v2 routes.
+pub(crate) const BASE: &str = "/polars/api/v2";
+
+pub(crate) fn routes() -> Vec<Route> {
+    routes![list, get, find_by_polar_id, find_by_label, post, put, delete, archive, restore, list_sails, get_sail, get_winch]
+}
+
+pub(crate) fn catchers() -> Vec<Catcher> {
+    catchers![default_catcher]
+}
+
+/// Body of every v2 error response.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiError {
+    status: u16,
+    /// reason phrase of the status
+    error: String,
+    message: String,
+    /// offending fields of an invalid polar
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<ValidationError>,
+}
+
+type Failure = (Status, Json<ApiError>);
+
+fn failure(status: Status, message: impl Into<String>) -> Failure {
+    (status, Json(ApiError {
+        status: status.code,
+        error: status.reason().unwrap_or_default().to_string(),
+        message: message.into(),
+        errors: Vec::new(),
+    }))
+}
+
+fn not_found(polar_id: &str) -> Failure {
+    failure(Status::NotFound, PolarError::NotFound(polar_id.to_string()).to_string())
+}
+
+/// Error response of `error`, internal errors being logged rather than detailed.
+fn from_error(error: anyhow::Error) -> Failure {
+    let status = match error.downcast_ref::<PolarError>() {
+        Some(PolarError::NotFound(_)) => Status::NotFound,
+        Some(PolarError::AlreadyExists(_)) | Some(PolarError::PolarIdTaken(_)) | Some(PolarError::NoFreeId()) | Some(PolarError::InUse(_)) => Status::Conflict,
+        Some(PolarError::IdIsMandatory()) | Some(PolarError::InvalidParent(_)) | Some(PolarError::InvalidSignature(_)) | Some(PolarError::NoFreeSailId(_)) => Status::BadRequest,
+        Some(PolarError::Invalid(errors)) => {
+            // the message lists the offending fields too, e.g. the unknown ones in strict mode
+            let (status, Json(mut api_error)) = failure(Status::BadRequest, error.to_string());
+            api_error.errors = errors.clone();
+            return (status, Json(api_error));
+        }
+        None => {
+            println!("Error serving v2 request : {}", error);
+            return failure(Status::InternalServerError, "Internal error");
+        }
+    };
+    failure(status, error.to_string())
+}
+
+/// Errors raised before reaching a handler, e.g. unknown routes or malformed parameters.
+#[catch(default)]
+fn default_catcher(status: Status, _request: &Request) -> Json<ApiError> {
+    let (_, body) = failure(status, status.reason().unwrap_or("Error"));
+    body
+}
+
+#[derive(FromFormField, Clone, Copy, PartialEq, Eq)]
+enum View {
+    /// identity and classification only, no file content beyond them
+    Summary,
+    Full,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PolarSummary {
+    id: Option<String>,
+    polar_id: u8,
+    label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    class: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default: Option<DefaultScope>,
+    archived: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verified: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<FileMeta>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Representation {
+    Summary(PolarSummary),
+    Full(Box<Polar>),
+}
+
+impl Representation {
+    fn new(mut polar: Polar, view: View, precision: Option<u8>, lang: Option<&str>) -> Self {
+        if let Some(lang) = lang {
+            polar.localize(lang);
+        }
+        match view {
+            View::Summary => Representation::Summary(PolarSummary {
+                id: polar.id,
+                polar_id: polar.polar_id,
+                label: polar.label,
+                class: polar.class,
+                tags: polar.tags,
+                default: polar.default,
+                archived: polar.archived,
+                modified: polar.modified,
+                verified: polar.verified,
+                file: polar.file,
+            }),
+            View::Full => {
+                if let Some(precision) = precision {
+                    polar.round(precision);
+                }
+                Representation::Full(Box::new(polar))
+            }
+        }
+    }
+}
+
+/// Polars of the selection, as summaries unless `view` is `full`, sorted by the comma separated
+/// keys of `sort_by` as in v1, then by id.
+#[get("/polars?<view>&<sort_by>&<precision>&<lang>&<selection..>")]
+async fn list(polar_service: &State<Arc<PolarService>>, view: Option<View>, sort_by: Option<String>, precision: Option<u8>, lang: Option<String>, selection: Selection) -> Result<Wire<Vec<Representation>>, Failure> {
+
+    let view = view.unwrap_or(View::Summary);
+    let Some(keys) = SortKey::parse_list(sort_by.as_deref().unwrap_or_default(), false) else {
+        return Err(failure(Status::BadRequest, format!("Unknown sort key in {}", sort_by.unwrap_or_default())));
+    };
+    let mut polars = polar_service.select(&selection, view == View::Summary).await.map_err(from_error)?;
+    // sorted by the labels as returned
+    if let Some(lang) = &lang {
+        polars.iter_mut().for_each(|polar| polar.localize(lang));
+    }
+    nav_polars_core::service::sort(&mut polars, &keys);
+    Ok(Wire(polars.into_iter().map(|polar| Representation::new(polar, view, precision, None)).collect()))
+}
+
+/// Polar, in full unless `view` is `summary`.
+#[get("/polars/<polar_id>?<view>&<precision>&<lang>")]
+async fn get(polar_service: &State<Arc<PolarService>>, polar_id: String, view: Option<View>, precision: Option<u8>, lang: Option<String>) -> Result<Wire<Representation>, Failure> {
+
+    let polar = load(polar_service, &polar_id).await?;
+    polar_service.usage().fetched(&polar_id);
+    Ok(Wire(Representation::new(polar, view.unwrap_or(View::Full), precision, lang.as_deref())))
+}
+
+/// Ranked after `/polars/<polar_id>/sails` and `/polars/<polar_id>/winch`, which match the same paths.
+#[get("/polars/by-polar-id/<polar_id>?<view>&<precision>&<lang>", rank = 1)]
+async fn find_by_polar_id(polar_service: &State<Arc<PolarService>>, polar_id: u8, view: Option<View>, precision: Option<u8>, lang: Option<String>) -> Result<Wire<Representation>, Failure> {
+
+    match polar_service.find_by_polar_id(polar_id).await {
+        Ok(Some(polar)) => {
+            if let Some(id) = &polar.id {
+                polar_service.usage().fetched(id);
+            }
+            Ok(Wire(Representation::new(polar, view.unwrap_or(View::Full), precision, lang.as_deref())))
+        },
+        Ok(None) => Err(failure(Status::NotFound, format!("No polar has _id {}.", polar_id))),
+        Err(error) => Err(from_error(error)),
+    }
+}
+
+#[get("/polars/by-label?<label>&<view>&<precision>&<lang>")]
+async fn find_by_label(polar_service: &State<Arc<PolarService>>, label: String, view: Option<View>, precision: Option<u8>, lang: Option<String>) -> Result<Wire<Representation>, Failure> {
+
+    match polar_service.find_by_label(&label).await {
+        Ok(Some(polar)) => {
+            if let Some(id) = &polar.id {
+                polar_service.usage().fetched(id);
+            }
+            Ok(Wire(Representation::new(polar, view.unwrap_or(View::Full), precision, lang.as_deref())))
+        },
+        Ok(None) => Err(failure(Status::NotFound, format!("No polar is labelled {}.", label))),
+        Err(error) => Err(from_error(error)),
+    }
+}
+
+/// Creates a polar, resolving a conflict with an existing polar of the same id with `on_conflict`
+/// (`fail` by default). A reserved `_id` can only be used with the token of its `reservation`.
+#[post("/polars?<on_conflict>&<reservation>", data = "<polar>")]
+async fn post(polar_service: &State<Arc<PolarService>>, on_conflict: Option<OnConflict>, reservation: Option<String>, polar: Json<Value>) -> Result<(Status, Json<ImportResult>), Failure> {
+
+    let polar = polar_service.parse(polar.into_inner()).map_err(from_error)?;
+    let result = polar_service.import_reserved(&polar, on_conflict.unwrap_or_default(), reservation.as_deref()).await.map_err(from_error)?;
+    let status = match result.outcome {
+        ImportOutcome::Created | ImportOutcome::Renamed => Status::Created,
+        ImportOutcome::Skipped | ImportOutcome::Overwritten => Status::Ok,
+    };
+    Ok((status, Json(result)))
+}
+
+#[put("/polars/<polar_id>", data = "<polar>")]
+async fn put(polar_service: &State<Arc<PolarService>>, polar_id: String, polar: Json<Value>) -> Result<Status, Failure> {
+
+    let polar = polar_service.parse(polar.into_inner()).map_err(from_error)?;
+    polar_service.update(polar_id, &polar).await.map_err(from_error)?;
+    Ok(Status::NoContent)
+}
+
+#[delete("/polars/<polar_id>")]
+async fn delete(polar_service: &State<Arc<PolarService>>, polar_id: String) -> Result<Status, Failure> {
+
+    polar_service.delete(polar_id).await.map_err(from_error)?;
+    Ok(Status::NoContent)
+}
+
+#[post("/polars/<polar_id>/archive")]
+async fn archive(polar_service: &State<Arc<PolarService>>, polar_id: String) -> Result<Status, Failure> {
+
+    polar_service.archive(polar_id).await.map_err(from_error)?;
+    Ok(Status::NoContent)
+}
+
+#[post("/polars/<polar_id>/restore")]
+async fn restore(polar_service: &State<Arc<PolarService>>, polar_id: String) -> Result<Status, Failure> {
+
+    polar_service.restore(polar_id).await.map_err(from_error)?;
+    Ok(Status::NoContent)
+}
+
+/// Sails of the polar, named after the sail catalog.
+#[get("/polars/<polar_id>/sails?<precision>")]
+async fn list_sails(polar_service: &State<Arc<PolarService>>, polar_id: String, precision: Option<u8>) -> Result<Json<Vec<Sail>>, Failure> {
+
+    let mut polar = load(polar_service, &polar_id).await?;
+    if let Some(precision) = precision {
+        polar.round(precision);
+    }
+    Ok(Json(polar.sail))
+}
+
+#[get("/polars/<polar_id>/sails/<sail_id>?<precision>")]
+async fn get_sail(polar_service: &State<Arc<PolarService>>, polar_id: String, sail_id: u8, precision: Option<u8>) -> Result<Json<Sail>, Failure> {
+
+    let mut polar = load(polar_service, &polar_id).await?;
+    if let Some(precision) = precision {
+        polar.round(precision);
+    }
+    match polar.sail.into_iter().find(|sail| sail.id == sail_id) {
+        Some(sail) => Ok(Json(sail)),
+        None => Err(failure(Status::NotFound, format!("Polar {} has no sail {}.", polar_id, sail_id))),
+    }
+}
+
+/// Winch of the polar, inline or from its winch profile.
+#[get("/polars/<polar_id>/winch")]
+async fn get_winch(polar_service: &State<Arc<PolarService>>, polar_id: String) -> Result<Json<Winch>, Failure> {
+
+    let polar = load(polar_service, &polar_id).await?;
+    match polar.winch {
+        Some(winch) => Ok(Json(winch)),
+        None => Err(failure(Status::NotFound, format!("Polar {} has no winch.", polar_id))),
+    }
+}
+
+async fn load(polar_service: &PolarService, polar_id: &str) -> Result<Polar, Failure> {
+    match polar_service.get(polar_id.to_string()).await {
+        Ok(Some(polar)) => Ok(polar),
+        Ok(None) => Err(not_found(polar_id)),
+        Err(error) => Err(from_error(error)),
+    }
+}
@@ -0,0 +1,100 @@
+use std::io::Cursor;
+
+use rocket::{Data, Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Status};
+use rocket::http::uri::Origin;
+
+use crate::api::{v1, v2};
+
+/// Unversioned base, e.g. `/polars/api/polars/<id>` for `/polars/api/v2/polars/<id>`.
+const BASE: &str = "/polars/api";
+
+/// Routes the requests of the unversioned base to the API version asked for with the
+/// `X-Api-Version` header (`1`, `2`, `v1` or `v2`) or an `Accept` profile ending with
+/// `/v1` or `/v2`, v1 by default, for gateways that can't rewrite paths. An unknown version is
+/// rejected with 400 from the header and 406 from a profile, and the responses vary with both
+/// headers for caches.
+pub(crate) struct Negotiation;
+
+/// Outcome of the negotiation of a request of the unversioned base.
+enum Negotiated {
+    Skipped,
+    Routed,
+    Unknown(Status, String),
+}
+
+/// Base of the API version asked for by `request`, if any, or the status and message rejecting
+/// an unknown version.
+fn requested(request: &Request<'_>) -> Result<Option<&'static str>, (Status, String)> {
+    let base = |version: &str| match version.trim().trim_start_matches('v') {
+        "1" => Some(v1::BASE),
+        "2" => Some(v2::BASE),
+        _ => None,
+    };
+
+    if let Some(version) = request.headers().get_one("X-Api-Version") {
+        return base(version).map(Some).ok_or_else(|| (Status::BadRequest, format!("Unknown API version {}.", version)));
+    }
+    let versions: Vec<&str> = request.headers().get("Accept")
+        .flat_map(|accept| accept.split([',', ';']))
+        .filter_map(|param| param.trim().strip_prefix("profile="))
+        .map(|profile| profile.trim_matches('"').rsplit('/').next().unwrap_or_default())
+        .filter(|version| version.strip_prefix('v').is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())))
+        .collect();
+    match versions.first() {
+        None => Ok(None),
+        Some(version) => versions.iter().find_map(|version| base(version)).map(Some)
+            .ok_or_else(|| (Status::NotAcceptable, format!("Unknown API version {}.", version))),
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Negotiation {
+    fn info(&self) -> Info {
+        Info { name: "API version negotiation", kind: Kind::Request | Kind::Response }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        let path = request.uri().path().as_str();
+        let Some(rest) = path.strip_prefix(BASE).filter(|rest| rest.starts_with('/')) else { return };
+        if [v1::BASE, v2::BASE].iter().any(|base| path.starts_with(&format!("{}/", base))) {
+            return;
+        }
+
+        let base = match requested(request) {
+            Ok(base) => base.unwrap_or(v1::BASE),
+            Err((status, message)) => {
+                // left unrouted, the response is replaced
+                request.local_cache(|| Negotiated::Unknown(status, message));
+                return;
+            }
+        };
+        let mut uri = format!("{}{}", base, rest);
+        if let Some(query) = request.uri().query() {
+            uri.push('?');
+            uri.push_str(query.as_str());
+        }
+        match Origin::parse_owned(uri) {
+            Ok(uri) => {
+                request.local_cache(|| Negotiated::Routed);
+                request.set_uri(uri);
+            }
+            Err(_) => println!("Couldn't route {} to an API version", path),
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        match request.local_cache(|| Negotiated::Skipped) {
+            Negotiated::Skipped => {}
+            Negotiated::Routed => {
+                response.set_header(Header::new("Vary", "X-Api-Version, Accept"));
+            }
+            Negotiated::Unknown(status, message) => {
+                response.set_status(*status);
+                response.set_header(Header::new("Vary", "X-Api-Version, Accept"));
+                response.set_sized_body(message.len(), Cursor::new(message.clone()));
+            }
+        }
+    }
+}
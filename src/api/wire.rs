@@ -0,0 +1,26 @@
+use std::io::Cursor;
+
+use rocket::{Request, Response};
+use rocket::http::{ContentType, Status};
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use serde::Serialize;
+
+/// Serializes a response as MessagePack when the client accepts `application/msgpack`,
+/// as JSON otherwise.
+pub(crate) struct Wire<T>(pub(crate) T);
+
+impl<'r, T: Serialize> Responder<'r, 'static> for Wire<T> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let msgpack = request.headers().get("Accept").any(|accept| accept.contains("application/msgpack"));
+        if !msgpack {
+            return Json(self.0).respond_to(request);
+        }
+
+        let bytes = rmp_serde::to_vec_named(&self.0).map_err(|_| Status::InternalServerError)?;
+        Response::build()
+            .header(ContentType::MsgPack)
+            .sized_body(bytes.len(), Cursor::new(bytes))
+            .ok()
+    }
+}
@@ -0,0 +1,280 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use structopt::StructOpt;
+
+use nav_polars_core::compute::Options;
+use nav_polars_core::export::{ExportFormat, Steps};
+use nav_polars_core::import::ImportFormat;
+use nav_polars_core::migrate;
+use nav_polars_core::polar::{Polar, PolarError, unknown_fields};
+use nav_polars_core::service::{ImportOutcome, ImportResult, OnConflict, PolarService, parents_first};
+use nav_polars_core::store::read_file;
+use serde_json::Value;
+
+/// Offline commands, working directly on the configured directories.
+#[derive(Debug, StructOpt)]
+pub(crate) enum Command {
+    /// Starts the web server, the default
+    Serve,
+    /// Lists the polars
+    List {
+        #[structopt(long)]
+        archived: bool,
+    },
+    /// Prints a polar as YAML
+    Get {
+        id: String,
+    },
+    /// Lints the polars, all of them when no id is given, and fails when something is found
+    Validate {
+        ids: Vec<String>,
+    },
+    /// Creates a polar from a YAML or JSON file, or from the table of another app with `--format`
+    Import {
+        file: PathBuf,
+        /// `sailgrib` or `weather4d` for their polar tables
+        #[structopt(long)]
+        format: Option<String>,
+        /// id of the polar, taken from the file or its label otherwise
+        #[structopt(long)]
+        id: Option<String>,
+        /// prints the polar that would be created and its lint findings, without writing it
+        #[structopt(long)]
+        dry_run: bool,
+        /// `skip`, `overwrite`, `rename` or `fail` when a polar of the same id exists
+        #[structopt(long, default_value = "fail")]
+        on_conflict: String,
+    },
+    /// Writes the effective polar in the `bin`, `lite`, `adrena`, `timezero` or `bandg` format, or its
+    /// `targets` or `crossover` (`crossover-json`) table
+    Export {
+        id: String,
+        #[structopt(long, default_value = "bin")]
+        format: String,
+        /// standard output when not given
+        #[structopt(long, short = "o")]
+        output: Option<PathBuf>,
+    },
+    /// Converts a directory of polar files of older layouts to the current schema
+    /// and creates them in the polars directory, reporting the outcome of each file
+    Migrate {
+        dir: PathBuf,
+        /// `skip`, `overwrite`, `rename` or `fail` when a polar of the same id exists
+        #[structopt(long, default_value = "fail")]
+        on_conflict: String,
+    },
+    /// Signs the polar file with an ed25519 key and attaches the signature, printed hex encoded
+    Sign {
+        id: String,
+        /// file of the hex encoded secret key
+        #[structopt(long)]
+        key: PathBuf,
+    },
+    /// Prints the differences between two polars as JSON
+    Diff {
+        id: String,
+        other_id: String,
+    },
+}
+
+async fn load(polar_service: &PolarService, polar_id: &str) -> Result<Polar> {
+    polar_service.get(polar_id.to_string()).await?
+        .ok_or_else(|| PolarError::NotFound(polar_id.to_string()).into())
+}
+
+pub(crate) async fn run(command: Command, polar_service: &PolarService) -> Result<()> {
+    match command {
+        Command::Serve => Ok(()),
+        Command::List { archived } => {
+            for polar in polar_service.summaries(Some(archived)).await? {
+                println!("{}\t{}\t{}", polar.id.unwrap_or_default(), polar.polar_id, polar.label);
+            }
+            Ok(())
+        }
+        Command::Get { id } => {
+            let polar = load(polar_service, &id).await?;
+            serde_yaml::to_writer(std::io::stdout(), &polar)?;
+            Ok(())
+        }
+        Command::Validate { ids } => {
+            let polars = if ids.is_empty() {
+                polar_service.list(None).await?
+            } else {
+                let mut polars = Vec::with_capacity(ids.len());
+                for id in &ids {
+                    polars.push(load(polar_service, id).await?);
+                }
+                polars
+            };
+
+            let mut count = 0;
+            for polar in &polars {
+                for finding in nav_polars_core::lint::lint(polar).findings {
+                    println!("{}\t{}", polar.id.as_deref().unwrap_or_default(), finding.message);
+                    count += 1;
+                }
+            }
+            if count > 0 {
+                return Err(anyhow!("{} findings in {} polars", count, polars.len()));
+            }
+            Ok(())
+        }
+        Command::Import { file, format, id, dry_run, on_conflict } => {
+            let on_conflict = parse_on_conflict(&on_conflict)?;
+            let imported = format.is_some();
+            let (mut polar, unknown) = match format {
+                Some(format) => {
+                    let format = ImportFormat::parse(&format).ok_or_else(|| anyhow!("unknown import format {}", format))?;
+                    let stem = file.file_stem().map(|stem| nav_polars_core::polar::slug(&stem.to_string_lossy())).unwrap_or_default();
+                    (nav_polars_core::import::import(&fs::read_to_string(&file)?, format, &stem)?, Vec::new())
+                }
+                None => {
+                    let mut value: Value = serde_yaml::from_reader(BufReader::new(File::open(&file)?))?;
+                    migrate::migrate(&mut value);
+                    let unknown = unknown_fields(&value);
+                    let polar = if dry_run { polar_service.parse_lenient(value)? } else { polar_service.parse(value)? };
+                    (polar, unknown)
+                }
+            };
+            if id.is_some() {
+                polar.id = id;
+            }
+            if polar.id.is_none() {
+                let slug = polar.label.rsplit('/').next().map(nav_polars_core::polar::slug).unwrap_or_default();
+                if !slug.is_empty() {
+                    polar.id = Some(polar_service.available_id(&slug));
+                }
+            }
+            if dry_run {
+                println!("{}", serde_json::to_string_pretty(&polar_service.preview(&polar, unknown, on_conflict)?)?);
+                return Ok(());
+            }
+            let result = if imported {
+                polar_service.import_new(&polar, on_conflict, None).await?
+            } else {
+                polar_service.import(&polar, on_conflict).await?
+            };
+            println!("{}\t{}", result.id, outcome_name(result.outcome));
+            Ok(())
+        }
+        Command::Export { id, format, output } => {
+            let format = match format.as_str() {
+                "bin" => ExportFormat::Bin,
+                "lite" => ExportFormat::Lite,
+                "adrena" => ExportFormat::Adrena,
+                "timezero" => ExportFormat::TimeZero,
+                "targets" => ExportFormat::Targets,
+                "bandg" => ExportFormat::BandG,
+                "crossover" => ExportFormat::Crossover,
+                "crossover-json" => ExportFormat::CrossoverJson,
+                _ => return Err(anyhow!("unknown export format {}", format)),
+            };
+            let polar = load(polar_service, &id).await?;
+            let table = polar_service.table(&polar);
+            let options = Options { extrapolation: Some(polar_service.default_extrapolation()), ..Options::default() };
+            let bytes = nav_polars_core::export::export(&polar, &table, &options, format, Steps::default())
+                .ok_or_else(|| anyhow!("polar {} can't be exported without extrapolation", id))?;
+            match output {
+                Some(output) => File::create(output)?.write_all(&bytes)?,
+                None => std::io::stdout().write_all(&bytes)?,
+            }
+            Ok(())
+        }
+        Command::Migrate { dir, on_conflict } => migrate(polar_service, &dir, parse_on_conflict(&on_conflict)?).await,
+        Command::Sign { id, key } => {
+            println!("{}", polar_service.sign(id, &fs::read_to_string(key)?).await?);
+            Ok(())
+        }
+        Command::Diff { id, other_id } => {
+            let polar = load(polar_service, &id).await?;
+            let other = load(polar_service, &other_id).await?;
+            println!("{}", serde_json::to_string_pretty(&nav_polars_core::diff::diff(&polar, &other)?)?);
+            Ok(())
+        }
+    }
+}
+
+/// Extensions of the files picked by `migrate`.
+const MIGRATE_EXTENSIONS: [&str; 4] = [".yaml", ".yml", ".json", ".yaml.gz"];
+
+fn parse_on_conflict(name: &str) -> Result<OnConflict> {
+    OnConflict::parse(name).ok_or_else(|| anyhow!("unknown conflict strategy {}", name))
+}
+
+fn outcome_name(outcome: ImportOutcome) -> &'static str {
+    match outcome {
+        ImportOutcome::Created => "created",
+        ImportOutcome::Skipped => "skipped",
+        ImportOutcome::Overwritten => "overwritten",
+        ImportOutcome::Renamed => "renamed",
+    }
+}
+
+async fn migrate(polar_service: &PolarService, dir: &Path, on_conflict: OnConflict) -> Result<()> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && MIGRATE_EXTENSIONS.iter().any(|ext| path.to_string_lossy().ends_with(ext)))
+        .collect();
+    files.sort();
+
+    let mut failed = 0;
+    let mut values = Vec::with_capacity(files.len());
+    for path in files {
+        match read_file(&path) {
+            Ok(value) if value.is_object() => values.push((path, value)),
+            Ok(_) => {
+                println!("{}\terror\tnot a polar", path.display());
+                failed += 1;
+            }
+            Err(e) => {
+                println!("{}\terror\t{}", path.display(), e);
+                failed += 1;
+            }
+        }
+    }
+    // parents are created before the polars extending them, ids default to the file names
+    let values = parents_first(values,
+        |(path, value)| value.get("id").and_then(Value::as_str).or_else(|| file_id(path)),
+        |(_, value)| value.get("extends").and_then(Value::as_str));
+
+    for (path, mut value) in values {
+        let version = migrate::version(&value);
+        migrate::migrate(&mut value);
+        let unknown = unknown_fields(&value);
+        match migrate_one(polar_service, &path, value, on_conflict).await {
+            Ok(result) => {
+                print!("{}\t{}\t{}, migrated from version {}", path.display(), result.id, outcome_name(result.outcome), version);
+                if !unknown.is_empty() {
+                    print!(", unknown fields dropped : {}", unknown.join(", "));
+                }
+                println!();
+            }
+            Err(e) => {
+                println!("{}\terror\t{}", path.display(), e);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow!("{} files could not be migrated", failed));
+    }
+    Ok(())
+}
+
+/// Creates the migrated polar file content `value` of `path`, the id defaulting to the file name.
+async fn migrate_one(polar_service: &PolarService, path: &Path, value: Value, on_conflict: OnConflict) -> Result<ImportResult> {
+    let mut polar: Polar = serde_json::from_value(polar_service.resolve(value)?)?;
+    if polar.id.is_none() {
+        polar.id = file_id(path).map(str::to_string);
+    }
+    polar_service.import(&polar, on_conflict).await
+}
+
+/// Id of the polar of the file at `path` without one : its name without extensions.
+fn file_id(path: &Path) -> Option<&str> {
+    path.file_name()?.to_str()?.split('.').next()
+}
@@ -1,8 +1,83 @@
 use serde::{Serialize, Deserialize};
 
+use nav_polars_core::compute::AngleConvention;
+use nav_polars_core::matrix::Extrapolation;
+use nav_polars_core::polar::MaxSpeedPolicy;
+
 #[derive(Default, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
     pub(crate) polars_dir: String,
-    pub(crate) archived_dir: String
+    pub(crate) archived_dir: String,
+    #[serde(default)]
+    pub(crate) max_speed_policy: MaxSpeedPolicy,
+    /// number of decimals of the speeds written on disk
+    #[serde(default)]
+    pub(crate) precision: Option<u8>,
+    #[serde(default)]
+    pub(crate) extrapolation: Extrapolation,
+    /// convention of the twa returned by the speed evaluations, signed by default
+    #[serde(default)]
+    pub(crate) angles: AngleConvention,
+    /// file of the shared sail catalog
+    #[serde(default)]
+    pub(crate) sails_file: Option<String>,
+    /// file of the shared winch profiles
+    #[serde(default)]
+    pub(crate) winches_file: Option<String>,
+    /// write polar files gzip compressed
+    #[serde(default)]
+    pub(crate) compress: bool,
+    /// gzip compress polar files when archiving them
+    #[serde(default)]
+    pub(crate) compress_archived: bool,
+    /// secondary directory of the archived polars
+    #[serde(default)]
+    pub(crate) cold_dir: Option<String>,
+    /// age in days of the archived polars moved to `cold_dir`
+    #[serde(default)]
+    pub(crate) cold_after_days: Option<u64>,
+    /// days without use after which active polars are reported, then archived, at most 90
+    #[serde(default)]
+    pub(crate) archive_inactive_days: Option<u64>,
+    /// days between the report of an inactive polar and its archiving, 7 by default
+    #[serde(default)]
+    pub(crate) archive_notice_days: Option<u64>,
+    /// file of the usage history, not persisted when missing
+    #[serde(default)]
+    pub(crate) usage_file: Option<String>,
+    /// file of the audit log, not kept when missing
+    #[serde(default)]
+    pub(crate) audit_file: Option<String>,
+    /// reject incoming polars with unknown fields
+    #[serde(default)]
+    pub(crate) strict: bool,
+    /// rewrite polar files of an older schema version when they are read
+    #[serde(default)]
+    pub(crate) rewrite_migrated: bool,
+    /// directory of the sail matrices shared between polar files, matrices are kept in the files when missing
+    #[serde(default)]
+    pub(crate) matrices_dir: Option<String>,
+    /// hex encoded ed25519 public keys of the signatures of official polars
+    #[serde(default)]
+    pub(crate) trusted_keys: Vec<String>,
+    /// seed the polars directory at startup when it has no active polar
+    #[serde(default)]
+    pub(crate) bootstrap: bool,
+    /// gzip compressed tar of polar files, as served by `/polars/export`, seeding the polars
+    /// directory on bootstrap instead of the bundled presets
+    #[serde(default)]
+    pub(crate) seed_url: Option<String>,
+    /// deprecation date of the v1 API sent in the `Deprecation` header, in seconds since epoch
+    #[serde(default)]
+    pub(crate) v1_deprecated_at: Option<u64>,
+    /// date after which the v1 API may be removed, sent in the `Sunset` header, in seconds since epoch
+    #[serde(default)]
+    pub(crate) v1_sunset_at: Option<u64>,
+    /// directory of the previous files of the transformed and repaired polars, `.revisions` in `polars_dir` when missing
+    #[serde(default)]
+    pub(crate) revisions_dir: Option<String>,
+    /// directory of the binary effective polars served memory mapped, not kept when missing
+    #[serde(default)]
+    pub(crate) binary_dir: Option<String>,
 }
@@ -1,23 +1,25 @@
-#![feature(path_file_prefix)]
+use std::sync::Arc;
 
-use rocket::launch;
+use nav_polars_core::service::PolarService;
 use structopt::StructOpt;
 
-use crate::polar::PolarService;
+use crate::cli::Command;
 
 mod api;
+mod cli;
 mod config;
-mod polar;
 
 #[derive(Debug, StructOpt)]
 struct Cli {
     /// config file
     #[structopt(long = "config-file", short = "c", default_value = "config.yaml")]
     config_file: String,
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
-#[launch]
-fn rocket() -> _ {
+#[rocket::main]
+async fn main() {
     std::env::var("RUST_LOG").map_err(|_| {
         std::env::set_var("RUST_LOG", "debug");
     }).unwrap_or_default();
@@ -27,7 +29,51 @@ fn rocket() -> _ {
 
     let config: config::Config = confy::load_path(std::path::Path::new(&args.config_file)).unwrap();
 
-    let polar_service = PolarService::new(config.polars_dir, config.archived_dir);
+    let polar_service = PolarService::new(config.polars_dir, config.archived_dir)
+        .max_speed_policy(config.max_speed_policy)
+        .precision(config.precision)
+        .extrapolation(config.extrapolation)
+        .angles(config.angles)
+        .sails_file(config.sails_file)
+        .winches_file(config.winches_file)
+        .compress(config.compress)
+        .compress_archived(config.compress_archived)
+        .cold_dir(config.cold_dir)
+        .cold_after_days(config.cold_after_days)
+        .archive_notice_days(config.archive_notice_days)
+        .usage_file(config.usage_file)
+        .audit_file(config.audit_file)
+        .strict(config.strict)
+        .rewrite_migrated(config.rewrite_migrated)
+        .matrices_dir(config.matrices_dir)
+        .revisions_dir(config.revisions_dir)
+        .binary_dir(config.binary_dir)
+        .archive_inactive_days(config.archive_inactive_days)
+        .and_then(|polar_service| polar_service.trusted_keys(&config.trusted_keys))
+        .unwrap_or_else(|e| {
+            eprintln!("Invalid configuration : {}", e);
+            std::process::exit(1);
+        });
 
-    api::init().manage(polar_service)
+    match args.command {
+        None | Some(Command::Serve) => {
+            if config.bootstrap {
+                api::admin::bootstrap(&polar_service, config.seed_url).await;
+            }
+            let polar_service = Arc::new(polar_service);
+            #[cfg(unix)]
+            rocket::tokio::spawn(api::admin::reload_on_hangup(polar_service.clone()));
+            rocket::tokio::spawn(api::admin::run_daily(polar_service.clone()));
+            if let Err(e) = api::init(api::v1::Deprecation { deprecated_at: config.v1_deprecated_at, sunset_at: config.v1_sunset_at }).manage(polar_service).launch().await {
+                eprintln!("Error launching the server : {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(command) => {
+            if let Err(e) = cli::run(command, &polar_service).await {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
 }